@@ -1,6 +1,7 @@
 use crate::metrics::{
     auto_flush_from, exponential_buckets, make_auto_flush_static_metric, register_counter_vec,
-    register_histogram_vec, register_int_gauge, CounterVec, HistogramVec, IntGauge,
+    register_histogram, register_histogram_vec, register_int_counter, register_int_gauge,
+    CounterVec, Histogram, HistogramVec, IntCounter, IntGauge,
 };
 
 use lazy_static::lazy_static;
@@ -74,6 +75,38 @@ lazy_static! {
         register_int_gauge!("axon_mempool_tx_count", "Tx len in mempool").unwrap();
     pub static ref MEMPOOL_CO_QUEUE_LEN: IntGauge =
         register_int_gauge!("axon_mempool_co_queue_count", "Tx len in mempool").unwrap();
+    pub static ref MEMPOOL_SENDER_QUEUE_DEPTH: IntGauge = register_int_gauge!(
+        "axon_mempool_max_sender_depth",
+        "The most txs pooled by a single sender"
+    )
+    .unwrap();
+    pub static ref MEMPOOL_RESIDENCE_EXPIRED_COUNTER: IntCounter = register_int_counter!(
+        "axon_mempool_residence_expired_count",
+        "Txs dropped for staying in mempool too long"
+    )
+    .unwrap();
+    pub static ref MEMPOOL_BROADCAST_FAILURE_COUNTER: IntCounter = register_int_counter!(
+        "axon_mempool_broadcast_failure_count",
+        "Local txs pooled but failed to broadcast"
+    )
+    .unwrap();
+    pub static ref MEMPOOL_SUBSCRIPTION_LAG_COUNTER: IntCounter = register_int_counter!(
+        "axon_mempool_subscription_lagged_count",
+        "Tx hashes missed by lagging subscribers"
+    )
+    .unwrap();
+    pub static ref MEMPOOL_TX_SIZE_HISTOGRAM: Histogram = register_histogram!(
+        "axon_mempool_tx_size_bytes",
+        "Encoded size of the txs accepted by mempool",
+        exponential_buckets(64.0, 2.0, 12).expect("mempool tx size exponential")
+    )
+    .unwrap();
+    pub static ref MEMPOOL_ACCEPTED_GAS_PRICE: Histogram = register_histogram!(
+        "axon_mempool_accepted_gas_price",
+        "Gas price of the txs accepted by mempool",
+        exponential_buckets(1.0, 4.0, 24).expect("mempool gas price exponential")
+    )
+    .unwrap();
 }
 
 lazy_static! {
@@ -5,7 +5,7 @@ use protocol::traits::StorageCategory;
 
 use crate::metrics::{
     auto_flush_from, duration_to_sec, make_auto_flush_static_metric, register_counter_vec,
-    CounterVec,
+    register_int_counter, CounterVec, IntCounter,
 };
 
 make_auto_flush_static_metric! {
@@ -62,6 +62,16 @@ lazy_static! {
         &["cf"]
     )
     .unwrap();
+    pub static ref STORAGE_STATE_CACHE_HIT_COUNTER: IntCounter = register_int_counter!(
+        "axon_storage_state_cache_hit_count",
+        "State reads served by the trie cache"
+    )
+    .unwrap();
+    pub static ref STORAGE_STATE_CACHE_MISS_COUNTER: IntCounter = register_int_counter!(
+        "axon_storage_state_cache_miss_count",
+        "State reads that fell through the trie cache to the db"
+    )
+    .unwrap();
 }
 
 lazy_static! {
@@ -82,6 +92,14 @@ pub fn on_storage_get_state(duration: Duration, keys: f64) {
     STORAGE_GET_CF_COUNTER.state.inc_by(keys);
 }
 
+pub fn on_storage_state_cache(hit: bool) {
+    if hit {
+        STORAGE_STATE_CACHE_HIT_COUNTER.inc();
+    } else {
+        STORAGE_STATE_CACHE_MISS_COUNTER.inc();
+    }
+}
+
 pub fn on_storage_put_state(duration: Duration, size: f64) {
     let seconds = duration_to_sec(duration);
 
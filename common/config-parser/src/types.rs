@@ -71,9 +71,30 @@ fn default_sync_txs_chunk_size() -> usize {
 #[derive(Clone, Debug, Deserialize)]
 pub struct ConfigConsensus {
     #[serde(default = "default_overlord_gap")]
-    pub overlord_gap:        usize,
+    pub overlord_gap:           usize,
     #[serde(default = "default_sync_txs_chunk_size")]
-    pub sync_txs_chunk_size: usize,
+    pub sync_txs_chunk_size:    usize,
+    #[serde(default)]
+    pub save_txs_chunk_size:    Option<usize>,
+    // Defaults to the number of cores
+    #[serde(default)]
+    pub sync_proof_concurrency: Option<usize>,
+    // Defaults to 1
+    #[serde(default)]
+    pub live_proof_concurrency: Option<usize>,
+    // Defaults to 64
+    #[serde(default)]
+    pub tx_read_concurrency:    Option<usize>,
+    // The [num, den] fraction of the vote weight a proof has to exceed,
+    // defaults to [2, 3]
+    #[serde(default)]
+    pub vote_threshold:         Option<(u64, u64)>,
+    // The seconds a sync rpc waits for the peer, defaults to 30
+    #[serde(default)]
+    pub rpc_timeout:            Option<u64>,
+    // Stop the node rather than fail the block when execution panics
+    #[serde(default)]
+    pub raise_exec_panic:       bool,
 }
 
 fn default_broadcast_txs_size() -> usize {
@@ -90,15 +111,68 @@ pub struct ConfigMempool {
     pub timeout_gap: u64,
 
     #[serde(default = "default_broadcast_txs_size")]
-    pub broadcast_txs_size:     usize,
+    pub broadcast_txs_size:       usize,
     #[serde(default = "default_broadcast_txs_interval")]
-    pub broadcast_txs_interval: u64,
+    pub broadcast_txs_interval:   u64,
+    // seconds
+    #[serde(default)]
+    pub max_residence:            Option<u64>,
+    #[serde(default)]
+    pub strict_broadcast:         bool,
+    // Broadcast local txs before the full check, see `PropagationMode`
+    #[serde(default)]
+    pub eager_propagation:        bool,
+    // Cache the committed tx checks until the state root changes
+    #[serde(default)]
+    pub verify_cache:             bool,
+    // Reject the txs calling an address unknown to the state
+    #[serde(default)]
+    pub recipient_check:          bool,
+    #[serde(default)]
+    pub max_concurrent_ensure:    Option<usize>,
+    #[serde(default)]
+    pub tx_size_metrics:          bool,
+    #[serde(default)]
+    pub eviction_archive_size:    Option<usize>,
+    #[serde(default)]
+    pub rejection_log_size:       Option<usize>,
+    // Fail `package` until the first state root is set
+    #[serde(default)]
+    pub reject_unprepared:        bool,
+    #[serde(default)]
+    pub storage_cache_size:       Option<usize>,
+    #[serde(default)]
+    pub max_missing_txs:          Option<usize>,
+    #[serde(default)]
+    pub min_gas_price:            u64,
+    // Raise the gas price floor to the cheapest pooled tx once the pool is
+    // filled to this percent
+    #[serde(default)]
+    pub pressure_threshold:       Option<usize>,
+    // Accept system script txs with a zero gas limit
+    #[serde(default)]
+    pub zero_gas_system_script:   bool,
+    // Compute tx sizes on demand rather than on insert, see `SizeTracking`
+    #[serde(default)]
+    pub lazy_size_tracking:       bool,
+    // Max storage reads in flight when fetching txs of unknown height
+    #[serde(default)]
+    pub storage_read_concurrency: Option<usize>,
+    #[serde(default)]
+    pub max_txs_per_sender:       Option<usize>,
+    #[serde(default)]
+    pub max_system_script_txs:    Option<usize>,
+    // Max txs of a batch verified at the same time
+    #[serde(default)]
+    pub verify_concurrency:       Option<usize>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct ConfigExecutor {
-    pub light:             bool,
-    pub triedb_cache_size: usize,
+    pub light:                bool,
+    pub triedb_cache_size:    usize,
+    #[serde(default)]
+    pub triedb_sync_interval: Option<u64>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -1,23 +1,31 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
+use futures::stream::{self, StreamExt, TryStreamExt};
+use lru::LruCache;
 use overlord::types::{Node, OverlordMsg, Vote, VoteType};
 use overlord::{extract_voters, Crypto, OverlordHandler};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
 use common_apm::Instant;
 use common_apm_derive::trace_span;
+use common_merkle::Merkle;
 use core_executor::{AxonExecutor, AxonExecutorAdapter};
 use core_network::{PeerId, PeerIdExt};
+use protocol::tokio::{sync::watch, task};
 use protocol::traits::{
     CommonConsensusAdapter, ConsensusAdapter, Context, CrossClient, Executor, Gossip, MemPool,
-    MessageTarget, MetadataControl, PeerTrust, Priority, Rpc, Storage, SynchronizationAdapter,
+    MessageCodec, MessageTarget, MetadataControl, Network, PeerTrust, Priority, Rpc, Storage,
+    SynchronizationAdapter,
 };
 use protocol::types::{
-    BatchSignedTxs, Block, BlockNumber, Bytes, ExecResp, Hash, Hasher, Header, Hex, Log,
-    MerkleRoot, Metadata, Proof, Proposal, Receipt, SignedTransaction, Validator, U256,
+    BatchSignedTxs, Block, BlockNumber, Bytes, ExecResp, Hash, Header, Hex, Log, MerkleRoot,
+    Metadata, Proof, Proposal, Receipt, SignedTransaction, Validator, H160, U256,
 };
-use protocol::{async_trait, codec::ProtocolCodec, tokio::task, ProtocolResult};
+use protocol::{async_trait, tokio, ProtocolResult};
 
 use crate::consensus::gen_overlord_status;
 use crate::message::{
@@ -29,9 +37,32 @@ use crate::BlockHeaderField::PreviousBlockHash;
 use crate::BlockProofField::{BitMap, HashMismatch, HeightMismatch, Signature, WeightNotFound};
 use crate::{BlockProofField, ConsensusError};
 
+// The number of recent block headers kept to verify the proposals on top of
+// them without reading storage.
+const HEADER_CACHE_SIZE: usize = 16;
+const DEFAULT_TX_READ_CONCURRENCY: usize = 64;
+// The signed vote weight of a proof has to be over 2/3 of the total
+const DEFAULT_VOTE_THRESHOLD: (u64, u64) = (2, 3);
+const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What `exec` does when the executor panics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecPanicPolicy {
+    /// Fail the execution with `ConsensusError::ExecutionPanic`
+    Catch,
+    /// Let the panic unwind, for deployments which rather stop than go on
+    Raise,
+}
+
+impl Default for ExecPanicPolicy {
+    fn default() -> Self {
+        ExecPanicPolicy::Catch
+    }
+}
+
 pub struct OverlordConsensusAdapter<
     M: MemPool,
-    N: Rpc + PeerTrust + Gossip + 'static,
+    N: Rpc + PeerTrust + Gossip + Network + 'static,
     S: Storage,
     CS: CrossClient,
     MT: MetadataControl,
@@ -44,15 +75,33 @@ pub struct OverlordConsensusAdapter<
     trie_db:          Arc<DB>,
     cross_client:     Arc<CS>,
     metadata:         Arc<MT>,
+    // The latest updated metadata, the receiver keeps the channel open
+    metadata_tx:      watch::Sender<Metadata>,
+    metadata_rx:      watch::Receiver<Metadata>,
     overlord_handler: RwLock<Option<OverlordHandler<Proposal>>>,
     crypto:           Arc<OverlordCrypto>,
+
+    save_txs_chunk_size:    Option<usize>,
+    // The max number of proofs `verify_proofs` checks at the same time while
+    // syncing and while following the chain
+    sync_proof_concurrency: usize,
+    live_proof_concurrency: usize,
+    // The max number of txs `get_txs_from_storage` reads at the same time
+    tx_read_concurrency:    usize,
+    vote_threshold:         (u64, u64),
+    // The longest a sync rpc waits for the peer
+    rpc_timeout:            Duration,
+    sync_mode:              AtomicBool,
+    exec_panic_policy:      ExecPanicPolicy,
+    // Committed headers never change, so they just age out
+    header_cache:           Mutex<LruCache<u64, Header>>,
 }
 
 #[async_trait]
 impl<M, N, S, CS, MT, DB> ConsensusAdapter for OverlordConsensusAdapter<M, N, S, CS, MT, DB>
 where
     M: MemPool + 'static,
-    N: Rpc + PeerTrust + Gossip + 'static,
+    N: Rpc + PeerTrust + Gossip + Network + 'static,
     S: Storage + 'static,
     CS: CrossClient + 'static,
     MT: MetadataControl + 'static,
@@ -113,11 +162,8 @@ where
     #[trace_span(kind = "consensus.adapter")]
     async fn pull_block(&self, ctx: Context, number: u64, end: &str) -> ProtocolResult<Block> {
         log::debug!("consensus: send rpc pull block {}", number);
-        let res = self
-            .network
-            .call::<BlockNumber, Block>(ctx, end, number, Priority::High)
-            .await?;
-        Ok(res)
+        self.call_with_timeout::<BlockNumber, Block>(ctx, end, number, number)
+            .await
     }
 
     #[trace_span(kind = "consensus.adapter", logs = "{txs_len: txs.len()}")]
@@ -139,7 +185,7 @@ where
 impl<M, N, S, CS, MT, DB> SynchronizationAdapter for OverlordConsensusAdapter<M, N, S, CS, MT, DB>
 where
     M: MemPool + 'static,
-    N: Rpc + PeerTrust + Gossip + 'static,
+    N: Rpc + PeerTrust + Gossip + Network + 'static,
     S: Storage + 'static,
     CS: CrossClient + 'static,
     MT: MetadataControl + 'static,
@@ -181,8 +227,7 @@ where
     #[trace_span(kind = "consensus.adapter")]
     async fn get_block_from_remote(&self, ctx: Context, number: u64) -> ProtocolResult<Block> {
         let res = self
-            .network
-            .call::<BlockNumber, Block>(ctx, RPC_SYNC_PULL_BLOCK, number, Priority::High)
+            .call_with_timeout::<BlockNumber, Block>(ctx, RPC_SYNC_PULL_BLOCK, number, number)
             .await;
         match res {
             Ok(data) => {
@@ -212,12 +257,11 @@ where
         hashes: &[Hash],
     ) -> ProtocolResult<Vec<SignedTransaction>> {
         let res = self
-            .network
-            .call::<PullTxsRequest, BatchSignedTxs>(
+            .call_with_timeout::<PullTxsRequest, BatchSignedTxs>(
                 ctx,
                 RPC_SYNC_PULL_TXS,
+                number,
                 PullTxsRequest::new(number, hashes.to_vec()),
-                Priority::High,
             )
             .await?;
         Ok(res.inner())
@@ -226,11 +270,41 @@ where
     /// Pull a proof of certain block from other nodes
     #[trace_span(kind = "consensus.adapter")]
     async fn get_proof_from_remote(&self, ctx: Context, number: u64) -> ProtocolResult<Proof> {
-        let ret = self
-            .network
-            .call::<BlockNumber, Proof>(ctx.clone(), RPC_SYNC_PULL_PROOF, number, Priority::High)
+        self.call_with_timeout::<BlockNumber, Proof>(ctx, RPC_SYNC_PULL_PROOF, number, number)
+            .await
+    }
+
+    /// Switch `verify_proofs` between the sync and the live concurrency.
+    fn set_sync_mode(&self, syncing: bool) {
+        self.sync_mode.store(syncing, Ordering::Release);
+    }
+
+    /// Verify the proofs of a batch of blocks, at most `proof_concurrency`
+    /// of them at the same time. Stop at the first invalid proof.
+    async fn verify_proofs(
+        self: &Arc<Self>,
+        ctx: Context,
+        proofs: Vec<(Block, Proof)>,
+    ) -> ProtocolResult<()> {
+        stream::iter(proofs)
+            .map(|(block, proof)| {
+                let adapter = Arc::clone(self);
+                let ctx = ctx.clone();
+
+                async move {
+                    match task::spawn(async move { adapter.verify_proof(ctx, block, proof).await })
+                        .await
+                    {
+                        Ok(res) => res,
+                        Err(e) => Err(ConsensusError::Other(e.to_string()).into()),
+                    }
+                }
+            })
+            .buffer_unordered(self.proof_concurrency())
+            .try_collect::<Vec<_>>()
             .await?;
-        Ok(ret)
+
+        Ok(())
     }
 }
 
@@ -238,7 +312,7 @@ where
 impl<M, N, S, CS, MT, DB> CommonConsensusAdapter for OverlordConsensusAdapter<M, N, S, CS, MT, DB>
 where
     M: MemPool + 'static,
-    N: Rpc + PeerTrust + Gossip + 'static,
+    N: Rpc + PeerTrust + Gossip + Network + 'static,
     S: Storage + 'static,
     CS: CrossClient + 'static,
     MT: MetadataControl + 'static,
@@ -255,7 +329,11 @@ where
         self.storage.update_latest_proof(ctx, proof).await
     }
 
-    /// Save some signed transactions to the database.
+    /// Save some signed transactions to the database. If a chunk size is set,
+    /// the transactions are written in several bounded batches. A failure in
+    /// the middle leaves the earlier chunks saved, which is fine since the
+    /// block is saved again on the next commit or sync and the inserts are
+    /// idempotent.
     #[trace_span(kind = "consensus.adapter", logs = "{txs_len: signed_txs.len()}")]
     async fn save_signed_txs(
         &self,
@@ -263,9 +341,33 @@ where
         block_number: u64,
         signed_txs: Vec<SignedTransaction>,
     ) -> ProtocolResult<()> {
-        self.storage
-            .insert_transactions(ctx, block_number, signed_txs)
-            .await
+        let chunk_size = match self.save_txs_chunk_size {
+            Some(size) if size > 0 && size < signed_txs.len() => size,
+            _ => {
+                return self
+                    .storage
+                    .insert_transactions(ctx, block_number, signed_txs)
+                    .await
+            }
+        };
+
+        let total = signed_txs.len();
+        let mut saved = 0;
+        for chunk in signed_txs.chunks(chunk_size) {
+            self.storage
+                .insert_transactions(ctx.clone(), block_number, chunk.to_vec())
+                .await?;
+
+            saved += chunk.len();
+            log::debug!(
+                "[consensus] save_signed_txs number {}, saved {}/{}",
+                block_number,
+                saved,
+                total
+            );
+        }
+
+        Ok(())
     }
 
     #[trace_span(kind = "consensus.adapter", logs = "{receipts_len: receipts.len()}")]
@@ -301,10 +403,17 @@ where
         ctx: Context,
         number: u64,
     ) -> ProtocolResult<Header> {
-        self.storage
+        if let Some(header) = self.header_cache.lock().get(&number) {
+            return Ok(header.clone());
+        }
+
+        let header: Header = self
+            .storage
             .get_block_header(ctx, number)
             .await?
-            .ok_or_else(|| ConsensusError::StorageItemNotFound.into())
+            .ok_or(ConsensusError::StorageItemNotFound)?;
+        self.header_cache.lock().put(number, header.clone());
+        Ok(header)
     }
 
     /// Get the current number from storage.
@@ -320,13 +429,12 @@ where
         ctx: Context,
         tx_hashes: &[Hash],
     ) -> ProtocolResult<Vec<SignedTransaction>> {
-        let futs = tx_hashes
-            .iter()
+        let txs = stream::iter(tx_hashes)
             .map(|tx_hash| self.storage.get_transaction_by_hash(ctx.clone(), tx_hash))
-            .collect::<Vec<_>>();
-        futures::future::try_join_all(futs)
-            .await
-            .map(|txs| txs.into_iter().flatten().collect::<Vec<_>>())
+            .buffered(self.tx_read_concurrency)
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(txs.into_iter().flatten().collect())
     }
 
     #[allow(unused_braces)]
@@ -345,15 +453,17 @@ where
             proposal.clone().into(),
         )?;
 
-        Ok(task::block_in_place(|| {
-            let time = Instant::now();
-            let res = AxonExecutor::default().exec(&mut backend, signed_txs);
-            common_apm::metrics::consensus::CONSENSUS_TIME_HISTOGRAM_VEC_STATIC
-                .exec
-                .observe(common_apm::metrics::duration_to_sec(time.elapsed()));
+        task::block_in_place(|| {
+            exec_catching(proposal.number, self.exec_panic_policy, || {
+                let time = Instant::now();
+                let res = AxonExecutor::default().exec(&mut backend, signed_txs);
+                common_apm::metrics::consensus::CONSENSUS_TIME_HISTOGRAM_VEC_STATIC
+                    .exec
+                    .observe(common_apm::metrics::duration_to_sec(time.elapsed()));
 
-            res
-        }))
+                res
+            })
+        })
     }
 
     fn need_change_metadata(&self, block_number: u64) -> bool {
@@ -369,7 +479,9 @@ where
     }
 
     fn update_metadata(&self, ctx: Context, header: &Header) -> ProtocolResult<()> {
-        self.metadata.update_metadata(ctx, header)
+        let metadata = self.metadata.update_metadata(ctx, header)?;
+        let _ = self.metadata_tx.send(metadata);
+        Ok(())
     }
 
     #[trace_span(kind = "consensus.adapter")]
@@ -384,14 +496,13 @@ where
             .set_args(context, state_root, gas_limit, max_tx_size);
     }
 
-    fn tag_consensus(&self, _ctx: Context, _pub_keys: Vec<Bytes>) -> ProtocolResult<()> {
-        // let _peer_ids_bytes = pub_keys
-        //     .iter()
-        //     .map(|pk| PeerId::from_pubkey_bytes(pk).map(PeerIdExt::into_bytes_ext))
-        //     .collect::<Result<_, _>>()?;
+    fn tag_consensus(&self, ctx: Context, pub_keys: Vec<Bytes>) -> ProtocolResult<()> {
+        let peer_ids_bytes = pub_keys
+            .iter()
+            .map(|pk| PeerId::from_pubkey_bytes(pk).map(PeerIdExt::into_bytes_ext))
+            .collect::<Result<_, _>>()?;
 
-        // self.network.tag_consensus(ctx, peer_ids_bytes)
-        Ok(())
+        self.network.tag_consensus(ctx, peer_ids_bytes)
     }
 
     /// this function verify all info in header except proof and roots
@@ -444,8 +555,7 @@ where
             .into());
         }
 
-        // Todo: impl From<&Block> for Proposal
-        let proposal_hash = Hasher::digest(Proposal::from(block.clone()).encode()?);
+        let proposal_hash = block.proposal_hash()?;
 
         if proposal_hash != proof.block_hash {
             log::error!(
@@ -606,11 +716,14 @@ where
             }
         }
 
-        if 3 * accumulator <= 2 * total_validator_weight {
+        let (num, den) = self.vote_threshold;
+        if den * accumulator <= num * total_validator_weight {
             log::error!(
-                "[consensus] verify_proof_weight, accumulator: {}, total: {}",
+                "[consensus] verify_proof_weight, accumulator: {}, total: {}, threshold: {}/{}",
                 accumulator,
-                total_validator_weight
+                total_validator_weight,
+                num,
+                den
             );
 
             return Err(ConsensusError::VerifyProof(block_number, BlockProofField::Weight).into());
@@ -622,7 +735,7 @@ where
 impl<M, N, S, CS, MT, DB> OverlordConsensusAdapter<M, N, S, CS, MT, DB>
 where
     M: MemPool + 'static,
-    N: Rpc + PeerTrust + Gossip + 'static,
+    N: Rpc + PeerTrust + Gossip + Network + 'static,
     S: Storage + 'static,
     CS: CrossClient + 'static,
     MT: MetadataControl + 'static,
@@ -637,19 +750,1133 @@ where
         metadata: Arc<MT>,
         crypto: Arc<OverlordCrypto>,
     ) -> ProtocolResult<Self> {
+        let (metadata_tx, metadata_rx) = watch::channel(Metadata::default());
         Ok(OverlordConsensusAdapter {
             network,
             mempool,
             storage,
             metadata,
+            metadata_tx,
+            metadata_rx,
             trie_db,
             cross_client,
             overlord_handler: RwLock::new(None),
             crypto,
+            save_txs_chunk_size: None,
+            sync_proof_concurrency: std::thread::available_parallelism()
+                .map(usize::from)
+                .unwrap_or(1),
+            live_proof_concurrency: 1,
+            tx_read_concurrency: DEFAULT_TX_READ_CONCURRENCY,
+            vote_threshold: DEFAULT_VOTE_THRESHOLD,
+            rpc_timeout: DEFAULT_RPC_TIMEOUT,
+            sync_mode: AtomicBool::new(false),
+            exec_panic_policy: ExecPanicPolicy::default(),
+            header_cache: Mutex::new(LruCache::new(HEADER_CACHE_SIZE)),
         })
     }
 
+    pub fn save_txs_chunk_size(mut self, size: Option<usize>) -> Self {
+        self.save_txs_chunk_size = size;
+        self
+    }
+
+    pub fn sync_proof_concurrency(mut self, concurrency: Option<usize>) -> Self {
+        if let Some(n) = concurrency.filter(|n| *n > 0) {
+            self.sync_proof_concurrency = n;
+        }
+        self
+    }
+
+    pub fn live_proof_concurrency(mut self, concurrency: Option<usize>) -> Self {
+        if let Some(n) = concurrency.filter(|n| *n > 0) {
+            self.live_proof_concurrency = n;
+        }
+        self
+    }
+
+    pub fn tx_read_concurrency(mut self, concurrency: Option<usize>) -> Self {
+        if let Some(n) = concurrency.filter(|n| *n > 0) {
+            self.tx_read_concurrency = n;
+        }
+        self
+    }
+
+    pub fn rpc_timeout(mut self, timeout: Option<Duration>) -> Self {
+        if let Some(timeout) = timeout.filter(|t| !t.is_zero()) {
+            self.rpc_timeout = timeout;
+        }
+        self
+    }
+
+    /// The fraction `num / den` of the total vote weight a proof has to
+    /// exceed, which must be in (1/2, 1].
+    pub fn vote_threshold(mut self, threshold: Option<(u64, u64)>) -> ProtocolResult<Self> {
+        if let Some((num, den)) = threshold {
+            if den == 0 || 2 * num <= den || num > den {
+                return Err(ConsensusError::InvalidVoteThreshold(num, den).into());
+            }
+            self.vote_threshold = (num, den);
+        }
+        Ok(self)
+    }
+
+    pub fn exec_panic_policy(mut self, policy: ExecPanicPolicy) -> Self {
+        self.exec_panic_policy = policy;
+        self
+    }
+
+    // A silent peer fails the call with `RpcTimeout` instead of hanging the
+    // sync worker.
+    async fn call_with_timeout<M: MessageCodec, R: MessageCodec>(
+        &self,
+        ctx: Context,
+        end: &str,
+        number: u64,
+        msg: M,
+    ) -> ProtocolResult<R> {
+        tokio::time::timeout(
+            self.rpc_timeout,
+            self.network.call::<M, R>(ctx, end, msg, Priority::High),
+        )
+        .await
+        .map_err(|_| ConsensusError::RpcTimeout {
+            end: end.to_string(),
+            number,
+        })?
+    }
+
+    /// Watch the metadata `update_metadata` brings, the receiver holds the
+    /// default metadata until the first update.
+    pub fn subscribe_metadata(&self) -> watch::Receiver<Metadata> {
+        self.metadata_rx.clone()
+    }
+
+    pub fn proof_concurrency(&self) -> usize {
+        if self.sync_mode.load(Ordering::Acquire) {
+            self.sync_proof_concurrency
+        } else {
+            self.live_proof_concurrency
+        }
+    }
+
     pub fn set_overlord_handler(&self, handler: OverlordHandler<Proposal>) {
         *self.overlord_handler.write() = Some(handler)
     }
+
+    /// Parse the bls public keys of all the validators in the metadata, so
+    /// that a bad key is found before verifying the first proof.
+    pub fn validate_crypto_config(&self, metadata: &Metadata) -> ProtocolResult<()> {
+        for validator in metadata.verifier_list.iter() {
+            convert_hex_to_bls_pubkeys(validator.bls_pub_key.clone()).map_err(|e| {
+                ConsensusError::InvalidBlsPubKey {
+                    address: validator.address,
+                    err:     e.to_string(),
+                }
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that the block has no duplicate tx hashes, that every tx can be
+    /// found in the mempool or storage, and that the tx hashes are in the
+    /// order committed by the transactions root.
+    pub async fn validate_block_txs(&self, ctx: Context, block: &Block) -> ProtocolResult<()> {
+        let number = block.header.number;
+
+        let mut seen = HashSet::with_capacity(block.tx_hashes.len());
+        if let Some(hash) = block.tx_hashes.iter().find(|hash| !seen.insert(*hash)) {
+            return Err(ConsensusError::DuplicateTx(number, *hash).into());
+        }
+
+        // The storage is read like in `get_txs_from_storage`, and the txs not
+        // committed yet are looked up in the mempool at once
+        let committed = stream::iter(block.tx_hashes.iter())
+            .map(|hash| self.storage.get_transaction_by_hash(ctx.clone(), hash))
+            .buffered(self.tx_read_concurrency)
+            .try_collect::<Vec<_>>()
+            .await?;
+        let uncommitted = block
+            .tx_hashes
+            .iter()
+            .zip(committed.iter())
+            .filter(|(_, tx)| tx.is_none())
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+        if !uncommitted.is_empty() {
+            let (_, missing) = self
+                .mempool
+                .get_full_txs_partial(ctx, Some(number), &uncommitted)
+                .await?;
+            if let Some(hash) = missing.first() {
+                return Err(ConsensusError::MissingTx(number, *hash).into());
+            }
+        }
+
+        let order_root = Merkle::from_hashes(block.tx_hashes.clone())
+            .get_root_hash()
+            .unwrap_or_default();
+        if order_root != block.header.transactions_root {
+            return Err(ConsensusError::InvalidOrderRoot {
+                expect: order_root,
+                actual: block.header.transactions_root,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+// The backend is dropped after a caught panic, its state root is never used,
+// so the half written trie nodes are unreachable.
+fn exec_catching<F: FnOnce() -> ExecResp>(
+    number: BlockNumber,
+    policy: ExecPanicPolicy,
+    exec: F,
+) -> ProtocolResult<ExecResp> {
+    if policy == ExecPanicPolicy::Raise {
+        return Ok(exec());
+    }
+
+    panic::catch_unwind(AssertUnwindSafe(exec)).map_err(|_| {
+        log::error!("[consensus] execution of block {} panicked", number);
+        ConsensusError::ExecutionPanic { number }.into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    use common_crypto::{BlsPrivateKey, PrivateKey, PublicKey, Secp256k1PrivateKey, ToPublicKey};
+    use core_mempool::MemPoolError;
+    use core_storage::{adapter::memory::MemoryAdapter, ImplStorage};
+    use protocol::codec::hex_decode;
+    use protocol::traits::{CommonStorage, MessageCodec, PeerTag};
+    use protocol::types::{
+        MetadataVersion, Transaction, TransactionAction, UnverifiedTransaction, ValidatorExtend,
+        H256,
+    };
+    use protocol::{tokio, ProtocolResult};
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    type TestAdapter = OverlordConsensusAdapter<
+        MockMemPool,
+        MockNetwork,
+        ImplStorage<MemoryAdapter>,
+        MockCrossClient,
+        MockMetadata,
+        cita_trie::MemoryDB,
+    >;
+
+    #[derive(Default)]
+    struct MockMemPool {
+        txs:         RwLock<HashMap<Hash, SignedTransaction>>,
+        // Fail the tx lookups if set, like a broken mempool
+        unavailable: AtomicBool,
+    }
+
+    #[async_trait]
+    impl MemPool for MockMemPool {
+        async fn insert(&self, _ctx: Context, tx: SignedTransaction) -> ProtocolResult<()> {
+            self.txs.write().insert(tx.transaction.hash, tx);
+            Ok(())
+        }
+
+        async fn package(
+            &self,
+            _ctx: Context,
+            _gas_limit: U256,
+            _tx_num_limit: u64,
+        ) -> ProtocolResult<Vec<Hash>> {
+            Ok(self.txs.read().keys().cloned().collect())
+        }
+
+        async fn flush(&self, _ctx: Context, tx_hashes: &[Hash]) -> ProtocolResult<()> {
+            let mut txs = self.txs.write();
+            tx_hashes.iter().for_each(|hash| {
+                txs.remove(hash);
+            });
+            Ok(())
+        }
+
+        async fn get_full_txs(
+            &self,
+            ctx: Context,
+            height: Option<u64>,
+            tx_hashes: &[Hash],
+        ) -> ProtocolResult<Vec<SignedTransaction>> {
+            // Any missing tx fails the call, like in the real mempool
+            let (full_txs, missing) = self.get_full_txs_partial(ctx, height, tx_hashes).await?;
+            if !missing.is_empty() {
+                return Err(MemPoolError::MisMatch {
+                    require:  tx_hashes.len(),
+                    response: full_txs.len(),
+                }
+                .into());
+            }
+            Ok(full_txs)
+        }
+
+        async fn get_full_txs_partial(
+            &self,
+            _ctx: Context,
+            _height: Option<u64>,
+            tx_hashes: &[Hash],
+        ) -> ProtocolResult<(Vec<SignedTransaction>, Vec<Hash>)> {
+            if self.unavailable.load(Ordering::SeqCst) {
+                return Err(ConsensusError::Other("mempool unavailable".to_string()).into());
+            }
+
+            let txs = self.txs.read();
+            let (found, missing): (Vec<_>, Vec<_>) =
+                tx_hashes.iter().partition(|hash| txs.contains_key(hash));
+            Ok((
+                found.into_iter().map(|hash| txs[hash].clone()).collect(),
+                missing.into_iter().cloned().collect(),
+            ))
+        }
+
+        async fn ensure_order_txs(
+            &self,
+            _ctx: Context,
+            _height: Option<u64>,
+            order_tx_hashes: &[Hash],
+        ) -> ProtocolResult<()> {
+            let txs = self.txs.read();
+            if order_tx_hashes.iter().all(|hash| txs.contains_key(hash)) {
+                Ok(())
+            } else {
+                Err(ConsensusError::Other("missing txs".to_string()).into())
+            }
+        }
+
+        async fn get_tx_count_by_address(
+            &self,
+            _ctx: Context,
+            address: H160,
+        ) -> ProtocolResult<usize> {
+            Ok(self
+                .txs
+                .read()
+                .values()
+                .filter(|tx| tx.sender == address)
+                .count())
+        }
+
+        fn set_args(
+            &self,
+            _context: Context,
+            _state_root: MerkleRoot,
+            _gas_limit: u64,
+            _max_tx_size: u64,
+        ) {
+        }
+    }
+
+    #[derive(Default)]
+    struct MockNetwork {
+        consensus_peers: Mutex<Vec<Bytes>>,
+        // The calls never resolve, like a peer which stays silent
+        silent:          bool,
+    }
+
+    #[async_trait]
+    impl Gossip for MockNetwork {
+        async fn broadcast<M>(
+            &self,
+            _cx: Context,
+            _end: &str,
+            _msg: M,
+            _p: Priority,
+        ) -> ProtocolResult<()>
+        where
+            M: MessageCodec,
+        {
+            Ok(())
+        }
+
+        async fn multicast<'a, M, P>(
+            &self,
+            _cx: Context,
+            _end: &str,
+            _peer_ids: P,
+            _msg: M,
+            _p: Priority,
+        ) -> ProtocolResult<()>
+        where
+            M: MessageCodec,
+            P: AsRef<[Bytes]> + Send + 'a,
+        {
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Rpc for MockNetwork {
+        async fn call<M, R>(
+            &self,
+            _ctx: Context,
+            _end: &str,
+            _msg: M,
+            _pri: Priority,
+        ) -> ProtocolResult<R>
+        where
+            M: MessageCodec,
+            R: MessageCodec,
+        {
+            if self.silent {
+                futures::future::pending::<()>().await;
+            }
+            Err(ConsensusError::Other("no remote peer".to_string()).into())
+        }
+
+        async fn response<M>(
+            &self,
+            _cx: Context,
+            _end: &str,
+            _ret: ProtocolResult<M>,
+            _p: Priority,
+        ) -> ProtocolResult<()>
+        where
+            M: MessageCodec,
+        {
+            Ok(())
+        }
+    }
+
+    impl PeerTrust for MockNetwork {
+        fn report(&self, _ctx: Context, _feedback: protocol::traits::TrustFeedback) {}
+    }
+
+    impl Network for MockNetwork {
+        fn tag(&self, _ctx: Context, _peer_id: Bytes, _tag: PeerTag) -> ProtocolResult<()> {
+            Ok(())
+        }
+
+        fn untag(&self, _ctx: Context, _peer_id: Bytes, _tag: &PeerTag) -> ProtocolResult<()> {
+            Ok(())
+        }
+
+        fn tag_consensus(&self, _ctx: Context, peer_ids: Vec<Bytes>) -> ProtocolResult<()> {
+            *self.consensus_peers.lock() = peer_ids;
+            Ok(())
+        }
+
+        fn peer_count(&self, _ctx: Context) -> ProtocolResult<usize> {
+            Ok(0)
+        }
+    }
+
+    struct MockCrossClient;
+
+    #[async_trait]
+    impl CrossClient for MockCrossClient {
+        async fn set_evm_log(
+            &self,
+            _ctx: Context,
+            _block_number: BlockNumber,
+            _block_hash: Hash,
+            _logs: &[Vec<Log>],
+        ) {
+        }
+
+        async fn set_checkpoint(&self, _ctx: Context, _block: Block, _proof: Proof) {}
+    }
+
+    #[derive(Default)]
+    struct MockMetadata {
+        // Hold `get_metadata` to probe how many proofs are verified at once
+        delay:     Option<Duration>,
+        verifying: AtomicUsize,
+        peak:      AtomicUsize,
+    }
+
+    impl MetadataControl for MockMetadata {
+        fn calc_epoch(&self, _block_number: u64) -> u64 {
+            0
+        }
+
+        fn need_change_metadata(&self, _block_number: u64) -> bool {
+            false
+        }
+
+        fn update_metadata(&self, _ctx: Context, header: &Header) -> ProtocolResult<Metadata> {
+            Ok(Metadata {
+                version: MetadataVersion::new(header.number + 1, u64::MAX),
+                epoch: 1,
+                ..Default::default()
+            })
+        }
+
+        fn get_metadata(&self, _ctx: Context, _header: &Header) -> ProtocolResult<Metadata> {
+            if let Some(delay) = self.delay {
+                let verifying = self.verifying.fetch_add(1, Ordering::SeqCst) + 1;
+                self.peak.fetch_max(verifying, Ordering::SeqCst);
+                std::thread::sleep(delay);
+                self.verifying.fetch_sub(1, Ordering::SeqCst);
+            }
+
+            Ok(Metadata::default())
+        }
+
+        fn get_metadata_unchecked(&self, _ctx: Context, _block_number: u64) -> Metadata {
+            Metadata::default()
+        }
+    }
+
+    fn mock_crypto() -> OverlordCrypto {
+        let priv_key =
+            hex_decode("37aa0f893d05914a4def0460c0a984d3611546cfb26924d7a7ca6e0db9950a2d").unwrap();
+        OverlordCrypto::new(
+            BlsPrivateKey::try_from(priv_key.as_ref()).unwrap(),
+            HashMap::new(),
+            "muta".into(),
+        )
+    }
+
+    fn mock_adapter() -> TestAdapter {
+        mock_adapter_with_metadata(MockMetadata::default())
+    }
+
+    fn mock_adapter_with_metadata(metadata: MockMetadata) -> TestAdapter {
+        OverlordConsensusAdapter::new(
+            Arc::new(MockNetwork::default()),
+            Arc::new(MockMemPool::default()),
+            Arc::new(ImplStorage::new(Arc::new(MemoryAdapter::new()))),
+            Arc::new(cita_trie::MemoryDB::new(false)),
+            Arc::new(MockCrossClient),
+            Arc::new(metadata),
+            Arc::new(mock_crypto()),
+        )
+        .unwrap()
+    }
+
+    // Counts the header reads which reach the storage, and the peak of the
+    // concurrent tx reads
+    struct CountingStorage {
+        inner:         ImplStorage<MemoryAdapter>,
+        header_reads:  AtomicUsize,
+        tx_reads:      AtomicUsize,
+        tx_reads_peak: AtomicUsize,
+    }
+
+    impl CountingStorage {
+        fn new() -> Self {
+            CountingStorage {
+                inner:         ImplStorage::new(Arc::new(MemoryAdapter::new())),
+                header_reads:  AtomicUsize::new(0),
+                tx_reads:      AtomicUsize::new(0),
+                tx_reads_peak: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl CommonStorage for CountingStorage {
+        async fn insert_block(&self, ctx: Context, block: Block) -> ProtocolResult<()> {
+            self.inner.insert_block(ctx, block).await
+        }
+
+        async fn get_block(&self, ctx: Context, height: u64) -> ProtocolResult<Option<Block>> {
+            self.inner.get_block(ctx, height).await
+        }
+
+        async fn get_block_header(
+            &self,
+            ctx: Context,
+            height: u64,
+        ) -> ProtocolResult<Option<Header>> {
+            self.header_reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.get_block_header(ctx, height).await
+        }
+
+        async fn set_block(&self, ctx: Context, block: Block) -> ProtocolResult<()> {
+            self.inner.set_block(ctx, block).await
+        }
+
+        async fn remove_block(&self, ctx: Context, height: u64) -> ProtocolResult<()> {
+            self.inner.remove_block(ctx, height).await
+        }
+
+        async fn get_latest_block(&self, ctx: Context) -> ProtocolResult<Block> {
+            self.inner.get_latest_block(ctx).await
+        }
+
+        async fn set_latest_block(&self, ctx: Context, block: Block) -> ProtocolResult<()> {
+            self.inner.set_latest_block(ctx, block).await
+        }
+
+        async fn get_latest_block_header(&self, ctx: Context) -> ProtocolResult<Header> {
+            self.inner.get_latest_block_header(ctx).await
+        }
+    }
+
+    #[async_trait]
+    impl Storage for CountingStorage {
+        async fn insert_transactions(
+            &self,
+            ctx: Context,
+            block_height: u64,
+            signed_txs: Vec<SignedTransaction>,
+        ) -> ProtocolResult<()> {
+            self.inner
+                .insert_transactions(ctx, block_height, signed_txs)
+                .await
+        }
+
+        async fn get_block_by_hash(
+            &self,
+            ctx: Context,
+            block_hash: &Hash,
+        ) -> ProtocolResult<Option<Block>> {
+            self.inner.get_block_by_hash(ctx, block_hash).await
+        }
+
+        async fn get_transactions(
+            &self,
+            ctx: Context,
+            block_height: u64,
+            hashes: &[Hash],
+        ) -> ProtocolResult<Vec<Option<SignedTransaction>>> {
+            self.inner.get_transactions(ctx, block_height, hashes).await
+        }
+
+        async fn get_transaction_by_hash(
+            &self,
+            ctx: Context,
+            hash: &Hash,
+        ) -> ProtocolResult<Option<SignedTransaction>> {
+            let reads = self.tx_reads.fetch_add(1, Ordering::SeqCst) + 1;
+            self.tx_reads_peak.fetch_max(reads, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            let res = self.inner.get_transaction_by_hash(ctx, hash).await;
+            self.tx_reads.fetch_sub(1, Ordering::SeqCst);
+            res
+        }
+
+        async fn insert_receipts(
+            &self,
+            ctx: Context,
+            block_height: u64,
+            receipts: Vec<Receipt>,
+        ) -> ProtocolResult<()> {
+            self.inner
+                .insert_receipts(ctx, block_height, receipts)
+                .await
+        }
+
+        async fn insert_code(
+            &self,
+            ctx: Context,
+            code_address: H256,
+            code_hash: Hash,
+            code: Bytes,
+        ) -> ProtocolResult<()> {
+            self.inner
+                .insert_code(ctx, code_address, code_hash, code)
+                .await
+        }
+
+        async fn get_code_by_hash(
+            &self,
+            ctx: Context,
+            hash: &Hash,
+        ) -> ProtocolResult<Option<Bytes>> {
+            self.inner.get_code_by_hash(ctx, hash).await
+        }
+
+        async fn get_code_by_address(
+            &self,
+            ctx: Context,
+            address: &H256,
+        ) -> ProtocolResult<Option<Bytes>> {
+            self.inner.get_code_by_address(ctx, address).await
+        }
+
+        async fn get_receipt_by_hash(
+            &self,
+            ctx: Context,
+            hash: &Hash,
+        ) -> ProtocolResult<Option<Receipt>> {
+            self.inner.get_receipt_by_hash(ctx, hash).await
+        }
+
+        async fn get_receipts(
+            &self,
+            ctx: Context,
+            block_height: u64,
+            hashes: &[Hash],
+        ) -> ProtocolResult<Vec<Option<Receipt>>> {
+            self.inner.get_receipts(ctx, block_height, hashes).await
+        }
+
+        async fn update_latest_proof(&self, ctx: Context, proof: Proof) -> ProtocolResult<()> {
+            self.inner.update_latest_proof(ctx, proof).await
+        }
+
+        async fn get_latest_proof(&self, ctx: Context) -> ProtocolResult<Proof> {
+            self.inner.get_latest_proof(ctx).await
+        }
+    }
+
+    fn counting_adapter(
+        storage: &Arc<CountingStorage>,
+    ) -> OverlordConsensusAdapter<
+        MockMemPool,
+        MockNetwork,
+        CountingStorage,
+        MockCrossClient,
+        MockMetadata,
+        cita_trie::MemoryDB,
+    > {
+        OverlordConsensusAdapter::new(
+            Arc::new(MockNetwork::default()),
+            Arc::new(MockMemPool::default()),
+            Arc::clone(storage),
+            Arc::new(cita_trie::MemoryDB::new(false)),
+            Arc::new(MockCrossClient),
+            Arc::new(MockMetadata::default()),
+            Arc::new(mock_crypto()),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_header_cache() {
+        let storage = Arc::new(CountingStorage::new());
+        let adapter = counting_adapter(&storage);
+
+        let mut parent = Block::default();
+        parent.header.number = 1;
+        storage
+            .set_block(Context::new(), parent.clone())
+            .await
+            .unwrap();
+
+        let proposal = Proposal {
+            prev_hash: parent.header.hash(),
+            number: 2,
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            adapter
+                .verify_block_header(Context::new(), &proposal)
+                .await
+                .unwrap();
+        }
+        assert_eq!(storage.header_reads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_tag_consensus() {
+        let adapter = mock_adapter();
+        let pub_keys = (0..4)
+            .map(|_| {
+                Secp256k1PrivateKey::generate(&mut OsRng)
+                    .pub_key()
+                    .to_bytes()
+            })
+            .collect::<Vec<_>>();
+
+        adapter
+            .tag_consensus(Context::new(), pub_keys.clone())
+            .unwrap();
+        let expected = pub_keys
+            .iter()
+            .map(|pk| PeerId::from_pubkey_bytes(pk).unwrap().into_bytes_ext())
+            .collect::<Vec<_>>();
+        assert_eq!(*adapter.network.consensus_peers.lock(), expected);
+
+        let res = adapter.tag_consensus(Context::new(), vec![Bytes::from_static(b"bad")]);
+        assert!(res.is_err());
+    }
+
+    fn mock_signed_tx(nonce: u64) -> SignedTransaction {
+        let raw = Transaction {
+            nonce:                    nonce.into(),
+            max_priority_fee_per_gas: U256::one(),
+            gas_price:                U256::one(),
+            gas_limit:                U256::from(21000u64),
+            action:                   TransactionAction::Create,
+            value:                    U256::zero(),
+            data:                     Bytes::new(),
+            access_list:              vec![],
+        };
+
+        let tx = UnverifiedTransaction {
+            unsigned:  raw,
+            signature: Some(Bytes::from(vec![0u8; 65]).into()),
+            chain_id:  0,
+            hash:      Default::default(),
+        };
+
+        SignedTransaction {
+            transaction: tx.hash(),
+            sender:      H160::default(),
+            public:      None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_signed_txs_in_chunks() {
+        let adapter = mock_adapter().save_txs_chunk_size(Some(7));
+        let txs = (0..100).map(mock_signed_tx).collect::<Vec<_>>();
+        let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+        adapter
+            .save_signed_txs(Context::new(), 1, txs.clone())
+            .await
+            .unwrap();
+
+        let saved = adapter
+            .get_txs_from_storage(Context::new(), &hashes)
+            .await
+            .unwrap();
+        assert_eq!(saved, txs);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_timeout() {
+        let timeout = Duration::from_millis(100);
+        let adapter = OverlordConsensusAdapter::new(
+            Arc::new(MockNetwork {
+                silent: true,
+                ..Default::default()
+            }),
+            Arc::new(MockMemPool::default()),
+            Arc::new(ImplStorage::new(Arc::new(MemoryAdapter::new()))),
+            Arc::new(cita_trie::MemoryDB::new(false)),
+            Arc::new(MockCrossClient),
+            Arc::new(MockMetadata::default()),
+            Arc::new(mock_crypto()),
+        )
+        .unwrap()
+        .rpc_timeout(Some(timeout));
+
+        let start = std::time::Instant::now();
+        let err = adapter
+            .get_proof_from_remote(Context::new(), 7)
+            .await
+            .unwrap_err();
+        let elapsed = start.elapsed();
+        assert!(err.to_string().contains("RpcTimeout"));
+        assert!(elapsed >= timeout && elapsed < timeout * 10);
+
+        let err = adapter
+            .get_txs_from_remote(Context::new(), 7, &[Hash::default()])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("RpcTimeout"));
+        let err = adapter
+            .pull_block(Context::new(), 7, RPC_SYNC_PULL_BLOCK)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("RpcTimeout"));
+    }
+
+    #[tokio::test]
+    async fn test_tx_read_concurrency() {
+        let storage = Arc::new(CountingStorage::new());
+        let adapter = counting_adapter(&storage).tx_read_concurrency(Some(4));
+        let txs = (0..50).map(mock_signed_tx).collect::<Vec<_>>();
+        let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+        adapter
+            .save_signed_txs(Context::new(), 1, txs.clone())
+            .await
+            .unwrap();
+
+        let saved = adapter
+            .get_txs_from_storage(Context::new(), &hashes)
+            .await
+            .unwrap();
+        assert_eq!(saved, txs);
+        let peak = storage.tx_reads_peak.load(Ordering::SeqCst);
+        assert!(peak > 1 && peak <= 4);
+
+        // So does the check of the block txs
+        storage.tx_reads_peak.store(0, Ordering::SeqCst);
+        let mut block = Block::default();
+        block.header.number = 2;
+        block.header.transactions_root = Merkle::from_hashes(hashes.clone())
+            .get_root_hash()
+            .unwrap_or_default();
+        block.tx_hashes = hashes;
+        adapter
+            .validate_block_txs(Context::new(), &block)
+            .await
+            .unwrap();
+        let peak = storage.tx_reads_peak.load(Ordering::SeqCst);
+        assert!(peak > 1 && peak <= 4);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_metadata() {
+        let adapter = mock_adapter();
+        let mut rx = adapter.subscribe_metadata();
+        assert_eq!(*rx.borrow(), Metadata::default());
+
+        let mut header = Block::default().header;
+        header.number = 10;
+        adapter.update_metadata(Context::new(), &header).unwrap();
+
+        rx.changed().await.unwrap();
+        assert_eq!(rx.borrow().epoch, 1);
+        assert_eq!(rx.borrow().version, MetadataVersion::new(11, u64::MAX));
+        // A late subscriber sees the latest metadata
+        assert_eq!(adapter.subscribe_metadata().borrow().epoch, 1);
+    }
+
+    async fn mock_block_txs(adapter: &TestAdapter, number: u64) -> Block {
+        let txs = (0..10).map(mock_signed_tx).collect::<Vec<_>>();
+        let tx_hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+        // Half of the txs are committed, the others are still pooled.
+        adapter
+            .save_signed_txs(Context::new(), number, txs[..5].to_vec())
+            .await
+            .unwrap();
+        for tx in txs[5..].iter() {
+            adapter
+                .mempool
+                .insert(Context::new(), tx.clone())
+                .await
+                .unwrap();
+        }
+
+        let mut block = Block::default();
+        block.header.number = number;
+        block.header.transactions_root = Merkle::from_hashes(tx_hashes.clone())
+            .get_root_hash()
+            .unwrap_or_default();
+        block.tx_hashes = tx_hashes;
+        block
+    }
+
+    #[tokio::test]
+    async fn test_validate_block_txs() {
+        let adapter = mock_adapter();
+        let block = mock_block_txs(&adapter, 1).await;
+
+        assert!(adapter
+            .validate_block_txs(Context::new(), &block)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_block_txs_missing() {
+        let adapter = mock_adapter();
+        let mut block = mock_block_txs(&adapter, 1).await;
+        let missing = mock_signed_tx(100).transaction.hash;
+        block.tx_hashes.insert(3, missing);
+        block.tx_hashes.push(mock_signed_tx(101).transaction.hash);
+        block.header.transactions_root = Merkle::from_hashes(block.tx_hashes.clone())
+            .get_root_hash()
+            .unwrap_or_default();
+
+        // The full lookup only tells that some tx is missing
+        let err = adapter
+            .mempool
+            .get_full_txs(Context::new(), Some(1), &block.tx_hashes[5..])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("MisMatch"));
+
+        // The first missing tx is named
+        let err = adapter
+            .validate_block_txs(Context::new(), &block)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("MissingTx"));
+        assert!(err.to_string().contains(&format!("{:?}", missing)));
+    }
+
+    #[tokio::test]
+    async fn test_validate_block_txs_mempool_err() {
+        let adapter = mock_adapter();
+        let block = mock_block_txs(&adapter, 1).await;
+        adapter.mempool.unavailable.store(true, Ordering::SeqCst);
+
+        // The mempool error is not mistaken for a missing tx
+        let err = adapter
+            .validate_block_txs(Context::new(), &block)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("mempool unavailable"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_block_txs_reordered() {
+        let adapter = mock_adapter();
+        let mut block = mock_block_txs(&adapter, 1).await;
+        block.tx_hashes.swap(2, 7);
+
+        let err = adapter
+            .validate_block_txs(Context::new(), &block)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("InvalidOrderRoot"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_block_txs_duplicate() {
+        let adapter = mock_adapter();
+        let mut block = mock_block_txs(&adapter, 1).await;
+        block.tx_hashes.push(block.tx_hashes[0]);
+
+        let err = adapter
+            .validate_block_txs(Context::new(), &block)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("DuplicateTx"));
+    }
+
+    #[test]
+    fn test_exec_catching() {
+        let resp = ExecResp {
+            state_root:   MerkleRoot::default(),
+            receipt_root: MerkleRoot::default(),
+            gas_used:     0,
+            tx_resp:      vec![],
+        };
+        let ok = exec_catching(10, ExecPanicPolicy::Catch, || resp.clone()).unwrap();
+        assert_eq!(ok, resp);
+
+        let err =
+            exec_catching(10, ExecPanicPolicy::Catch, || panic!("mock executor")).unwrap_err();
+        assert!(err.to_string().contains("ExecutionPanic"));
+
+        let raised = panic::catch_unwind(|| {
+            exec_catching(10, ExecPanicPolicy::Raise, || panic!("mock executor"))
+        });
+        assert!(raised.is_err());
+    }
+
+    #[test]
+    fn test_validate_crypto_config() {
+        let adapter = mock_adapter();
+        let bls_pub_keys = [
+            "0xac85bbb40347b6e06ac2dc2da1f75eece029cdc0ed2d456c457d27e288bfbfbcd4c5c19716e9b250134a0e76ce50fa22",
+            "0x91ed9f3c51c580e56948b1bda9d00c2159665f8a6e284191ab816ee64ef2487d78453a547a0f14efbf842bba5b5a3b4f",
+        ];
+        let mut metadata = Metadata::default();
+        metadata.verifier_list = bls_pub_keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| ValidatorExtend {
+                bls_pub_key: Hex::from_string(key.to_string()).unwrap(),
+                address: H160::from_low_u64_be(i as u64 + 1),
+                ..Default::default()
+            })
+            .collect();
+        adapter.validate_crypto_config(&metadata).unwrap();
+
+        let bad = H160::from_low_u64_be(0xbad);
+        metadata.verifier_list.push(ValidatorExtend {
+            bls_pub_key: Hex::from_string("0x1234".to_string()).unwrap(),
+            address: bad,
+            ..Default::default()
+        });
+        let err = adapter.validate_crypto_config(&metadata).unwrap_err();
+        assert!(err.to_string().contains("InvalidBlsPubKey"));
+        assert!(err.to_string().contains(&format!("{:?}", bad)));
+    }
+
+    fn mock_weights(count: u64) -> HashMap<Bytes, u32> {
+        (0..count)
+            .map(|i| (Bytes::from(i.to_be_bytes().to_vec()), 1))
+            .collect()
+    }
+
+    fn mock_voters(count: u64) -> Vec<Bytes> {
+        (0..count)
+            .map(|i| Bytes::from(i.to_be_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_vote_threshold() {
+        let adapter = mock_adapter();
+
+        // Exactly 2/3 of the weight is not enough
+        let err = adapter
+            .verify_proof_weight(Context::new(), 1, mock_weights(3), mock_voters(2))
+            .unwrap_err();
+        assert!(err.to_string().contains("Weight"));
+        adapter
+            .verify_proof_weight(Context::new(), 1, mock_weights(3), mock_voters(3))
+            .unwrap();
+        adapter
+            .verify_proof_weight(Context::new(), 1, mock_weights(4), mock_voters(3))
+            .unwrap();
+
+        let adapter = adapter.vote_threshold(Some((3, 4))).unwrap();
+        assert!(adapter
+            .verify_proof_weight(Context::new(), 1, mock_weights(4), mock_voters(3))
+            .is_err());
+        adapter
+            .verify_proof_weight(Context::new(), 1, mock_weights(4), mock_voters(4))
+            .unwrap();
+
+        for threshold in [(1, 2), (5, 4), (0, 0)] {
+            let err = mock_adapter()
+                .vote_threshold(Some(threshold))
+                .err()
+                .unwrap();
+            assert!(err.to_string().contains("InvalidVoteThreshold"));
+        }
+    }
+
+    fn mock_proofs(count: u64) -> Vec<(Block, Proof)> {
+        (1..=count)
+            .map(|number| {
+                let mut block = Block::default();
+                block.header.number = number;
+                let proof = Proof {
+                    number,
+                    block_hash: block.proposal_hash().unwrap(),
+                    ..Default::default()
+                };
+                (block, proof)
+            })
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_proof_concurrency() {
+        let metadata = MockMetadata {
+            delay: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let adapter = Arc::new(
+            mock_adapter_with_metadata(metadata)
+                .sync_proof_concurrency(Some(4))
+                .live_proof_concurrency(Some(1)),
+        );
+        assert_eq!(adapter.proof_concurrency(), 1);
+
+        // The default metadata doesn't cover the blocks, so every proof fails
+        // right after the probe
+        let res = adapter.verify_proofs(Context::new(), mock_proofs(8)).await;
+        assert!(res.is_err());
+        assert_eq!(adapter.metadata.peak.load(Ordering::SeqCst), 1);
+
+        adapter.set_sync_mode(true);
+        assert_eq!(adapter.proof_concurrency(), 4);
+        let res = adapter.verify_proofs(Context::new(), mock_proofs(8)).await;
+        assert!(res.is_err());
+        let peak = adapter.metadata.peak.load(Ordering::SeqCst);
+        assert!(peak > 1 && peak <= 4);
+
+        adapter.set_sync_mode(false);
+        assert_eq!(adapter.proof_concurrency(), 1);
+    }
 }
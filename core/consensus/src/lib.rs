@@ -19,10 +19,10 @@ use std::error::Error;
 
 use common_crypto::Error as CryptoError;
 
-use protocol::types::{ExitReason, Hash, MerkleRoot};
+use protocol::types::{ExitReason, Hash, MerkleRoot, H160};
 use protocol::{Display, ProtocolError, ProtocolErrorKind};
 
-pub use crate::adapter::OverlordConsensusAdapter;
+pub use crate::adapter::{ExecPanicPolicy, OverlordConsensusAdapter};
 pub use crate::consensus::OverlordConsensus;
 pub use crate::synchronization::{OverlordSynchronization, RichBlock, SyncStatus, SYNC_STATUS};
 pub use crate::wal::{ConsensusWal, SignedTxsWAL};
@@ -68,22 +68,6 @@ pub enum ConsensusError {
         actual: MerkleRoot,
     },
 
-    #[display(fmt = "Check invalid state root, expect {:?} get {:?}", expect, actual)]
-    InvalidStateRoot {
-        expect: MerkleRoot,
-        actual: MerkleRoot,
-    },
-
-    #[display(
-        fmt = "Check invalid receipts root, expect {:?} get {:?}",
-        expect,
-        actual
-    )]
-    InvalidReceiptsRoot {
-        expect: MerkleRoot,
-        actual: MerkleRoot,
-    },
-
     #[display(
         fmt = "Check invalid order signed transactions hash, expect {:?} get {:?}",
         expect,
@@ -130,9 +114,27 @@ pub enum ConsensusError {
     #[display(fmt = "Crypto error {:?}", _0)]
     CryptoErr(Box<CryptoError>),
 
+    #[display(fmt = "Execution of block {} panicked", number)]
+    ExecutionPanic { number: u64 },
+
+    #[display(fmt = "Validator {:?} has an invalid bls public key: {}", address, err)]
+    InvalidBlsPubKey { address: H160, err: String },
+
+    #[display(fmt = "Vote threshold {}/{} is not in (1/2, 1]", _0, _1)]
+    InvalidVoteThreshold(u64, u64),
+
+    #[display(fmt = "Rpc {} for block {} timed out", end, number)]
+    RpcTimeout { end: String, number: u64 },
+
     #[display(fmt = "Synchronization {} block error", _0)]
     VerifyTransaction(u64),
 
+    #[display(fmt = "Block {} contains duplicate tx {:?}", _0, _1)]
+    DuplicateTx(u64, Hash),
+
+    #[display(fmt = "Block {} tx {:?} is neither in mempool nor storage", _0, _1)]
+    MissingTx(u64, Hash),
+
     #[display(fmt = "Synchronization/Consensus {} block error : {}", _0, _1)]
     VerifyBlockHeader(u64, BlockHeaderField),
 
@@ -217,6 +219,20 @@ pub enum BlockProofField {
     #[display(fmt = "Hash of block and proof diverse")]
     HashMismatch,
 
+    #[display(
+        fmt = "State roots of block and execution diverse, block {:?}, exec {:?}",
+        _0,
+        _1
+    )]
+    StateRootMismatch(MerkleRoot, MerkleRoot),
+
+    #[display(
+        fmt = "Receipts roots of block and execution diverse, block {:?}, exec {:?}",
+        _0,
+        _1
+    )]
+    ReceiptsRootMismatch(MerkleRoot, MerkleRoot),
+
     #[display(fmt = "There is at least one validator not in the committee")]
     Validator,
 
@@ -11,7 +11,7 @@ use protocol::types::{Block, Proof, Proposal, Receipt, SignedTransaction, U256};
 use protocol::{async_trait, ProtocolResult};
 
 use crate::status::{CurrentStatus, StatusAgent};
-use crate::util::digest_signed_transactions;
+use crate::util::{digest_signed_transactions, verify_block_roots};
 use crate::{engine::generate_receipts_and_logs, ConsensusError};
 
 const POLLING_BROADCAST: u64 = 2000;
@@ -67,6 +67,7 @@ impl<Adapter: SynchronizationAdapter> Synchronization for OverlordSynchronizatio
         );
 
         let sync_status_agent = self.init_status_agent().await?;
+        self.adapter.set_sync_mode(true);
         let sync_resp = self
             .start_sync(
                 ctx.clone(),
@@ -75,6 +76,7 @@ impl<Adapter: SynchronizationAdapter> Synchronization for OverlordSynchronizatio
                 remote_number,
             )
             .await;
+        self.adapter.set_sync_mode(false);
         let sync_status = sync_status_agent.inner();
 
         if let Err(e) = sync_resp {
@@ -227,57 +229,46 @@ impl<Adapter: SynchronizationAdapter> OverlordSynchronization<Adapter> {
                 e
             })?;
 
-        self.adapter
-            .verify_proof(
-                ctx.clone(),
-                consenting_rich_block.block.clone(),
-                consenting_proof.clone(),
-            )
+        let previous_block = self
+            .adapter
+            .get_block_by_number(ctx.clone(), consenting_rich_block.block.header.number - 1)
             .await
             .map_err(|e| {
                 log::error!(
-                    "[synchronization]: verify_proof error, syncing block header: {:?}, proof: {:?}",
-                    consenting_rich_block.block.header,
-                    consenting_proof,
+                    "[synchronization] get previous block {} error",
+                    consenting_rich_block.block.header.number - 1
                 );
                 e
             })?;
 
+        // The proof of the syncing block and the one it carries of the
+        // previous block
         self.adapter
-            .verify_block_header(ctx.clone(), &proposal)
+            .verify_proofs(ctx.clone(), vec![
+                (consenting_rich_block.block.clone(), consenting_proof.clone()),
+                (
+                    previous_block.clone(),
+                    consenting_rich_block.block.header.proof.clone(),
+                ),
+            ])
             .await
             .map_err(|e| {
                 log::error!(
-                    "[synchronization]: verify_block_header error, block header: {:?}",
-                    consenting_rich_block.block.header
-                );
-                e
-            })?;
-
-        let previous_block = self
-            .adapter
-            .get_block_by_number(ctx.clone(), consenting_rich_block.block.header.number - 1)
-            .await
-            .map_err(|e| {
-                log::error!(
-                    "[synchronization] get previous block {} error",
-                    consenting_rich_block.block.header.number - 1
+                    "[synchronization]: verify_proofs error, syncing block header: {:?}, proof: {:?}, previous block header: {:?}",
+                    consenting_rich_block.block.header,
+                    consenting_proof,
+                    previous_block.header,
                 );
                 e
             })?;
 
         self.adapter
-            .verify_proof(
-                ctx.clone(),
-                previous_block.clone(),
-                consenting_rich_block.block.header.proof.clone(),
-            )
+            .verify_block_header(ctx.clone(), &proposal)
             .await
             .map_err(|e| {
                 log::error!(
-                    "[synchronization]: verify_proof error, previous block header: {:?}, proof: {:?}",
-                    previous_block.header,
-                    consenting_rich_block.block.header.proof
+                    "[synchronization]: verify_block_header error, block header: {:?}",
+                    consenting_rich_block.block.header
                 );
                 e
             })?;
@@ -318,21 +309,7 @@ impl<Adapter: SynchronizationAdapter> OverlordSynchronization<Adapter> {
             )
             .await?;
 
-        if resp.state_root != block.header.state_root {
-            return Err(ConsensusError::InvalidStateRoot {
-                expect: block.header.state_root,
-                actual: resp.state_root,
-            }
-            .into());
-        }
-
-        if resp.receipt_root != block.header.receipts_root {
-            return Err(ConsensusError::InvalidReceiptsRoot {
-                expect: block.header.receipts_root,
-                actual: resp.receipt_root,
-            }
-            .into());
-        }
+        verify_block_roots(&block.header, &resp)?;
 
         let (receipts, _logs) = generate_receipts_and_logs(
             block.header.number,
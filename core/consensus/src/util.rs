@@ -6,18 +6,42 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use overlord::Crypto;
 use parking_lot::RwLock;
 
+use crate::BlockProofField::{ReceiptsRootMismatch, StateRootMismatch};
 use crate::ConsensusError;
 use common_crypto::{
     BlsPrivateKey, BlsPublicKey, BlsSignature, BlsSignatureVerify, HashValue, PrivateKey, Signature,
 };
 use protocol::traits::Context;
-use protocol::types::{Address, Bytes, Hash, Hasher, Hex, MerkleRoot, SignedTransaction};
+use protocol::types::{
+    Address, Bytes, ExecResp, Hash, Hasher, Header, Hex, MerkleRoot, SignedTransaction,
+};
 use protocol::{ProtocolError, ProtocolResult};
 
 pub fn digest_signed_transactions(stxs: &[SignedTransaction]) -> Hash {
     Hasher::digest(rlp::encode_list(stxs))
 }
 
+// The proof doesn't commit to the roots, only executing the block tells them.
+pub fn verify_block_roots(header: &Header, resp: &ExecResp) -> ProtocolResult<()> {
+    if header.state_root != resp.state_root {
+        return Err(ConsensusError::VerifyProof(
+            header.number,
+            StateRootMismatch(header.state_root, resp.state_root),
+        )
+        .into());
+    }
+
+    if header.receipts_root != resp.receipt_root {
+        return Err(ConsensusError::VerifyProof(
+            header.number,
+            ReceiptsRootMismatch(header.receipts_root, resp.receipt_root),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
 pub fn time_now() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -251,4 +275,32 @@ mod tests {
             convert_hex_to_bls_pubkeys(Hex::from_string(String::from(hex_str)).unwrap()).is_ok()
         );
     }
+
+    #[test]
+    fn test_verify_block_roots() {
+        let resp = ExecResp {
+            state_root:   Hasher::digest(Bytes::from_static(b"state")),
+            receipt_root: Hasher::digest(Bytes::from_static(b"receipts")),
+            gas_used:     0,
+            tx_resp:      vec![],
+        };
+        let mut header = Header {
+            number: 10,
+            state_root: resp.state_root,
+            receipts_root: resp.receipt_root,
+            ..Default::default()
+        };
+        verify_block_roots(&header, &resp).unwrap();
+
+        header.state_root = Hasher::digest(Bytes::from_static(b"tampered"));
+        let err = verify_block_roots(&header, &resp).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("State roots of block and execution diverse"));
+
+        header.state_root = resp.state_root;
+        header.receipts_root = Hasher::digest(Bytes::from_static(b"tampered"));
+        let err = verify_block_roots(&header, &resp).unwrap_err();
+        assert!(err.to_string().contains("ReceiptsRootMismatch"));
+    }
 }
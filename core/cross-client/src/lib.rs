@@ -4,14 +4,101 @@ mod adapter;
 
 pub use adapter::DefaultCrossAdapter;
 
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
-use protocol::async_trait;
+use ethabi::{ParamType, Token};
+
+use protocol::tokio::{self, task::JoinHandle};
 use protocol::traits::{Context, CrossAdapter, CrossClient};
-use protocol::types::{Block, BlockNumber, Hash, Log, Proof};
+use protocol::types::{Block, BlockNumber, Hash, Log, Proof, H160, H256};
+use protocol::{async_trait, Display, ProtocolError, ProtocolErrorKind, ProtocolResult};
+
+pub const DEFAULT_PAUSE_BUFFER_SIZE: usize = 1024;
+pub const DEFAULT_WATCH_BACKOFF: Duration = Duration::from_secs(1);
+pub const DEFAULT_MAX_WATCH_BACKOFF: Duration = Duration::from_secs(60);
+
+/// What to do with the logs and checkpoints arriving while relaying is paused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PausePolicy {
+    /// Keep at most this many items and relay them on resume, the items
+    /// beyond are dropped.
+    Buffer(usize),
+    Drop,
+}
+
+#[derive(Debug)]
+enum RelayItem {
+    EvmLog {
+        block_number: BlockNumber,
+        block_hash:   Hash,
+        logs:         Vec<Vec<Log>>,
+    },
+    Checkpoint {
+        block: Block,
+        proof: Proof,
+    },
+}
+
+impl RelayItem {
+    fn block_number(&self) -> BlockNumber {
+        match self {
+            RelayItem::EvmLog { block_number, .. } => *block_number,
+            RelayItem::Checkpoint { block, .. } => block.header.number,
+        }
+    }
+}
+
+#[derive(Default)]
+struct RelayState {
+    paused: bool,
+    buffer: VecDeque<RelayItem>,
+
+    latest_number: BlockNumber,
+    unconfirmed:   VecDeque<RelayItem>,
+}
+
+// The data of a cross-chain log, encoded as `(uint256 amount, bytes
+// recipient)`.
+#[derive(Debug)]
+struct CrossChainPayload {
+    amount:    ethabi::Uint,
+    // The CKB address of the recipient
+    recipient: Vec<u8>,
+}
+
+impl CrossChainPayload {
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut tokens = ethabi::decode(&[ParamType::Uint(256), ParamType::Bytes], data)
+            .ok()?
+            .into_iter();
+        match (tokens.next(), tokens.next()) {
+            (Some(Token::Uint(amount)), Some(Token::Bytes(recipient))) => {
+                Some(CrossChainPayload { amount, recipient })
+            }
+            _ => None,
+        }
+    }
+}
 
 pub struct CrossChainImpl<Adapter> {
-    adapter: Arc<Adapter>,
+    adapter:  Arc<Adapter>,
+    // Only the logs of this contract with this first topic go to CKB
+    contract: H160,
+    topic:    H256,
+
+    pause_policy:       PausePolicy,
+    confirmation_depth: u64,
+    state:              Mutex<RelayState>,
+    latest_checkpoint:  RwLock<Option<(Block, Proof)>>,
+
+    // Wait this long before watching CKB again after a failure, doubled on
+    // every failure in a row up to the max
+    watch_backoff:     Duration,
+    max_watch_backoff: Duration,
+    watcher:           Mutex<Option<JoinHandle<()>>>,
 }
 
 #[async_trait]
@@ -23,13 +110,472 @@ impl<Adapter: CrossAdapter + 'static> CrossClient for CrossChainImpl<Adapter> {
         block_hash: Hash,
         logs: &[Vec<Log>],
     ) {
+        let item = RelayItem::EvmLog {
+            block_number,
+            block_hash,
+            logs: logs.to_vec(),
+        };
+
+        self.accept(ctx, item).await
     }
 
-    async fn set_checkpoint(&self, ctx: Context, block: Block, proof: Proof) {}
+    async fn set_checkpoint(&self, ctx: Context, block: Block, proof: Proof) {
+        if let Err(e) = verify_checkpoint(&block, &proof) {
+            log::error!("[cross client]: reject checkpoint {:?}", e);
+            return;
+        }
+
+        {
+            let mut latest = self.latest_checkpoint.write().unwrap();
+            if latest.as_ref().map_or(true, |(latest, _)| {
+                latest.header.number <= block.header.number
+            }) {
+                *latest = Some((block.clone(), proof.clone()));
+            }
+        }
+
+        self.accept(ctx, RelayItem::Checkpoint { block, proof })
+            .await
+    }
 }
 
 impl<Adapter: CrossAdapter + 'static> CrossChainImpl<Adapter> {
-    pub fn new(adapter: Arc<Adapter>) -> Self {
-        CrossChainImpl { adapter }
+    pub fn new(adapter: Arc<Adapter>, contract: H160, topic: H256) -> Self {
+        CrossChainImpl {
+            adapter,
+            contract,
+            topic,
+            pause_policy: PausePolicy::Buffer(DEFAULT_PAUSE_BUFFER_SIZE),
+            confirmation_depth: 0,
+            state: Mutex::new(RelayState::default()),
+            latest_checkpoint: RwLock::new(None),
+            watch_backoff: DEFAULT_WATCH_BACKOFF,
+            max_watch_backoff: DEFAULT_MAX_WATCH_BACKOFF,
+            watcher: Mutex::new(None),
+        }
+    }
+
+    pub fn pause_policy(mut self, policy: PausePolicy) -> Self {
+        self.pause_policy = policy;
+        self
+    }
+
+    /// Hold the items of a block until `depth` newer blocks have arrived. The
+    /// default 0 relays every item as soon as it arrives.
+    pub fn confirmation_depth(mut self, depth: u64) -> Self {
+        self.confirmation_depth = depth;
+        self
+    }
+
+    pub fn watch_backoff(mut self, backoff: Duration, max: Duration) -> Self {
+        self.watch_backoff = backoff;
+        self.max_watch_backoff = max.max(backoff);
+        self
+    }
+
+    /// Spawn a task watching CKB, which retries with an exponential backoff
+    /// when the watch fails and ends once it returns `Ok`. It replaces the
+    /// previous task if any, the task is aborted when the client is dropped.
+    pub fn run(&self, ctx: Context) {
+        let adapter = Arc::clone(&self.adapter);
+        let (mut backoff, max) = (self.watch_backoff, self.max_watch_backoff);
+
+        let handle = tokio::spawn(async move {
+            loop {
+                match adapter.watch_ckb_client(ctx.clone()).await {
+                    Ok(()) => return,
+                    Err(e) => log::warn!(
+                        "[cross client]: watch ckb failed {:?}, retry in {:?}",
+                        e,
+                        backoff
+                    ),
+                }
+
+                tokio::time::sleep(backoff).await;
+                backoff = backoff.saturating_mul(2).min(max);
+            }
+        });
+
+        if let Some(old) = self.watcher.lock().unwrap().replace(handle) {
+            old.abort();
+        }
+    }
+
+    /// The checkpoint of the highest block accepted so far.
+    pub fn latest_checkpoint(&self) -> Option<(Block, Proof)> {
+        self.latest_checkpoint.read().unwrap().clone()
+    }
+
+    pub fn pause(&self) {
+        self.state.lock().unwrap().paused = true;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Relay the buffered items in arrival order, then leave the paused state.
+    /// Items arriving during the drain are buffered behind so that the order
+    /// is kept.
+    pub async fn resume(&self) {
+        loop {
+            let item = {
+                let mut state = self.state.lock().unwrap();
+                match state.buffer.pop_front() {
+                    Some(item) => item,
+                    None => {
+                        state.paused = false;
+                        return;
+                    }
+                }
+            };
+
+            self.relay(Context::new(), item).await;
+        }
+    }
+
+    async fn accept(&self, ctx: Context, item: RelayItem) {
+        for item in self.confirm(item) {
+            if let Some(item) = self.hold_if_paused(item) {
+                self.relay(ctx.clone(), item).await;
+            }
+        }
+    }
+
+    // Return the items buried under enough blocks, in arrival order.
+    fn confirm(&self, item: RelayItem) -> Vec<RelayItem> {
+        let mut state = self.state.lock().unwrap();
+        state.latest_number = state.latest_number.max(item.block_number());
+        state.unconfirmed.push_back(item);
+
+        let latest = state.latest_number;
+        let depth = self.confirmation_depth;
+        let (confirmed, unconfirmed): (VecDeque<_>, VecDeque<_>) = state
+            .unconfirmed
+            .drain(..)
+            .partition(|item| item.block_number().saturating_add(depth) <= latest);
+        state.unconfirmed = unconfirmed;
+
+        confirmed.into_iter().collect()
+    }
+
+    // Return the item back if it should be relayed right now.
+    fn hold_if_paused(&self, item: RelayItem) -> Option<RelayItem> {
+        let mut state = self.state.lock().unwrap();
+        if !state.paused {
+            return Some(item);
+        }
+
+        match self.pause_policy {
+            PausePolicy::Buffer(size) if state.buffer.len() < size => state.buffer.push_back(item),
+            _ => log::warn!("[cross client]: relaying paused, drop {:?}", item),
+        }
+
+        None
+    }
+
+    async fn relay(&self, ctx: Context, item: RelayItem) {
+        log::debug!("[cross client]: relay {:?}", item);
+
+        let logs = match item {
+            RelayItem::EvmLog { logs, .. } => logs,
+            RelayItem::Checkpoint { .. } => return self.send_ckb_tx(ctx).await,
+        };
+
+        for log in logs.iter().flatten().filter(|log| self.is_cross_chain(log)) {
+            match CrossChainPayload::decode(&log.data) {
+                Some(payload) => {
+                    log::debug!("[cross client]: cross chain {:?}", payload);
+                    self.send_ckb_tx(ctx.clone()).await;
+                }
+                None => log::warn!("[cross client]: undecodable cross chain log {:?}", log),
+            }
+        }
+    }
+
+    fn is_cross_chain(&self, log: &Log) -> bool {
+        log.address == self.contract && log.topics.first() == Some(&self.topic)
+    }
+
+    async fn send_ckb_tx(&self, ctx: Context) {
+        if let Err(e) = self.adapter.send_ckb_tx(ctx).await {
+            log::error!("[cross client]: relay failed {:?}", e);
+        }
+    }
+}
+
+impl<Adapter> Drop for CrossChainImpl<Adapter> {
+    fn drop(&mut self) {
+        if let Some(watcher) = self.watcher.get_mut().unwrap().take() {
+            watcher.abort();
+        }
+    }
+}
+
+// The same number and hash checks as the consensus does on a proof, the genesis
+// block has no proof.
+fn verify_checkpoint(block: &Block, proof: &Proof) -> ProtocolResult<()> {
+    let number = block.header.number;
+    if number == 0 {
+        return Ok(());
+    }
+
+    if number != proof.number {
+        return Err(CrossChainError::ProofNumberMismatch {
+            block: number,
+            proof: proof.number,
+        }
+        .into());
+    }
+
+    let proposal_hash = block.proposal_hash()?;
+    if proposal_hash != proof.block_hash {
+        return Err(CrossChainError::ProofHashMismatch(number).into());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Display)]
+pub enum CrossChainError {
+    #[display(fmt = "Proof number {} mismatches block {}", proof, block)]
+    ProofNumberMismatch { block: u64, proof: u64 },
+
+    #[display(fmt = "Proof hash mismatches block {}", _0)]
+    ProofHashMismatch(u64),
+}
+
+impl Error for CrossChainError {}
+
+impl From<CrossChainError> for ProtocolError {
+    fn from(error: CrossChainError) -> ProtocolError {
+        ProtocolError::new(ProtocolErrorKind::CrossClient, Box::new(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use protocol::tokio;
+    use protocol::types::SignedTransaction;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MockCrossAdapter {
+        sent: AtomicUsize,
+
+        // The watches fail until this many calls were made
+        watch_failures: usize,
+        watch_calls:    AtomicUsize,
+    }
+
+    #[async_trait]
+    impl CrossAdapter for MockCrossAdapter {
+        async fn watch_ckb_client(&self, _ctx: Context) -> ProtocolResult<()> {
+            let calls = self.watch_calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if calls <= self.watch_failures {
+                let e = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+                return Err(ProtocolError::new(
+                    ProtocolErrorKind::CrossClient,
+                    Box::new(e),
+                ));
+            }
+            Ok(())
+        }
+
+        async fn send_axon_tx(&self, _ctx: Context, _stx: SignedTransaction) -> ProtocolResult<()> {
+            Ok(())
+        }
+
+        async fn send_ckb_tx(&self, _ctx: Context) -> ProtocolResult<()> {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn mock_client<A: CrossAdapter + 'static>(adapter: Arc<A>) -> CrossChainImpl<A> {
+        CrossChainImpl::new(adapter, H160::from_low_u64_be(1), H256::from_low_u64_be(2))
+    }
+
+    fn cross_chain_log() -> Log {
+        Log {
+            address: H160::from_low_u64_be(1),
+            topics:  vec![H256::from_low_u64_be(2), H256::from_low_u64_be(3)],
+            data:    ethabi::encode(&[
+                Token::Uint(100u64.into()),
+                Token::Bytes(b"ckt1qyq".to_vec()),
+            ]),
+        }
+    }
+
+    fn mock_checkpoint(number: u64) -> (Block, Proof) {
+        let mut block = Block::default();
+        block.header.number = number;
+        let proof = Proof {
+            number,
+            block_hash: block.proposal_hash().unwrap(),
+            ..Default::default()
+        };
+        (block, proof)
+    }
+
+    async fn feed<A: CrossAdapter + 'static>(client: &CrossChainImpl<A>, count: u64) {
+        for number in 0..count {
+            client
+                .set_evm_log(Context::new(), number, Hash::default(), &[vec![
+                    cross_chain_log(),
+                ]])
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_buffer() {
+        let adapter = Arc::new(MockCrossAdapter::default());
+        let client = mock_client(Arc::clone(&adapter)).pause_policy(PausePolicy::Buffer(2));
+
+        feed(&client, 1).await;
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 1);
+
+        client.pause();
+        feed(&client, 3).await;
+        client
+            .set_checkpoint(Context::new(), Block::default(), Proof::default())
+            .await;
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 1);
+
+        client.resume().await;
+        assert!(!client.is_paused());
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 3);
+
+        feed(&client, 1).await;
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_confirmation_depth() {
+        let adapter = Arc::new(MockCrossAdapter::default());
+        let client = mock_client(Arc::clone(&adapter)).confirmation_depth(3);
+
+        for number in 10..13 {
+            client
+                .set_evm_log(Context::new(), number, Hash::default(), &[vec![
+                    cross_chain_log(),
+                ]])
+                .await;
+        }
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 0);
+
+        let (block, proof) = mock_checkpoint(13);
+        client.set_checkpoint(Context::new(), block, proof).await;
+        // Only block 10 is buried deep enough
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 1);
+
+        client
+            .set_evm_log(Context::new(), 15, Hash::default(), &[vec![
+                cross_chain_log(),
+            ]])
+            .await;
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cross_chain_filter() {
+        let adapter = Arc::new(MockCrossAdapter::default());
+        let client = mock_client(Arc::clone(&adapter));
+
+        let mut other_contract = cross_chain_log();
+        other_contract.address = H160::from_low_u64_be(9);
+        let mut other_topic = cross_chain_log();
+        other_topic.topics = vec![H256::from_low_u64_be(3)];
+        let mut undecodable = cross_chain_log();
+        undecodable.data = vec![1, 2, 3];
+        client
+            .set_evm_log(Context::new(), 0, Hash::default(), &[
+                vec![other_contract, other_topic],
+                vec![undecodable],
+            ])
+            .await;
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 0);
+
+        client
+            .set_evm_log(Context::new(), 1, Hash::default(), &[vec![
+                cross_chain_log(),
+            ]])
+            .await;
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_set_checkpoint() {
+        let adapter = Arc::new(MockCrossAdapter::default());
+        let client = mock_client(Arc::clone(&adapter));
+        let (block, proof) = mock_checkpoint(1);
+
+        client
+            .set_checkpoint(Context::new(), block.clone(), proof.clone())
+            .await;
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            client.latest_checkpoint(),
+            Some((block.clone(), proof.clone()))
+        );
+
+        let mut next = block.clone();
+        next.header.number = 2;
+        let wrong_number = Proof {
+            number: 3,
+            ..proof.clone()
+        };
+        client
+            .set_checkpoint(Context::new(), next.clone(), wrong_number)
+            .await;
+        // The hash is of block 1
+        let wrong_hash = Proof {
+            number: 2,
+            ..proof.clone()
+        };
+        client
+            .set_checkpoint(Context::new(), next, wrong_hash)
+            .await;
+
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 1);
+        assert_eq!(client.latest_checkpoint(), Some((block, proof)));
+    }
+
+    #[tokio::test]
+    async fn test_run_watch_retry() {
+        let adapter = Arc::new(MockCrossAdapter {
+            watch_failures: 2,
+            ..Default::default()
+        });
+        let client = mock_client(Arc::clone(&adapter))
+            .watch_backoff(Duration::from_millis(10), Duration::from_millis(15));
+
+        let start = std::time::Instant::now();
+        client.run(Context::new());
+        while adapter.watch_calls.load(Ordering::SeqCst) < 3 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        // Backed off 10ms then the capped 15ms
+        assert!(start.elapsed() >= Duration::from_millis(25));
+
+        // The successful watch ends the task
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(adapter.watch_calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_pause_drop() {
+        let adapter = Arc::new(MockCrossAdapter::default());
+        let client = mock_client(Arc::clone(&adapter)).pause_policy(PausePolicy::Drop);
+
+        client.pause();
+        feed(&client, 3).await;
+        client.resume().await;
+        assert_eq!(adapter.sent.load(Ordering::SeqCst), 0);
     }
 }
@@ -2,7 +2,7 @@ mod trie;
 mod trie_db;
 
 pub use trie::MPTTrie;
-pub use trie_db::RocksTrieDB;
+pub use trie_db::{RocksTrieDB, RocksTrieDBConfig, TrieDbStats};
 
 use std::sync::Arc;
 
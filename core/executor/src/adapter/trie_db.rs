@@ -1,23 +1,104 @@
+#[cfg(test)]
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::{fs, io};
 
-use dashmap::DashMap;
-use rand::{rngs::SmallRng, Rng, SeedableRng};
-use rocksdb::ops::{Get, Open, Put, WriteOps};
-use rocksdb::{Options, WriteBatch, DB};
+use dashmap::{DashMap, DashSet};
+use rocksdb::ops::{Delete, Get, GetProperty, Iterate, Open, Put, WriteOps};
+use rocksdb::{BlockBasedOptions, Cache, DBCompactionStyle, Options, WriteBatch, WriteOptions, DB};
 
-use common_apm::metrics::storage::{on_storage_get_state, on_storage_put_state};
+use common_apm::metrics::storage::{
+    on_storage_get_state, on_storage_put_state, on_storage_state_cache,
+};
 use common_apm::Instant;
 use protocol::{types::Bytes, Display, From, ProtocolError, ProtocolErrorKind, ProtocolResult};
 
-// 49999 is the largest prime number within 50000.
-const RAND_SEED: u64 = 49999;
+// The default number of levels of rocksdb.
+const NUM_LEVELS: usize = 7;
+// The number of cache entries `flush` reads the ticks of to pick the entries
+// to evict, a larger cache is evicted approximately.
+const EVICTION_SAMPLE: usize = 4096;
+
+/// The rocksdb tuning of the trie db. The trie nodes are small values under
+/// random keys, which favors larger memtables and block cache than the
+/// rocksdb defaults.
+#[derive(Clone, Debug)]
+pub struct RocksTrieDBConfig {
+    pub write_buffer_size:       usize,
+    pub max_write_buffer_number: i32,
+    // Keep the rocksdb default block cache if 0
+    pub block_cache_size:        usize,
+    pub compaction_style:        DBCompactionStyle,
+}
+
+impl Default for RocksTrieDBConfig {
+    fn default() -> Self {
+        RocksTrieDBConfig {
+            write_buffer_size:       64 * 1024 * 1024,
+            max_write_buffer_number: 4,
+            block_cache_size:        128 * 1024 * 1024,
+            compaction_style:        DBCompactionStyle::Level,
+        }
+    }
+}
+
+/// A snapshot of the rocksdb properties of the trie db. A property is `None`
+/// if rocksdb fails to report it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrieDbStats {
+    pub estimate_num_keys:       Option<u64>,
+    pub total_sst_files_size:    Option<u64>,
+    pub estimate_live_data_size: Option<u64>,
+    pub num_sst_files:           Option<u64>,
+    pub cache_len:               usize,
+}
 
 pub struct RocksTrieDB {
-    db:         Arc<DB>,
-    cache:      DashMap<Vec<u8>, Vec<u8>>,
-    cache_size: usize,
+    db:           Arc<DB>,
+    cache:        DashMap<Vec<u8>, CacheEntry>,
+    cache_size:   usize,
+    // Bound the summed key and value lengths of the cache rather than the
+    // entry count if set
+    byte_budget:  Option<usize>,
+    cache_bytes:  AtomicUsize,
+    // Ticks on every cache access, `flush` evicts the entries touched least
+    // recently
+    clock:        AtomicU64,
+    cache_hits:   AtomicU64,
+    cache_misses: AtomicU64,
+
+    // Fsync the WAL every `sync_interval` writes, the counter counts the
+    // inserts and the removes, batched or not
+    sync_interval: Option<u64>,
+    write_count:   AtomicU64,
+
+    // Delete the removed trie nodes, they are kept forever otherwise
+    pruning: bool,
+
+    // Keep the inserts in the cache until `commit` if set, the dirty keys are
+    // never evicted
+    buffered: bool,
+    dirty:    DashSet<Vec<u8>>,
+}
+
+struct CacheEntry {
+    value:   Vec<u8>,
+    touched: AtomicU64,
+}
+
+impl CacheEntry {
+    fn new(value: Vec<u8>, tick: u64) -> Self {
+        CacheEntry {
+            value,
+            touched: AtomicU64::new(tick),
+        }
+    }
+
+    fn touch(&self, tick: u64) {
+        self.touched.store(tick, Ordering::Relaxed);
+    }
 }
 
 impl RocksTrieDB {
@@ -25,6 +106,20 @@ impl RocksTrieDB {
         path: P,
         max_open_files: i32,
         cache_size: usize,
+    ) -> ProtocolResult<Self> {
+        Self::with_config(
+            path,
+            max_open_files,
+            cache_size,
+            RocksTrieDBConfig::default(),
+        )
+    }
+
+    pub fn with_config<P: AsRef<Path>>(
+        path: P,
+        max_open_files: i32,
+        cache_size: usize,
+        config: RocksTrieDBConfig,
     ) -> ProtocolResult<Self> {
         if !path.as_ref().is_dir() {
             fs::create_dir_all(&path).map_err(RocksTrieDBError::CreateDB)?;
@@ -34,6 +129,17 @@ impl RocksTrieDB {
         opts.create_if_missing(true);
         opts.create_missing_column_families(true);
         opts.set_max_open_files(max_open_files);
+        opts.set_write_buffer_size(config.write_buffer_size);
+        opts.set_max_write_buffer_number(config.max_write_buffer_number);
+        opts.set_compaction_style(config.compaction_style);
+
+        if config.block_cache_size > 0 {
+            let cache =
+                Cache::new_lru_cache(config.block_cache_size).map_err(RocksTrieDBError::from)?;
+            let mut block_opts = BlockBasedOptions::default();
+            block_opts.set_block_cache(&cache);
+            opts.set_block_based_table_factory(&block_opts);
+        }
 
         let db = DB::open(&opts, path).map_err(RocksTrieDBError::from)?;
 
@@ -42,36 +148,345 @@ impl RocksTrieDB {
             db: Arc::new(db),
             cache: DashMap::with_capacity(cache_size + cache_size),
             cache_size,
+            byte_budget: None,
+            cache_bytes: AtomicUsize::new(0),
+            clock: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            sync_interval: None,
+            write_count: AtomicU64::new(0),
+            pruning: false,
+            buffered: false,
+            dirty: DashSet::new(),
         })
     }
 
+    /// Bound the cache by the summed length of its keys and values instead
+    /// of the entry count.
+    pub fn with_byte_budget<P: AsRef<Path>>(
+        path: P,
+        max_open_files: i32,
+        max_bytes: usize,
+    ) -> ProtocolResult<Self> {
+        let mut trie = Self::new(path, max_open_files, 0)?;
+        trie.byte_budget = Some(max_bytes);
+        Ok(trie)
+    }
+
+    pub fn sync_interval(mut self, interval: Option<u64>) -> Self {
+        self.sync_interval = interval.filter(|n| *n > 0);
+        self
+    }
+
+    /// Make `remove` and `remove_batch` delete the keys from both the cache
+    /// and the db. It is disabled by default which keeps the db archival.
+    ///
+    /// The trie nodes are shared between the roots and not refcounted, so a
+    /// node removed from one root may still be referred by another. Do not
+    /// enable it on a db holding the state of a chain.
+    pub fn enable_pruning(mut self, enable: bool) -> Self {
+        self.pruning = enable;
+        self
+    }
+
+    /// Make `insert` and `insert_batch` only write the cache, the entries
+    /// reach the db on the next `commit`. It is disabled by default which
+    /// writes through.
+    ///
+    /// The buffered entries are lost if the node crashes before `commit`, so
+    /// it must run before anything referring to them, such as a state root in
+    /// a block, is persisted.
+    pub fn enable_buffered_writes(mut self, enable: bool) -> Self {
+        self.buffered = enable;
+        self
+    }
+
+    /// Write the buffered entries to the db in a single batch. The keys stay
+    /// dirty, hence in the cache, until the write succeeds, and a key
+    /// overwritten by an insert racing with the commit stays dirty.
+    pub fn commit(&self) -> Result<(), RocksTrieDBError> {
+        let keys = self
+            .dirty
+            .iter()
+            .map(|key| key.key().clone())
+            .collect::<Vec<_>>();
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let mut total_size = 0;
+        let mut batch = WriteBatch::default();
+        let mut written = Vec::with_capacity(keys.len());
+        for key in keys.into_iter() {
+            match self.cache.get(&key) {
+                Some(entry) => {
+                    total_size += key.len() + entry.value.len();
+                    batch.put(&key, &entry.value)?;
+                    let value = entry.value.clone();
+                    drop(entry);
+                    written.push((key, value));
+                }
+                // Removed by the pruning meanwhile
+                None => {
+                    self.dirty.remove(&key);
+                }
+            }
+        }
+
+        let inst = Instant::now();
+        self.db
+            .write_opt(&batch, &self.write_options())
+            .map_err(to_store_err)?;
+        on_storage_put_state(inst.elapsed(), total_size as f64);
+
+        // The entry is locked before the key, like in `flush`, so an insert
+        // either lands before the comparison or marks the key again after it
+        for (key, value) in written.into_iter() {
+            if let Some(entry) = self.cache.get(&key) {
+                if entry.value == value {
+                    self.dirty.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn db_stats(&self) -> TrieDbStats {
+        let num_sst_files = (0..NUM_LEVELS)
+            .map(|level| self.property_u64(&format!("rocksdb.num-files-at-level{}", level)))
+            .sum::<Option<u64>>();
+
+        TrieDbStats {
+            estimate_num_keys: self.property_u64("rocksdb.estimate-num-keys"),
+            total_sst_files_size: self.property_u64("rocksdb.total-sst-files-size"),
+            estimate_live_data_size: self.property_u64("rocksdb.estimate-live-data-size"),
+            num_sst_files,
+            cache_len: self.cache.len(),
+        }
+    }
+
+    // The `num-files-at-level<N>` properties are only available as strings.
+    fn property_u64(&self, name: &str) -> Option<u64> {
+        match self.db.property_value(name) {
+            Ok(val) => val.and_then(|v| v.trim().parse().ok()),
+            Err(e) => {
+                log::warn!("[executor] trie db property {} {:?}", name, e);
+                None
+            }
+        }
+    }
+
+    // A synced write flushes the WAL to disk, which makes all the previous
+    // writes durable as well.
+    fn write_options(&self) -> WriteOptions {
+        let mut opts = WriteOptions::default();
+
+        if let Some(interval) = self.sync_interval {
+            let count = self.write_count.fetch_add(1, Ordering::AcqRel) + 1;
+            if count % interval == 0 {
+                opts.set_sync(true);
+            }
+        }
+
+        opts
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// The numbers of reads served by the cache and of those that went to
+    /// the db, both `get` and `contains` count.
+    pub fn cache_stats(&self) -> (u64, u64) {
+        (
+            self.cache_hits.load(Ordering::Relaxed),
+            self.cache_misses.load(Ordering::Relaxed),
+        )
+    }
+
+    fn on_cache_read(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        on_storage_state_cache(hit);
+    }
+
+    // The size is counted before the entry is visible, so that the count
+    // never drops below the actual size.
+    fn cache_insert(&self, key: Vec<u8>, value: Vec<u8>) {
+        self.cache_bytes
+            .fetch_add(key.len() + value.len(), Ordering::AcqRel);
+        let old_len = key.len();
+        if let Some(old) = self.cache.insert(key, CacheEntry::new(value, self.tick())) {
+            self.cache_bytes
+                .fetch_sub(old_len + old.value.len(), Ordering::AcqRel);
+        }
+    }
+
+    fn cache_remove(&self, key: &[u8]) {
+        if let Some((key, entry)) = self.cache.remove(key) {
+            self.cache_bytes
+                .fetch_sub(key.len() + entry.value.len(), Ordering::AcqRel);
+        }
+    }
+
     fn inner_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, RocksTrieDBError> {
-        let res = self.cache.get(key);
+        if let Some(entry) = self.cache.get(key) {
+            entry.touch(self.tick());
+            self.on_cache_read(true);
+            return Ok(Some(entry.value.clone()));
+        }
+        self.on_cache_read(false);
+
+        let inst = Instant::now();
+        let ret = self.db.get(key).map_err(to_store_err)?.map(|r| r.to_vec());
+        on_storage_get_state(inst.elapsed(), 1.0);
+
+        if let Some(val) = &ret {
+            self.cache_insert(key.to_owned(), val.clone());
+        }
 
-        if res.is_none() {
-            let inst = Instant::now();
-            let ret = self.db.get(key).map_err(to_store_err)?.map(|r| r.to_vec());
-            on_storage_get_state(inst.elapsed(), 1.0);
+        Ok(ret)
+    }
 
-            if let Some(val) = &ret {
-                self.cache.insert(key.to_owned(), val.clone());
+    /// Read many keys at once, the results are in the order of `keys`. The
+    /// cache serves what it holds and the rest is read from the db in one
+    /// pass, then cached.
+    pub fn multi_get(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, RocksTrieDBError> {
+        let mut ret = Vec::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        for (idx, key) in keys.iter().enumerate() {
+            match self.cache.get(key) {
+                Some(entry) => {
+                    entry.touch(self.tick());
+                    self.on_cache_read(true);
+                    ret.push(Some(entry.value.clone()));
+                }
+                None => {
+                    self.on_cache_read(false);
+                    missing.push(idx);
+                    ret.push(None);
+                }
             }
+        }
 
+        if missing.is_empty() {
             return Ok(ret);
         }
 
-        Ok(Some(res.unwrap().clone()))
+        // The rocksdb binding has no multi get, the keys are read one by one
+        // but reported as a single batch.
+        let inst = Instant::now();
+        for idx in missing.iter() {
+            ret[*idx] = self
+                .db
+                .get(&keys[*idx])
+                .map_err(to_store_err)?
+                .map(|r| r.to_vec());
+        }
+        on_storage_get_state(inst.elapsed(), missing.len() as f64);
+
+        for idx in missing.into_iter() {
+            if let Some(val) = &ret[idx] {
+                self.cache_insert(keys[idx].clone(), val.clone());
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Load the keys from the db into the cache, such as the trie nodes
+    /// under the recent state roots after a restart. The cached keys are
+    /// left as they are, and the cache stats don't count the reads. Returns
+    /// the number of entries loaded.
+    pub fn warm_up(&self, keys: &[Vec<u8>]) -> Result<usize, RocksTrieDBError> {
+        let inst = Instant::now();
+        let mut loaded = 0;
+        for key in keys.iter() {
+            if self.cache.contains_key(key) {
+                continue;
+            }
+            if let Some(val) = self.db.get(key).map_err(to_store_err)? {
+                self.cache_insert(key.clone(), val.to_vec());
+                loaded += 1;
+            }
+        }
+        on_storage_get_state(inst.elapsed(), keys.len() as f64);
+
+        Ok(loaded)
     }
 
+    /// Load at most `limit` entries whose keys start with `prefix` into the
+    /// cache, in key order. Returns the number of entries loaded.
+    pub fn warm_up_prefix(&self, prefix: &[u8], limit: usize) -> usize {
+        let mut loaded = 0;
+        for (key, val) in self
+            .db
+            .prefix_iterator(prefix)
+            .take_while(|(key, _)| key.starts_with(prefix))
+        {
+            if loaded == limit {
+                break;
+            }
+            if self.cache.contains_key(key.as_ref()) {
+                continue;
+            }
+            self.cache_insert(key.to_vec(), val.to_vec());
+            loaded += 1;
+        }
+
+        loaded
+    }
+
+    /// Peek at the cache without touching the entry.
     #[cfg(test)]
     fn cache_get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        self.cache.get(key).map(|v| v.value().to_vec())
+        self.cache.get(key).map(|entry| entry.value.clone())
     }
 
     #[cfg(test)]
     fn cache_len(&self) -> usize {
         self.cache.len()
     }
+
+    #[cfg(test)]
+    fn cache_bytes(&self) -> usize {
+        self.cache_bytes.load(Ordering::Acquire)
+    }
+
+    #[cfg(test)]
+    fn dirty_len(&self) -> usize {
+        self.dirty.len()
+    }
+
+    #[cfg(test)]
+    fn write_count(&self) -> u64 {
+        self.write_count.load(Ordering::Acquire)
+    }
+
+    /// Capture the exact cache contents, which the eviction of `flush` would
+    /// otherwise make depend on the access history.
+    #[cfg(test)]
+    pub(crate) fn cache_snapshot(&self) -> HashMap<Vec<u8>, Vec<u8>> {
+        self.cache
+            .iter()
+            .map(|kv| (kv.key().clone(), kv.value().value.clone()))
+            .collect()
+    }
+
+    /// Replace the cache contents with the snapshot, the db is untouched. The
+    /// restored entries count as just touched.
+    #[cfg(test)]
+    pub(crate) fn cache_restore(&self, snapshot: &HashMap<Vec<u8>, Vec<u8>>) {
+        self.cache.clear();
+        self.cache_bytes.store(0, Ordering::Release);
+        for (key, val) in snapshot.iter() {
+            self.cache_insert(key.clone(), val.clone());
+        }
+    }
 }
 
 impl cita_trie::DB for RocksTrieDB {
@@ -82,17 +497,18 @@ impl cita_trie::DB for RocksTrieDB {
     }
 
     fn contains(&self, key: &[u8]) -> Result<bool, Self::Error> {
-        let res = self.cache.contains_key(key);
+        if let Some(entry) = self.cache.get(key) {
+            entry.touch(self.tick());
+            self.on_cache_read(true);
+            return Ok(true);
+        }
+        self.on_cache_read(false);
 
-        if res {
-            Ok(true)
-        } else {
-            if let Some(val) = self.db.get(key).map_err(to_store_err)?.map(|r| r.to_vec()) {
-                self.cache.insert(key.to_owned(), val);
-                return Ok(true);
-            }
-            Ok(false)
+        if let Some(val) = self.db.get(key).map_err(to_store_err)?.map(|r| r.to_vec()) {
+            self.cache_insert(key.to_owned(), val);
+            return Ok(true);
         }
+        Ok(false)
     }
 
     fn insert(&self, key: Vec<u8>, value: Vec<u8>) -> Result<(), Self::Error> {
@@ -100,11 +516,16 @@ impl cita_trie::DB for RocksTrieDB {
         let size = key.len() + value.len();
 
         {
-            self.cache.insert(key.clone(), value.clone());
+            self.cache_insert(key.clone(), value.clone());
+        }
+
+        if self.buffered {
+            self.dirty.insert(key);
+            return Ok(());
         }
 
         self.db
-            .put(Bytes::from(key), Bytes::from(value))
+            .put_opt(Bytes::from(key), Bytes::from(value), &self.write_options())
             .map_err(to_store_err)?;
 
         on_storage_put_state(inst.elapsed(), size as f64);
@@ -116,6 +537,14 @@ impl cita_trie::DB for RocksTrieDB {
             return Err(RocksTrieDBError::BatchLengthMismatch);
         }
 
+        if self.buffered {
+            for (key, val) in keys.into_iter().zip(values.into_iter()) {
+                self.cache_insert(key.clone(), val);
+                self.dirty.insert(key);
+            }
+            return Ok(());
+        }
+
         let mut total_size = 0;
         let mut batch = WriteBatch::default();
 
@@ -124,59 +553,119 @@ impl cita_trie::DB for RocksTrieDB {
                 total_size += key.len();
                 total_size += val.len();
                 batch.put(key, val)?;
-                self.cache.insert(key.clone(), val.clone());
+                self.cache_insert(key.clone(), val.clone());
             }
         }
 
         let inst = Instant::now();
-        self.db.write(&batch).map_err(to_store_err)?;
+        self.db
+            .write_opt(&batch, &self.write_options())
+            .map_err(to_store_err)?;
         on_storage_put_state(inst.elapsed(), total_size as f64);
         Ok(())
     }
 
-    fn remove(&self, _key: &[u8]) -> Result<(), Self::Error> {
-        Ok(())
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        if !self.pruning {
+            return Ok(());
+        }
+
+        self.cache_remove(key);
+        self.dirty.remove(key);
+        self.db
+            .delete_opt(key, &self.write_options())
+            .map_err(to_store_err)
     }
 
-    fn remove_batch(&self, _keys: &[Vec<u8>]) -> Result<(), Self::Error> {
-        Ok(())
+    fn remove_batch(&self, keys: &[Vec<u8>]) -> Result<(), Self::Error> {
+        if !self.pruning {
+            return Ok(());
+        }
+
+        let mut batch = WriteBatch::default();
+        for key in keys.iter() {
+            batch.delete(key)?;
+            self.cache_remove(key);
+            self.dirty.remove(key);
+        }
+
+        self.db
+            .write_opt(&batch, &self.write_options())
+            .map_err(to_store_err)
     }
 
     fn flush(&self) -> Result<(), Self::Error> {
-        let len = self.cache.len();
-
-        if len <= self.cache_size {
+        let (total, excess) = match self.byte_budget {
+            Some(budget) => {
+                let bytes = self.cache_bytes.load(Ordering::Acquire);
+                (bytes, bytes.saturating_sub(budget))
+            }
+            None => {
+                let len = self.cache.len();
+                (len, len.saturating_sub(self.cache_size))
+            }
+        };
+        if excess == 0 {
             return Ok(());
         }
 
-        let keys = self
+        // Only the sampled keys are read, no key is cloned
+        let sample = self
             .cache
             .iter()
-            .map(|kv| kv.key().clone())
+            .filter(|kv| !self.dirty.contains(kv.key()))
+            .take(EVICTION_SAMPLE)
+            .map(|kv| {
+                let weight = match self.byte_budget {
+                    Some(_) => kv.key().len() + kv.value().value.len(),
+                    None => 1,
+                };
+                (kv.value().touched.load(Ordering::Relaxed), weight)
+            })
             .collect::<Vec<_>>();
-        let remove_list = rand_remove_list(keys, len - self.cache_size);
 
-        for item in remove_list.iter() {
-            self.cache.remove(item);
-        }
+        // A partial sample evicts the same share of itself as of the cache
+        let excess = if sample.len() < EVICTION_SAMPLE {
+            excess
+        } else {
+            let weight = sample.iter().map(|(_, w)| *w).sum::<usize>();
+            ((excess as u128 * weight as u128 + total as u128 - 1) / total as u128) as usize
+        };
+        let threshold = match eviction_threshold(sample, excess) {
+            Some(tick) => tick,
+            None => return Ok(()),
+        };
+
+        // Every shard is locked on its own, the concurrent inserts only wait
+        // for the shard being swept
+        self.cache.retain(|key, entry| {
+            if entry.touched.load(Ordering::Relaxed) >= threshold || self.dirty.contains(key) {
+                return true;
+            }
+            self.cache_bytes
+                .fetch_sub(key.len() + entry.value.len(), Ordering::AcqRel);
+            false
+        });
         Ok(())
     }
 }
 
-fn rand_remove_list<T: Clone>(keys: Vec<T>, num: usize) -> Vec<T> {
-    let mut len = keys.len() - 1;
-    let mut idx_list = (0..len).collect::<Vec<_>>();
-    let mut rng = SmallRng::seed_from_u64(RAND_SEED);
-    let mut ret = Vec::with_capacity(num);
-
-    for _ in 0..num {
-        let tmp = rng.gen_range(0..len);
-        let idx = idx_list.remove(tmp);
-        ret.push(keys[idx].to_owned());
-        len -= 1;
+// The tick the least recently touched entries, whose weights add up to at
+// least `excess`, are below. `None` if nothing is to be evicted.
+fn eviction_threshold(mut sample: Vec<(u64, usize)>, excess: usize) -> Option<u64> {
+    if excess == 0 || sample.is_empty() {
+        return None;
     }
+    sample.sort_unstable_by_key(|(tick, _)| *tick);
 
-    ret
+    let mut freed = 0;
+    for (tick, weight) in sample.iter() {
+        if freed >= excess {
+            return Some(*tick);
+        }
+        freed += weight;
+    }
+    sample.last().map(|(tick, _)| tick + 1)
 }
 
 #[derive(Debug, Display, From)]
@@ -215,6 +704,7 @@ mod tests {
     extern crate test;
     use cita_trie::DB;
     use getrandom::getrandom;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
     use test::Bencher;
 
     use super::*;
@@ -226,14 +716,76 @@ mod tests {
     }
 
     #[test]
-    fn test_rand_remove() {
-        let list = (0..10).collect::<Vec<_>>();
-        let keys = list.iter().collect::<Vec<_>>();
+    fn test_eviction_threshold() {
+        let sample = (0..10u64).rev().map(|tick| (tick, 1)).collect::<Vec<_>>();
 
-        for num in 1..10 {
-            let res = rand_remove_list(keys.clone(), num);
-            assert_eq!(res.len(), num);
+        assert_eq!(eviction_threshold(sample.clone(), 0), None);
+        for num in 1..=10 {
+            assert_eq!(eviction_threshold(sample.clone(), num), Some(num as u64));
         }
+        assert_eq!(eviction_threshold(sample.clone(), 11), Some(10));
+
+        // Weighted by the entry sizes
+        let sample = vec![(3, 100), (1, 200), (2, 50)];
+        assert_eq!(eviction_threshold(sample.clone(), 200), Some(2));
+        assert_eq!(eviction_threshold(sample, 201), Some(3));
+    }
+
+    #[test]
+    fn test_trie_cache_lru() {
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 2).unwrap();
+        let kvs = (0..3)
+            .map(|_| (rand_bytes(32), rand_bytes(128)))
+            .collect::<Vec<_>>();
+        for (key, val) in kvs.iter() {
+            trie.insert(key.clone(), val.clone()).unwrap();
+        }
+
+        // The oldest insert is read again, the second one is left untouched
+        trie.get(&kvs[0].0).unwrap();
+        trie.flush().unwrap();
+
+        assert_eq!(trie.cache_len(), 2);
+        assert!(trie.cache_get(&kvs[0].0).is_some());
+        assert!(trie.cache_get(&kvs[1].0).is_none());
+        assert!(trie.cache_get(&kvs[2].0).is_some());
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_byte_budget() {
+        // 200 bytes per entry
+        let kvs = (0..6)
+            .map(|_| (rand_bytes(32), rand_bytes(168)))
+            .collect::<Vec<_>>();
+
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::with_byte_budget(dir.path(), 1024, 1000).unwrap();
+        for (key, val) in kvs[..5].iter() {
+            trie.insert(key.clone(), val.clone()).unwrap();
+        }
+        assert_eq!(trie.cache_bytes(), 1000);
+        trie.flush().unwrap();
+        assert_eq!(trie.cache_len(), 5);
+
+        trie.insert(kvs[5].0.clone(), kvs[5].1.clone()).unwrap();
+        assert_eq!(trie.cache_bytes(), 1200);
+        trie.flush().unwrap();
+        assert_eq!(trie.cache_len(), 5);
+        assert_eq!(trie.cache_bytes(), 1000);
+        assert!(trie.cache_get(&kvs[0].0).is_none());
+
+        // A larger value for a cached key counts the difference only
+        trie.insert(kvs[1].0.clone(), rand_bytes(568)).unwrap();
+        assert_eq!(trie.cache_bytes(), 1400);
+        trie.flush().unwrap();
+        assert_eq!(trie.cache_len(), 3);
+        assert_eq!(trie.cache_bytes(), 1000);
+        assert!(trie.cache_get(&kvs[1].0).is_some());
+
+        dir.close().unwrap();
     }
 
     #[test]
@@ -296,13 +848,440 @@ mod tests {
         dir.close().unwrap();
     }
 
+    #[test]
+    fn test_trie_cache_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 10).unwrap();
+
+        for _ in 0..10 {
+            trie.insert(rand_bytes(32), rand_bytes(128)).unwrap();
+        }
+        let snapshot = trie.cache_snapshot();
+        assert_eq!(snapshot.len(), 10);
+
+        let (key, _) = snapshot.iter().next().unwrap();
+        trie.insert(key.clone(), rand_bytes(128)).unwrap();
+        for _ in 0..10 {
+            trie.insert(rand_bytes(32), rand_bytes(128)).unwrap();
+        }
+        // Evicts the 10 least recently used keys
+        trie.flush().unwrap();
+        assert_ne!(trie.cache_snapshot(), snapshot);
+
+        trie.cache_restore(&snapshot);
+        assert_eq!(trie.cache_snapshot(), snapshot);
+        assert_eq!(trie.cache_len(), 10);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_cache_stats() {
+        let key = rand_bytes(32);
+        let val = rand_bytes(128);
+
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        trie.insert(key.clone(), val.clone()).unwrap();
+        drop(trie);
+
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        assert_eq!(trie.cache_stats(), (0, 0));
+        assert_eq!(trie.get(&key).unwrap().unwrap(), val);
+        assert_eq!(trie.cache_stats(), (0, 1));
+        assert_eq!(trie.get(&key).unwrap().unwrap(), val);
+        assert_eq!(trie.cache_stats(), (1, 1));
+        assert!(trie.contains(&key).unwrap());
+        assert_eq!(trie.cache_stats(), (2, 1));
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_warm_up() {
+        let kvs = (0..4)
+            .map(|_| (rand_bytes(32), rand_bytes(128)))
+            .collect::<Vec<_>>();
+        let prefixed = (0..4u8)
+            .map(|i| {
+                (
+                    [vec![0xab, 0xcd, 0xef, i], rand_bytes(28)].concat(),
+                    rand_bytes(128),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        for (key, val) in kvs.iter().chain(prefixed.iter()) {
+            trie.insert(key.clone(), val.clone()).unwrap();
+        }
+        drop(trie);
+
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        let keys = vec![kvs[0].0.clone(), kvs[1].0.clone(), rand_bytes(32)];
+        assert_eq!(trie.warm_up(&keys).unwrap(), 2);
+        assert_eq!(trie.warm_up(&keys).unwrap(), 0);
+        assert_eq!(trie.cache_stats(), (0, 0));
+
+        for (key, val) in kvs[..2].iter() {
+            assert_eq!(&trie.get(key).unwrap().unwrap(), val);
+        }
+        assert_eq!(trie.cache_stats(), (2, 0));
+
+        assert_eq!(trie.warm_up_prefix(&[0xab, 0xcd, 0xef], 3), 3);
+        assert_eq!(trie.cache_len(), 5);
+        for (key, val) in prefixed[..3].iter() {
+            assert_eq!(&trie.get(key).unwrap().unwrap(), val);
+        }
+        assert_eq!(trie.cache_stats(), (5, 0));
+        assert!(trie.cache_get(&prefixed[3].0).is_none());
+        assert!(trie.cache_get(&kvs[2].0).is_none());
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_multi_get() {
+        let kvs = (0..4)
+            .map(|_| (rand_bytes(32), rand_bytes(128)))
+            .collect::<Vec<_>>();
+
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        for (key, val) in kvs.iter() {
+            trie.insert(key.clone(), val.clone()).unwrap();
+        }
+        drop(trie);
+
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        trie.get(&kvs[1].0).unwrap();
+        trie.get(&kvs[3].0).unwrap();
+        assert_eq!(trie.cache_len(), 2);
+
+        let absent = rand_bytes(32);
+        let keys = vec![
+            kvs[0].0.clone(),
+            kvs[1].0.clone(),
+            absent,
+            kvs[2].0.clone(),
+            kvs[3].0.clone(),
+        ];
+        let res = trie.multi_get(&keys).unwrap();
+        assert_eq!(res, vec![
+            Some(kvs[0].1.clone()),
+            Some(kvs[1].1.clone()),
+            None,
+            Some(kvs[2].1.clone()),
+            Some(kvs[3].1.clone()),
+        ]);
+        assert_eq!(trie.cache_len(), 4);
+        assert_eq!(trie.cache_stats(), (2, 5));
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_sync_interval() {
+        let kvs = (0..5)
+            .map(|_| (rand_bytes(32), rand_bytes(128)))
+            .collect::<Vec<_>>();
+
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100)
+            .unwrap()
+            .sync_interval(Some(2));
+
+        for (key, val) in kvs[..3].iter() {
+            trie.insert(key.clone(), val.clone()).unwrap();
+        }
+        let (keys, vals): (Vec<_>, Vec<_>) = kvs[3..].iter().cloned().unzip();
+        trie.insert_batch(keys, vals).unwrap();
+        assert_eq!(trie.write_count(), 4);
+        drop(trie);
+
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        assert_eq!(trie.cache_len(), 0);
+        for (key, val) in kvs.iter() {
+            assert_eq!(&trie.get(key).unwrap().unwrap(), val);
+        }
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_remove() {
+        let kvs = (0..4)
+            .map(|_| (rand_bytes(32), rand_bytes(128)))
+            .collect::<Vec<_>>();
+
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        for (key, val) in kvs.iter() {
+            trie.insert(key.clone(), val.clone()).unwrap();
+        }
+        // Archival by default
+        trie.remove(&kvs[0].0).unwrap();
+        assert!(trie.contains(&kvs[0].0).unwrap());
+        drop(trie);
+
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100)
+            .unwrap()
+            .enable_pruning(true);
+        trie.remove(&kvs[0].0).unwrap();
+        trie.remove_batch(&[kvs[1].0.clone(), kvs[2].0.clone()])
+            .unwrap();
+        for (key, _) in kvs[..3].iter() {
+            assert!(trie.cache_get(key).is_none());
+            assert!(trie.get(key).unwrap().is_none());
+            assert!(!trie.contains(key).unwrap());
+        }
+        assert_eq!(trie.get(&kvs[3].0).unwrap().unwrap(), kvs[3].1);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_buffered_writes() {
+        let kvs = (0..4)
+            .map(|_| (rand_bytes(32), rand_bytes(128)))
+            .collect::<Vec<_>>();
+
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 1)
+            .unwrap()
+            .enable_buffered_writes(true);
+        trie.insert(kvs[0].0.clone(), kvs[0].1.clone()).unwrap();
+        let (keys, vals): (Vec<_>, Vec<_>) = kvs[1..].iter().cloned().unzip();
+        trie.insert_batch(keys, vals).unwrap();
+        assert_eq!(trie.dirty_len(), 4);
+
+        // The dirty entries outlive the eviction and are read from the cache
+        trie.flush().unwrap();
+        assert_eq!(trie.cache_len(), 4);
+        for (key, val) in kvs.iter() {
+            assert_eq!(&trie.get(key).unwrap().unwrap(), val);
+            assert!(trie.db.get(key).unwrap().is_none());
+        }
+
+        trie.commit().unwrap();
+        assert_eq!(trie.dirty_len(), 0);
+        trie.flush().unwrap();
+        assert_eq!(trie.cache_len(), 1);
+        drop(trie);
+
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        assert_eq!(trie.cache_len(), 0);
+        for (key, val) in kvs.iter() {
+            assert_eq!(&trie.get(key).unwrap().unwrap(), val);
+        }
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_commit_races() {
+        let keys = (0..16).map(|_| rand_bytes(32)).collect::<Vec<_>>();
+
+        let dir = tempfile::tempdir().unwrap();
+        let trie = Arc::new(
+            RocksTrieDB::new(dir.path(), 1024, 1)
+                .unwrap()
+                .enable_buffered_writes(true),
+        );
+        for key in keys.iter() {
+            trie.insert(key.clone(), rand_bytes(128)).unwrap();
+        }
+
+        let handles = (0..2)
+            .map(|_| {
+                let (trie, keys) = (Arc::clone(&trie), keys.clone());
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        for key in keys.iter() {
+                            trie.insert(key.clone(), rand_bytes(128)).unwrap();
+                        }
+                        trie.commit().unwrap();
+                        trie.flush().unwrap();
+                        for key in keys.iter() {
+                            assert!(trie.get(key).unwrap().is_some());
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles.into_iter() {
+            handle.join().unwrap();
+        }
+
+        // Whatever the interleaving, the last insert of every key is written
+        let last = keys
+            .iter()
+            .map(|key| trie.get(key).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        trie.commit().unwrap();
+        assert_eq!(trie.dirty_len(), 0);
+        drop(trie);
+
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        for (key, val) in keys.iter().zip(last.iter()) {
+            assert_eq!(&trie.get(key).unwrap().unwrap(), val);
+        }
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_with_config() {
+        let key = rand_bytes(32);
+        let val = rand_bytes(128);
+        let config = RocksTrieDBConfig {
+            write_buffer_size:       4 * 1024 * 1024,
+            max_write_buffer_number: 2,
+            block_cache_size:        8 * 1024 * 1024,
+            compaction_style:        DBCompactionStyle::Universal,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::with_config(dir.path(), 1024, 100, config.clone()).unwrap();
+        trie.insert(key.clone(), val.clone()).unwrap();
+        assert_eq!(trie.get(&key).unwrap().unwrap(), val);
+        drop(trie);
+
+        let trie = RocksTrieDB::with_config(dir.path(), 1024, 100, config).unwrap();
+        assert_eq!(trie.get(&key).unwrap().unwrap(), val);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_trie_db_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        assert_eq!(trie.db_stats().cache_len, 0);
+
+        for _ in 0..50 {
+            trie.insert(rand_bytes(32), rand_bytes(128)).unwrap();
+        }
+
+        let stats = trie.db_stats();
+        assert_eq!(stats.cache_len, 50);
+        assert!(stats.estimate_num_keys.unwrap() > 0);
+        assert!(stats.total_sst_files_size.is_some());
+        assert!(stats.estimate_live_data_size.is_some());
+        assert!(stats.num_sst_files.is_some());
+
+        dir.close().unwrap();
+    }
+
+    fn bench_trie_read(b: &mut Bencher, batch: bool) {
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 0).unwrap();
+        let keys = (0..100).map(|_| rand_bytes(32)).collect::<Vec<_>>();
+        for key in keys.iter() {
+            trie.insert(key.clone(), rand_bytes(128)).unwrap();
+        }
+
+        b.iter(|| {
+            // Every read misses the emptied cache
+            trie.flush().unwrap();
+            if batch {
+                trie.multi_get(&keys).unwrap();
+            } else {
+                for key in keys.iter() {
+                    trie.get(key).unwrap();
+                }
+            }
+        });
+
+        dir.close().unwrap();
+    }
+
+    #[bench]
+    fn bench_trie_get_loop_100(b: &mut Bencher) {
+        bench_trie_read(b, false);
+    }
+
+    #[bench]
+    fn bench_trie_multi_get_100(b: &mut Bencher) {
+        bench_trie_read(b, true);
+    }
+
+    // Flushes a cache over the sample size while other threads keep
+    // inserting, run it on both sides of an eviction change to compare the
+    // contention.
+    #[bench]
+    fn bench_trie_flush_concurrent_insert(b: &mut Bencher) {
+        let dir = tempfile::tempdir().unwrap();
+        let trie = Arc::new(RocksTrieDB::new(dir.path(), 1024, 10_000).unwrap());
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let writers = (0..2)
+            .map(|_| {
+                let (trie, done) = (Arc::clone(&trie), Arc::clone(&done));
+                std::thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        trie.insert(rand_bytes(32), rand_bytes(128)).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        b.iter(|| {
+            for _ in 0..2_000 {
+                trie.cache_insert(rand_bytes(32), rand_bytes(128));
+            }
+            trie.flush().unwrap();
+        });
+
+        done.store(true, Ordering::Relaxed);
+        for writer in writers.into_iter() {
+            writer.join().unwrap();
+        }
+        dir.close().unwrap();
+    }
+
+    // Draw `n` indices in `0..keys` with the probability of index `k`
+    // proportional to `1 / (k + 1)`.
+    fn zipf_indices(keys: usize, n: usize) -> Vec<usize> {
+        let mut cdf = Vec::with_capacity(keys);
+        let mut sum = 0.0;
+        for k in 0..keys {
+            sum += 1.0 / (k + 1) as f64;
+            cdf.push(sum);
+        }
+
+        let mut rng = SmallRng::seed_from_u64(49999);
+        (0..n)
+            .map(|_| {
+                let x = rng.gen::<f64>() * sum;
+                cdf.partition_point(|c| *c < x).min(keys - 1)
+            })
+            .collect()
+    }
+
+    // Reads hot keys through a cache holding a tenth of them, run it on both
+    // sides of an eviction change to compare the rocksdb reads saved.
     #[bench]
-    fn bench_rand(b: &mut Bencher) {
+    fn bench_trie_zipf_get(b: &mut Bencher) {
+        let dir = tempfile::tempdir().unwrap();
+        let trie = RocksTrieDB::new(dir.path(), 1024, 100).unwrap();
+        let keys = (0..1000).map(|_| rand_bytes(32)).collect::<Vec<_>>();
+        for key in keys.iter() {
+            trie.insert(key.clone(), rand_bytes(128)).unwrap();
+        }
+        trie.flush().unwrap();
+        let indices = zipf_indices(keys.len(), 10_000);
+
         b.iter(|| {
-            let mut rng = SmallRng::seed_from_u64(RAND_SEED);
-            for _ in 0..10000 {
-                rng.gen_range(10..1000000);
+            for chunk in indices.chunks(100) {
+                for idx in chunk.iter() {
+                    trie.get(&keys[*idx]).unwrap();
+                }
+                trie.flush().unwrap();
             }
-        })
+        });
+
+        dir.close().unwrap();
     }
 }
@@ -9,7 +9,9 @@ mod system;
 mod tests;
 mod vm;
 
-pub use crate::adapter::{AxonExecutorAdapter, MPTTrie, RocksTrieDB};
+pub use crate::adapter::{
+    AxonExecutorAdapter, MPTTrie, RocksTrieDB, RocksTrieDBConfig, TrieDbStats,
+};
 pub use crate::{system::NATIVE_TOKEN_ISSUE_ADDRESS, vm::code_address};
 
 use std::collections::BTreeMap;
@@ -135,6 +137,10 @@ impl Executor for AxonExecutor {
     }
 }
 
+pub fn is_precompile_address(addr: &H160) -> bool {
+    precompiles::build_precompile_set().contains_key(addr)
+}
+
 pub fn is_call_system_script(action: &TransactionAction) -> bool {
     match action {
         TransactionAction::Call(addr) => addr == &NATIVE_TOKEN_ISSUE_ADDRESS,
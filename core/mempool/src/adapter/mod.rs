@@ -2,28 +2,33 @@ use super::TxContext;
 
 pub mod message;
 
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::{error::Error, marker::PhantomData, sync::Arc, time::Duration};
 
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use futures::{
     channel::mpsc::{unbounded, TrySendError, UnboundedReceiver, UnboundedSender},
-    stream::StreamExt,
+    stream::{self, StreamExt, TryStreamExt},
 };
 use log::{debug, error};
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 
 use common_apm_derive::trace_span;
 use common_config_parser::types::ED25519;
 use common_crypto::{Crypto, Secp256k1Recoverable};
-use core_executor::{is_call_system_script, AxonExecutor, AxonExecutorAdapter};
+use core_executor::{
+    is_call_system_script, is_precompile_address, AxonExecutor, AxonExecutorAdapter,
+};
 use core_interoperation::{get_crypto_code_hash, SignatureType};
 use protocol::traits::{
-    Context, Executor, Gossip, Interoperation, MemPoolAdapter, MetadataControl, PeerTrust,
+    Backend, Context, Executor, Gossip, Interoperation, MemPoolAdapter, MetadataControl, PeerTrust,
     Priority, Rpc, Storage, TrustFeedback,
 };
 use protocol::types::{
-    recover_intact_pub_key, Bytes, Hash, MerkleRoot, SignedTransaction, H160, U256,
+    recover_intact_pub_key, Bytes, Hash, MerkleRoot, SignedTransaction, TransactionAction, H160,
+    U256,
 };
 use protocol::{
     async_trait, codec::ProtocolCodec, lazy::CURRENT_STATE_ROOT, tokio, Display, ProtocolError,
@@ -33,7 +38,7 @@ use protocol::{
 use crate::adapter::message::{
     MsgNewTxs, MsgPullTxs, MsgPushTxs, END_GOSSIP_NEW_TXS, RPC_PULL_TXS,
 };
-use crate::MemPoolError;
+use crate::{is_stale_nonce, AuthFailure, MemPoolError};
 
 struct IntervalTxsBroadcaster;
 
@@ -108,6 +113,44 @@ impl IntervalTxsBroadcaster {
     }
 }
 
+/// Remembers the txs which are not committed at a state root, everything is
+/// forgotten once the state root moves on. Only this state dependent result
+/// is kept: the hash doesn't cover the sender and the public key of a tx, so
+/// the signature is verified every time.
+struct VerifyCache {
+    state_root:  RwLock<MerkleRoot>,
+    uncommitted: DashSet<Hash>,
+}
+
+impl VerifyCache {
+    fn new() -> Self {
+        VerifyCache {
+            state_root:  RwLock::new(MerkleRoot::default()),
+            uncommitted: DashSet::new(),
+        }
+    }
+
+    fn is_uncommitted(&self, tx_hash: &Hash) -> bool {
+        self.uncommitted.contains(tx_hash)
+    }
+
+    fn set_uncommitted(&self, tx_hash: Hash) {
+        self.uncommitted.insert(tx_hash);
+    }
+
+    /// Clear the cache if the state root changed, return whether it did.
+    fn reset(&self, state_root: MerkleRoot) -> bool {
+        let mut root = self.state_root.write();
+        if *root == state_root {
+            return false;
+        }
+
+        *root = state_root;
+        self.uncommitted.clear();
+        true
+    }
+}
+
 pub struct DefaultMemPoolAdapter<C, N, S, DB, M, I> {
     network:        N,
     storage:        Arc<S>,
@@ -116,11 +159,18 @@ pub struct DefaultMemPoolAdapter<C, N, S, DB, M, I> {
     interoperation: Arc<I>,
 
     addr_nonce:   DashMap<H160, U256>,
+    addr_balance: DashMap<H160, U256>,
+    // The root of the last `set_args`, the committed nonces are read against
+    state_root:   RwLock<Option<MerkleRoot>>,
     _timeout_gap: AtomicU64,
     gas_limit:    AtomicU64,
     max_tx_size:  AtomicUsize,
     chain_id:     u64,
 
+    check_recipient:          bool,
+    verify_cache:             Option<VerifyCache>,
+    storage_read_concurrency: Option<usize>,
+
     stx_tx: UnboundedSender<SignedTransaction>,
     err_rx: Mutex<UnboundedReceiver<ProtocolError>>,
 
@@ -168,11 +218,17 @@ where
             interoperation,
 
             addr_nonce: DashMap::new(),
+            addr_balance: DashMap::new(),
+            state_root: RwLock::new(None),
             _timeout_gap: AtomicU64::new(timeout_gap),
             gas_limit: AtomicU64::new(gas_limit),
             max_tx_size: AtomicUsize::new(max_tx_size),
             chain_id,
 
+            check_recipient: false,
+            verify_cache: None,
+            storage_read_concurrency: None,
+
             stx_tx,
             err_rx: Mutex::new(err_rx),
 
@@ -180,6 +236,64 @@ where
         }
     }
 
+    /// Reject the txs calling an address which is neither an account in state
+    /// nor a precompile. It is disabled by default.
+    pub fn recipient_check(mut self, enable: bool) -> Self {
+        self.check_recipient = enable;
+        self
+    }
+
+    /// Cache the results of `check_storage_exist` until `set_args` brings a
+    /// new state root. It is disabled by default.
+    pub fn verify_cache(mut self, enable: bool) -> Self {
+        self.verify_cache = enable.then(VerifyCache::new);
+        self
+    }
+
+    /// Read at most `concurrency` txs by hash from storage at the same time
+    /// when their height is unknown. It is unbounded by default.
+    pub fn storage_read_concurrency(mut self, concurrency: Option<usize>) -> Self {
+        self.storage_read_concurrency = concurrency.filter(|n| *n > 0);
+        self
+    }
+
+    /// Read the committed nonces of the senders from the state at the
+    /// `set_args` root, sharing one backend for all the senders missing from
+    /// the nonce cache.
+    pub fn nonce_frontiers(&self, senders: &[H160]) -> ProtocolResult<HashMap<H160, U256>> {
+        let mut frontiers = HashMap::with_capacity(senders.len());
+        let mut missing = Vec::new();
+        for sender in senders.iter() {
+            match self.addr_nonce.get(sender) {
+                Some(nonce) => {
+                    frontiers.insert(*sender, *nonce.value());
+                }
+                None => missing.push(*sender),
+            }
+        }
+
+        if !missing.is_empty() {
+            // Before the first `set_args` the root is the one loaded at start
+            let state_root = self
+                .state_root
+                .read()
+                .unwrap_or_else(|| **CURRENT_STATE_ROOT.load());
+            let backend = AxonExecutorAdapter::from_root(
+                state_root,
+                Arc::clone(&self.trie_db),
+                Arc::clone(&self.storage),
+                Default::default(),
+            )?;
+
+            for (sender, nonce) in read_nonces(&backend, &missing).into_iter() {
+                self.addr_nonce.insert(sender, nonce);
+                frontiers.insert(sender, nonce);
+            }
+        }
+
+        Ok(frontiers)
+    }
+
     async fn check_system_script_tx_authorization(
         &self,
         ctx: Context,
@@ -195,10 +309,10 @@ where
             return Ok(());
         }
 
-        Err(MemPoolError::CheckAuthorization {
-            tx_hash:  stx.transaction.hash,
-            err_info: "Invalid system script transaction".to_string(),
-        }
+        Err(MemPoolError::Authorization(
+            AuthFailure::Other("Invalid system script transaction".to_string()),
+            stx.transaction.hash,
+        )
         .into())
     }
 }
@@ -259,46 +373,48 @@ where
         }
 
         let addr = &tx.sender;
-        if let Some(res) = self.addr_nonce.get(addr) {
-            if res.value() >= &tx.transaction.unsigned.nonce {
-                return Err(MemPoolError::InvalidNonce {
-                    current:  res.value().as_u64(),
-                    tx_nonce: tx.transaction.unsigned.nonce.as_u64(),
-                }
-                .into());
-            } else {
-                return Ok(());
+        let cached = self
+            .addr_nonce
+            .get(addr)
+            .map(|nonce| *nonce.value())
+            .zip(self.addr_balance.get(addr).map(|balance| *balance.value()));
+        let (nonce, balance) = match cached {
+            Some(account) => account,
+            None => {
+                let backend = AxonExecutorAdapter::from_root(
+                    **CURRENT_STATE_ROOT.load(),
+                    Arc::clone(&self.trie_db),
+                    Arc::clone(&self.storage),
+                    Default::default(),
+                )?;
+
+                let account = AxonExecutor::default().get_account(&backend, addr);
+                self.addr_nonce.insert(*addr, account.nonce);
+                self.addr_balance.insert(*addr, account.balance);
+                (account.nonce, account.balance)
             }
-        }
-
-        let backend = AxonExecutorAdapter::from_root(
-            **CURRENT_STATE_ROOT.load(),
-            Arc::clone(&self.trie_db),
-            Arc::clone(&self.storage),
-            Default::default(),
-        )?;
-
-        let account = AxonExecutor::default().get_account(&backend, addr);
-        self.addr_nonce.insert(*addr, account.nonce);
+        };
 
-        if account.nonce >= tx.transaction.unsigned.nonce {
-            return Err(MemPoolError::InvalidNonce {
-                current:  account.nonce.as_u64(),
-                tx_nonce: tx.transaction.unsigned.nonce.as_u64(),
-            }
-            .into());
+        if is_stale_nonce(nonce, tx.transaction.unsigned.nonce) {
+            return Err(stale_nonce(nonce, tx));
         }
 
-        Ok(())
+        check_balance(balance, tx)
     }
 
     async fn check_transaction(&self, ctx: Context, stx: &SignedTransaction) -> ProtocolResult<()> {
         if stx.transaction.signature.is_none() {
-            return Err(AdapterError::VerifySignature("missing signature".to_string()).into());
+            return Err(bad_signature(
+                stx.transaction.hash,
+                "missing signature".to_string(),
+            ));
         }
 
         if stx.public.is_none() {
-            return Err(AdapterError::VerifySignature("missing public key".to_string()).into());
+            return Err(bad_signature(
+                stx.transaction.hash,
+                "missing public key".to_string(),
+            ));
         }
 
         let fixed_bytes = stx.transaction.encode()?;
@@ -350,6 +466,17 @@ where
             return Err(wrong_chain_id.into());
         }
 
+        // Verify recipient
+        if self.check_recipient {
+            let backend = AxonExecutorAdapter::from_root(
+                **CURRENT_STATE_ROOT.load(),
+                Arc::clone(&self.trie_db),
+                Arc::clone(&self.storage),
+                Default::default(),
+            )?;
+            check_recipient(&backend, stx)?;
+        }
+
         // Verify signature
         let signature = stx.transaction.signature.clone().unwrap();
         match SignatureType::try_from(signature.standard_v)? {
@@ -360,7 +487,7 @@ where
                     signature.as_bytes().as_ref(),
                     recover_intact_pub_key(&stx.public.unwrap()).as_bytes(),
                 )
-                .map_err(|err| AdapterError::VerifySignature(err.to_string()))?;
+                .map_err(|err| bad_signature(tx_hash, err.to_string()))?;
             }
             SignatureType::Ed25519 => {
                 let code_hash = get_crypto_code_hash(ED25519)?;
@@ -371,7 +498,7 @@ where
                 ];
                 self.interoperation
                     .call_ckb_vm(Default::default(), code_hash, &args, u64::MAX)
-                    .map_err(|err| AdapterError::VerifySignature(err.to_string()))?;
+                    .map_err(|err| bad_signature(tx_hash, err.to_string()))?;
             }
         };
 
@@ -379,9 +506,20 @@ where
     }
 
     async fn check_storage_exist(&self, ctx: Context, tx_hash: &Hash) -> ProtocolResult<()> {
+        if let Some(cache) = self.verify_cache.as_ref() {
+            if cache.is_uncommitted(tx_hash) {
+                return Ok(());
+            }
+        }
+
         match self.storage.get_transaction_by_hash(ctx, tx_hash).await {
             Ok(Some(_)) => Err(MemPoolError::CommittedTx(*tx_hash).into()),
-            Ok(None) => Ok(()),
+            Ok(None) => {
+                if let Some(cache) = self.verify_cache.as_ref() {
+                    cache.set_uncommitted(*tx_hash);
+                }
+                Ok(())
+            }
             Err(err) => Err(err),
         }
     }
@@ -391,6 +529,11 @@ where
         Ok(height)
     }
 
+    async fn get_account_nonce(&self, _ctx: Context, address: H160) -> ProtocolResult<U256> {
+        let frontiers = self.nonce_frontiers(&[address])?;
+        Ok(frontiers.get(&address).copied().unwrap_or_default())
+    }
+
     async fn get_transactions_from_storage(
         &self,
         ctx: Context,
@@ -400,25 +543,30 @@ where
         if let Some(height) = block_height {
             self.storage.get_transactions(ctx, height, tx_hashes).await
         } else {
-            let futs = tx_hashes
-                .iter()
-                .map(|tx_hash| self.storage.get_transaction_by_hash(ctx.clone(), tx_hash))
-                .collect::<Vec<_>>();
-            futures::future::try_join_all(futs).await
+            read_txs_by_hash(tx_hashes, self.storage_read_concurrency, |tx_hash| {
+                self.storage.get_transaction_by_hash(ctx.clone(), tx_hash)
+            })
+            .await
         }
     }
 
     fn set_args(
         &self,
         _context: Context,
-        _state_root: MerkleRoot,
+        state_root: MerkleRoot,
         cycles_limit: u64,
         max_tx_size: u64,
     ) {
         self.gas_limit.store(cycles_limit, Ordering::Relaxed);
         self.max_tx_size
             .store(max_tx_size as usize, Ordering::Relaxed);
+        *self.state_root.write() = Some(state_root);
         self.addr_nonce.clear();
+        self.addr_balance.clear();
+
+        if let Some(cache) = self.verify_cache.as_ref() {
+            cache.reset(state_root);
+        }
     }
 
     fn report_good(&self, ctx: Context) {
@@ -426,6 +574,93 @@ where
             self.network.report(ctx, TrustFeedback::Good);
         }
     }
+
+    fn report_bad(&self, ctx: Context) {
+        self.network.report(
+            ctx,
+            TrustFeedback::Bad("Mempool pulls a short txs response".to_string()),
+        );
+    }
+}
+
+fn check_recipient<B: Backend>(backend: &B, stx: &SignedTransaction) -> ProtocolResult<()> {
+    let action = &stx.transaction.unsigned.action;
+    if let TransactionAction::Call(to) = action {
+        if !is_call_system_script(action) && !is_precompile_address(to) && !backend.exists(*to) {
+            return Err(MemPoolError::UnknownRecipient {
+                tx_hash: stx.transaction.hash,
+                to:      *to,
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+fn read_nonces<B: Backend>(backend: &B, senders: &[H160]) -> HashMap<H160, U256> {
+    senders
+        .iter()
+        .map(|sender| (*sender, backend.basic(*sender).nonce))
+        .collect()
+}
+
+// Keep the order of `tx_hashes`, with at most `concurrency` reads in flight.
+async fn read_txs_by_hash<'a, F, Fut>(
+    tx_hashes: &'a [Hash],
+    concurrency: Option<usize>,
+    read: F,
+) -> ProtocolResult<Vec<Option<SignedTransaction>>>
+where
+    F: Fn(&'a Hash) -> Fut,
+    Fut: Future<Output = ProtocolResult<Option<SignedTransaction>>>,
+{
+    match concurrency {
+        Some(n) => {
+            stream::iter(tx_hashes.iter().map(read))
+                .buffered(n)
+                .try_collect()
+                .await
+        }
+        None => futures::future::try_join_all(tx_hashes.iter().map(read)).await,
+    }
+}
+
+fn stale_nonce(current: U256, tx: &SignedTransaction) -> ProtocolError {
+    MemPoolError::Authorization(
+        AuthFailure::StaleNonce {
+            current,
+            tx: tx.transaction.unsigned.nonce,
+        },
+        tx.transaction.hash,
+    )
+    .into()
+}
+
+// The sender pays for the gas limit at the gas price on top of the value.
+fn check_balance(balance: U256, tx: &SignedTransaction) -> ProtocolResult<()> {
+    let unsigned = &tx.transaction.unsigned;
+    let need = unsigned
+        .gas_limit
+        .saturating_mul(unsigned.gas_price)
+        .saturating_add(unsigned.value);
+    if balance < need {
+        return Err(MemPoolError::Authorization(
+            AuthFailure::InsufficientBalance {
+                have: balance,
+                need,
+            },
+            tx.transaction.hash,
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+fn bad_signature(tx_hash: Hash, err: String) -> ProtocolError {
+    debug!("[mempool]: tx {:?} bad signature {}", tx_hash, err);
+    MemPoolError::Authorization(AuthFailure::BadSignature, tx_hash).into()
 }
 
 #[derive(Debug, Display)]
@@ -465,10 +700,19 @@ mod tests {
 
     use parking_lot::Mutex;
 
-    use protocol::{traits::MessageCodec, types::Bytes};
+    use std::collections::BTreeMap;
+
+    use common_crypto::Secp256k1;
+    use core_storage::{adapter::memory::MemoryAdapter, ImplStorage};
+    use protocol::traits::{ApplyBackend, MessageCodec};
+    use protocol::types::{
+        Apply, Basic, Bytes, Header, MemoryAccount, MemoryBackend, MemoryVicinity, Metadata,
+        VMResp, H256,
+    };
 
     use super::*;
-    use crate::{adapter::message::MsgNewTxs, tests::default_mock_txs};
+    use crate::tests::{default_mock_txs, mock_call_tx};
+    use crate::{adapter::message::MsgNewTxs, next_nonce};
 
     #[derive(Clone)]
     struct MockGossip {
@@ -523,6 +767,127 @@ mod tests {
         }
     }
 
+    #[derive(Clone)]
+    struct MockNetwork;
+
+    #[async_trait]
+    impl Gossip for MockNetwork {
+        async fn broadcast<M>(&self, _: Context, _: &str, _: M, _: Priority) -> ProtocolResult<()>
+        where
+            M: MessageCodec,
+        {
+            Ok(())
+        }
+
+        async fn multicast<'a, M, P>(
+            &self,
+            _: Context,
+            _: &str,
+            _: P,
+            _: M,
+            _: Priority,
+        ) -> ProtocolResult<()>
+        where
+            M: MessageCodec,
+            P: AsRef<[Bytes]> + Send + 'a,
+        {
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Rpc for MockNetwork {
+        async fn call<M, R>(&self, _: Context, _: &str, _: M, _: Priority) -> ProtocolResult<R>
+        where
+            M: MessageCodec,
+            R: MessageCodec,
+        {
+            unreachable!()
+        }
+
+        async fn response<M>(
+            &self,
+            _: Context,
+            _: &str,
+            _: ProtocolResult<M>,
+            _: Priority,
+        ) -> ProtocolResult<()>
+        where
+            M: MessageCodec,
+        {
+            Ok(())
+        }
+    }
+
+    impl PeerTrust for MockNetwork {
+        fn report(&self, _: Context, _: TrustFeedback) {}
+    }
+
+    struct MockMetadata;
+
+    impl MetadataControl for MockMetadata {
+        fn calc_epoch(&self, _: u64) -> u64 {
+            0
+        }
+
+        fn need_change_metadata(&self, _: u64) -> bool {
+            false
+        }
+
+        fn update_metadata(&self, _: Context, _: &Header) -> ProtocolResult<Metadata> {
+            Ok(Metadata::default())
+        }
+
+        fn get_metadata(&self, _: Context, _: &Header) -> ProtocolResult<Metadata> {
+            Ok(Metadata::default())
+        }
+
+        fn get_metadata_unchecked(&self, _: Context, _: u64) -> Metadata {
+            Metadata::default()
+        }
+    }
+
+    struct MockInteroperation;
+
+    impl Interoperation for MockInteroperation {
+        fn verify_external_signature(
+            &self,
+            _: Context,
+            _: SignedTransaction,
+        ) -> ProtocolResult<()> {
+            unreachable!()
+        }
+
+        fn call_ckb_vm(&self, _: Context, _: H256, _: &[Bytes], _: u64) -> ProtocolResult<VMResp> {
+            unreachable!()
+        }
+    }
+
+    type TestAdapter = DefaultMemPoolAdapter<
+        Secp256k1,
+        MockNetwork,
+        ImplStorage<MemoryAdapter>,
+        cita_trie::MemoryDB,
+        MockMetadata,
+        MockInteroperation,
+    >;
+
+    fn mock_adapter(chain_id: u64) -> TestAdapter {
+        DefaultMemPoolAdapter::new(
+            MockNetwork,
+            Arc::new(ImplStorage::new(Arc::new(MemoryAdapter::new()))),
+            Arc::new(cita_trie::MemoryDB::new(false)),
+            Arc::new(MockMetadata),
+            Arc::new(MockInteroperation),
+            chain_id,
+            0,
+            1000,
+            1024,
+            10,
+            200,
+        )
+    }
+
     macro_rules! pop_msg {
         ($msgs:expr) => {{
             let msg = $msgs.pop().expect("should have one message");
@@ -627,4 +992,285 @@ mod tests {
             "first message should only have 10 stx"
         );
     }
+
+    #[tokio::test]
+    async fn test_verify_cache() {
+        let txs = default_mock_txs(2);
+        let tx = txs[0].clone();
+        let adapter = mock_adapter(tx.transaction.chain_id).verify_cache(true);
+        adapter.set_args(Context::new(), MerkleRoot::from_low_u64_be(1), 1000, 1024);
+        adapter
+            .check_transaction(Context::new(), &tx)
+            .await
+            .unwrap();
+        adapter
+            .check_storage_exist(Context::new(), &tx.transaction.hash)
+            .await
+            .unwrap();
+
+        // A copy with a forged public key is verified again
+        let mut forged = tx.clone();
+        forged.public = txs[1].public;
+        forged.sender = txs[1].sender;
+        assert!(adapter
+            .check_transaction(Context::new(), &forged)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("BadSignature"));
+
+        // So are the new limits under the same state root
+        adapter.set_args(Context::new(), MerkleRoot::from_low_u64_be(1), 1000, 16);
+        assert!(adapter
+            .check_transaction(Context::new(), &tx)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("ExceedSizeLimit"));
+
+        // Only a new state root forgets that the tx is not committed
+        adapter
+            .storage
+            .insert_transactions(Context::new(), 1, vec![tx.clone()])
+            .await
+            .unwrap();
+        adapter.set_args(Context::new(), MerkleRoot::from_low_u64_be(1), 1000, 1024);
+        adapter
+            .check_storage_exist(Context::new(), &tx.transaction.hash)
+            .await
+            .unwrap();
+        adapter.set_args(Context::new(), MerkleRoot::from_low_u64_be(2), 1000, 1024);
+        assert!(adapter
+            .check_storage_exist(Context::new(), &tx.transaction.hash)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("CommittedTx"));
+    }
+
+    #[tokio::test]
+    async fn test_recipient_check() {
+        let tx = mock_call_tx(H160::from_low_u64_be(0x2000));
+        let adapter = mock_adapter(tx.transaction.chain_id);
+        adapter
+            .check_transaction(Context::new(), &tx)
+            .await
+            .unwrap();
+
+        let adapter = mock_adapter(tx.transaction.chain_id).recipient_check(true);
+        assert!(adapter
+            .check_transaction(Context::new(), &tx)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("UnknownRecipient"));
+
+        // The txs creating a contract have no recipient to check
+        let tx = default_mock_txs(1).remove(0);
+        let adapter = mock_adapter(tx.transaction.chain_id).recipient_check(true);
+        adapter
+            .check_transaction(Context::new(), &tx)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_authorization_nonce() {
+        let tx = default_mock_txs(2).remove(1);
+        let adapter = mock_adapter(tx.transaction.chain_id);
+
+        // The first nonce after the account nonce is the pending one
+        adapter.addr_nonce.insert(tx.sender, U256::zero());
+        adapter.addr_balance.insert(tx.sender, U256::MAX);
+        assert_eq!(next_nonce(U256::zero()), tx.transaction.unsigned.nonce);
+        adapter
+            .check_authorization(Context::new(), &tx)
+            .await
+            .unwrap();
+
+        adapter.addr_nonce.insert(tx.sender, U256::one());
+        assert!(adapter
+            .check_authorization(Context::new(), &tx)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("StaleNonce"));
+    }
+
+    #[tokio::test]
+    async fn test_check_authorization_balance() {
+        let tx = default_mock_txs(2).remove(1);
+        let unsigned = &tx.transaction.unsigned;
+        let need = unsigned.gas_limit * unsigned.gas_price + unsigned.value;
+        let adapter = mock_adapter(tx.transaction.chain_id);
+
+        // The account is read from the empty state
+        let err = adapter
+            .check_authorization(Context::new(), &tx)
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&format!("{:?}", AuthFailure::InsufficientBalance {
+                have: U256::zero(),
+                need,
+            })));
+        assert_eq!(*adapter.addr_balance.get(&tx.sender).unwrap(), U256::zero());
+
+        adapter.addr_balance.insert(tx.sender, need);
+        adapter
+            .check_authorization(Context::new(), &tx)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_transaction_unsigned() {
+        let tx = default_mock_txs(1).remove(0);
+        let adapter = mock_adapter(tx.transaction.chain_id);
+
+        let mut unsigned = tx.clone();
+        unsigned.transaction.signature = None;
+        let mut anonymous = tx;
+        anonymous.public = None;
+        for tx in [unsigned, anonymous] {
+            let err = adapter
+                .check_transaction(Context::new(), &tx)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains(&format!(
+                "{:?}",
+                MemPoolError::Authorization(AuthFailure::BadSignature, tx.transaction.hash)
+            )));
+        }
+    }
+
+    #[test]
+    fn test_nonce_frontiers() {
+        let adapter = mock_adapter(0);
+        let senders = (1..=3u64).map(H160::from_low_u64_be).collect::<Vec<_>>();
+        let mut backend = AxonExecutorAdapter::new(
+            Arc::clone(&adapter.trie_db),
+            Arc::clone(&adapter.storage),
+            Default::default(),
+        )
+        .unwrap();
+        let accounts = senders[..2].iter().map(|sender| Apply::Modify {
+            address:       *sender,
+            basic:         Basic {
+                balance: U256::one(),
+                nonce:   U256::from(sender.to_low_u64_be() + 2),
+            },
+            code:          None,
+            storage:       Vec::<(H256, H256)>::new(),
+            reset_storage: false,
+        });
+        backend.apply(accounts, vec![], false);
+        adapter.set_args(Context::new(), backend.root(), 1000, 1024);
+
+        // The cached nonce of the first sender wins over the state
+        adapter.addr_nonce.insert(senders[0], U256::from(10));
+        let frontiers = adapter.nonce_frontiers(&senders).unwrap();
+        assert_eq!(frontiers.len(), 3);
+        assert_eq!(frontiers[&senders[0]], U256::from(10));
+        assert_eq!(frontiers[&senders[1]], U256::from(4));
+        assert_eq!(frontiers[&senders[2]], U256::zero());
+        assert_eq!(*adapter.addr_nonce.get(&senders[1]).unwrap(), U256::from(4));
+
+        // A new `set_args` drops the cache
+        adapter.set_args(Context::new(), backend.root(), 1000, 1024);
+        let frontiers = adapter.nonce_frontiers(&senders[..1]).unwrap();
+        assert_eq!(frontiers[&senders[0]], U256::from(3));
+    }
+
+    #[test]
+    fn test_read_nonces() {
+        let mut state = BTreeMap::new();
+        for i in 1..=3u64 {
+            state.insert(H160::from_low_u64_be(i), MemoryAccount {
+                nonce:   U256::from(i * 10),
+                balance: U256::one(),
+                storage: BTreeMap::new(),
+                code:    Vec::new(),
+            });
+        }
+        let vicinity = mock_vicinity();
+        let backend = MemoryBackend::new(&vicinity, state);
+
+        let senders = (1..=4).map(H160::from_low_u64_be).collect::<Vec<_>>();
+        let nonces = read_nonces(&backend, &senders);
+        assert_eq!(nonces.len(), 4);
+        for i in 1..=3u64 {
+            assert_eq!(nonces[&H160::from_low_u64_be(i)], U256::from(i * 10));
+        }
+        // Unknown account
+        assert_eq!(nonces[&H160::from_low_u64_be(4)], U256::zero());
+    }
+
+    #[tokio::test]
+    async fn test_read_txs_by_hash_concurrency() {
+        let txs = default_mock_txs(20);
+        let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+        let reading = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let res = read_txs_by_hash(&hashes, Some(4), |hash| {
+            let tx = txs.iter().find(|tx| &tx.transaction.hash == hash).cloned();
+            let (reading, peak) = (&reading, &peak);
+            async move {
+                let now = reading.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                reading.fetch_sub(1, Ordering::SeqCst);
+                Ok(tx)
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(peak.load(Ordering::SeqCst), 4);
+        let read_hashes = res
+            .into_iter()
+            .map(|tx| tx.unwrap().transaction.hash)
+            .collect::<Vec<_>>();
+        assert_eq!(read_hashes, hashes);
+    }
+
+    #[test]
+    fn test_check_recipient() {
+        let known = H160::from_low_u64_be(0x1000);
+        let mut state = BTreeMap::new();
+        state.insert(known, MemoryAccount {
+            nonce:   U256::one(),
+            balance: U256::one(),
+            storage: BTreeMap::new(),
+            code:    Vec::new(),
+        });
+        let vicinity = mock_vicinity();
+        let backend = MemoryBackend::new(&vicinity, state);
+
+        let mut stx = default_mock_txs(1).remove(0);
+        assert!(check_recipient(&backend, &stx).is_ok());
+
+        stx.transaction.unsigned.action = TransactionAction::Call(known);
+        assert!(check_recipient(&backend, &stx).is_ok());
+
+        stx.transaction.unsigned.action = TransactionAction::Call(H160::from_low_u64_be(0x2000));
+        assert!(check_recipient(&backend, &stx).is_err());
+    }
+
+    fn mock_vicinity() -> MemoryVicinity {
+        MemoryVicinity {
+            gas_price:              U256::zero(),
+            origin:                 H160::default(),
+            block_hashes:           Vec::new(),
+            block_number:           Default::default(),
+            block_coinbase:         Default::default(),
+            block_timestamp:        Default::default(),
+            block_difficulty:       Default::default(),
+            block_gas_limit:        Default::default(),
+            chain_id:               U256::one(),
+            block_base_fee_per_gas: U256::zero(),
+        }
+    }
 }
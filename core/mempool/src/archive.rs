@@ -0,0 +1,147 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+
+use protocol::types::{Hash, U256};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictReason {
+    // Stayed in pool longer than the max residence
+    Expired,
+    // Removed together with all the txs of its sender
+    SenderRemoved,
+    // Made room for a better paying tx in the full pool
+    PoolFull,
+    // Superseded by a tx of the same sender and nonce
+    Replaced,
+    // Removed on its own by `remove_tx`
+    Removed,
+    // Committed, or left out of the pool by a flush or a clear
+    Flushed,
+}
+
+/// Remembers why the most recent `capacity` evicted txs left the pool, the
+/// oldest record is forgotten first.
+pub struct EvictionArchive {
+    capacity: usize,
+    inner:    Mutex<ArchiveInner>,
+}
+
+#[derive(Default)]
+struct ArchiveInner {
+    order:   VecDeque<Hash>,
+    reasons: HashMap<Hash, EvictReason>,
+}
+
+impl EvictionArchive {
+    pub fn new(capacity: usize) -> Self {
+        EvictionArchive {
+            capacity,
+            inner: Mutex::new(ArchiveInner::default()),
+        }
+    }
+
+    pub fn record<I: IntoIterator<Item = Hash>>(&self, hashes: I, reason: EvictReason) {
+        let mut inner = self.inner.lock();
+
+        for hash in hashes {
+            if inner.reasons.insert(hash, reason).is_some() {
+                continue;
+            }
+
+            inner.order.push_back(hash);
+            if inner.order.len() > self.capacity {
+                if let Some(oldest) = inner.order.pop_front() {
+                    inner.reasons.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<EvictReason> {
+        self.inner.lock().reasons.get(hash).copied()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RejectionRecord {
+    pub hash:   Hash,
+    // The error returned by the insert
+    pub reason: String,
+    pub at:     SystemTime,
+}
+
+/// The most recent `capacity` rejected inserts, the oldest is dropped first.
+pub struct RejectionLog {
+    capacity: usize,
+    records:  Mutex<VecDeque<RejectionRecord>>,
+}
+
+impl RejectionLog {
+    pub fn new(capacity: usize) -> Self {
+        RejectionLog {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn record(&self, hash: Hash, reason: String) {
+        let mut records = self.records.lock();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+
+        records.push_back(RejectionRecord {
+            hash,
+            reason,
+            at: SystemTime::now(),
+        });
+    }
+
+    /// The latest `n` records, the newest first.
+    pub fn recent(&self, n: usize) -> Vec<RejectionRecord> {
+        self.records.lock().iter().rev().take(n).cloned().collect()
+    }
+}
+
+/// The gas prices of the most recent `capacity` accepted txs, the oldest is
+/// dropped first.
+pub struct GasPriceSamples {
+    capacity: usize,
+    prices:   Mutex<VecDeque<U256>>,
+}
+
+impl GasPriceSamples {
+    pub fn new(capacity: usize) -> Self {
+        GasPriceSamples {
+            capacity,
+            prices: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn record(&self, price: U256) {
+        let mut prices = self.prices.lock();
+        if prices.len() == self.capacity {
+            prices.pop_front();
+        }
+        prices.push_back(price);
+    }
+
+    /// The nearest rank `p` percentile, `None` if there is no sample or `p`
+    /// is out of [0, 100].
+    pub fn percentile(&self, p: f64) -> Option<U256> {
+        if !(0.0..=100.0).contains(&p) {
+            return None;
+        }
+
+        let mut prices = self.prices.lock().iter().copied().collect::<Vec<_>>();
+        if prices.is_empty() {
+            return None;
+        }
+        prices.sort_unstable();
+
+        let rank = (p / 100.0 * prices.len() as f64).ceil() as usize;
+        Some(prices[rank.saturating_sub(1)])
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use protocol::types::U256;
+
+// Batches smaller than this are verified on the current task since spawning
+// costs more than it saves, see the `bench_verify_*` benches.
+pub const DEFAULT_SERIAL_VERIFY_THRESHOLD: usize = 4;
+pub const DEFAULT_SUBSCRIPTION_CAPACITY: usize = 1024;
+
+// Controls when a local tx is broadcast relative to the checks on insert.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropagationMode {
+    // Run all the checks and pool the tx before broadcasting it
+    Strict,
+    // Broadcast right after the authorization check, before the heavy
+    // `check_transaction`. An invalid tx may reach peers this way.
+    Eager,
+}
+
+impl Default for PropagationMode {
+    fn default() -> Self {
+        PropagationMode::Strict
+    }
+}
+
+// What `package` does before `set_args` has ever provided a state root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnpreparedPackage {
+    // Package as usual without any state dependent gating
+    Ungated,
+    // Fail with `MemPoolError::Unprepared`
+    Reject,
+}
+
+impl Default for UnpreparedPackage {
+    fn default() -> Self {
+        UnpreparedPackage::Ungated
+    }
+}
+
+// What a new tx subscriber gets after falling more than the channel capacity
+// behind. The default is `DropOldest`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubscriptionLag {
+    // Skip the missed hashes, counted by the subscription lag metric
+    DropOldest,
+    // End the subscription
+    Close,
+}
+
+impl Default for SubscriptionLag {
+    fn default() -> Self {
+        SubscriptionLag::DropOldest
+    }
+}
+
+// When the encoded size of a pooled tx is computed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeTracking {
+    // Encode every tx on insert and keep its size, queries are lookups
+    Eager,
+    // Encode a tx whenever its size is queried, inserts skip the encoding
+    Lazy,
+}
+
+impl Default for SizeTracking {
+    fn default() -> Self {
+        SizeTracking::Eager
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MemPoolConfig {
+    pub pool_size:        usize,
+    // Txs stay in pool longer than this are dropped by the sweeper
+    pub max_residence:    Option<Duration>,
+    // Fail the insert if broadcasting a local tx fails, the tx stays pooled
+    // either way
+    pub strict_broadcast: bool,
+
+    pub serial_verify_threshold: usize,
+    // Txs of a batch verified at the same time, unlimited if `None`
+    pub verify_concurrency:      Option<usize>,
+    pub propagation_mode:        PropagationMode,
+    // Calls of `ensure_order_txs` beyond this wait for a running one to finish
+    pub max_concurrent_ensure:   Option<usize>,
+    // Observe the encoded size of every accepted tx, it costs an encoding
+    pub tx_size_metrics:         bool,
+    // Remember why the latest evicted txs left the pool, disabled if `None`
+    pub eviction_archive_size:   Option<usize>,
+    // Remember the latest rejected inserts, disabled if `None`
+    pub rejection_log_size:      Option<usize>,
+    pub unprepared_package:      UnpreparedPackage,
+    // Keep the txs `get_full_txs` read from storage, disabled if `None`
+    pub storage_cache_size:      Option<usize>,
+    // `get_full_txs` fails without reading storage if more txs are neither
+    // pooled nor cached, unlimited if `None`
+    pub max_missing_txs:         Option<usize>,
+    pub subscription_capacity:   usize,
+    pub subscription_lag:        SubscriptionLag,
+    // Txs priced below this are rejected on insert, system script txs are
+    // exempt
+    pub min_gas_price:           U256,
+    // Once the pool is filled to this percent of its size the floor rises to
+    // the cheapest pooled gas price, no pressure floor if `None`
+    pub pressure_threshold:      Option<usize>,
+    // Accept system script txs with a zero gas limit, other txs with a zero
+    // gas limit are always rejected
+    pub zero_gas_system_script:  bool,
+    pub size_tracking:           SizeTracking,
+    // Txs of a sender beyond this are rejected unless they replace a pooled
+    // nonce, unlimited if `None`
+    pub max_txs_per_sender:      Option<usize>,
+    // System script txs beyond this are rejected, unlimited if `None`
+    pub max_system_script_txs:   Option<usize>,
+    // Txs of another chain are rejected before any adapter check, unchecked
+    // if `None`
+    pub chain_id:                Option<u64>,
+}
+
+impl MemPoolConfig {
+    pub fn new(pool_size: usize) -> Self {
+        MemPoolConfig {
+            pool_size,
+            max_residence: None,
+            strict_broadcast: false,
+
+            serial_verify_threshold: DEFAULT_SERIAL_VERIFY_THRESHOLD,
+            verify_concurrency: None,
+            propagation_mode: PropagationMode::default(),
+            max_concurrent_ensure: None,
+            tx_size_metrics: false,
+            eviction_archive_size: None,
+            rejection_log_size: None,
+            unprepared_package: UnpreparedPackage::default(),
+            storage_cache_size: None,
+            max_missing_txs: None,
+            subscription_capacity: DEFAULT_SUBSCRIPTION_CAPACITY,
+            subscription_lag: SubscriptionLag::default(),
+            min_gas_price: U256::zero(),
+            pressure_threshold: None,
+            zero_gas_system_script: false,
+            size_tracking: SizeTracking::default(),
+            max_txs_per_sender: None,
+            max_system_script_txs: None,
+            chain_id: None,
+        }
+    }
+
+    pub fn max_residence(mut self, max_residence: Option<Duration>) -> Self {
+        self.max_residence = max_residence;
+        self
+    }
+
+    pub fn strict_broadcast(mut self, strict: bool) -> Self {
+        self.strict_broadcast = strict;
+        self
+    }
+
+    pub fn serial_verify_threshold(mut self, threshold: usize) -> Self {
+        self.serial_verify_threshold = threshold;
+        self
+    }
+
+    pub fn verify_concurrency(mut self, concurrency: Option<usize>) -> Self {
+        self.verify_concurrency = concurrency.filter(|n| *n > 0);
+        self
+    }
+
+    pub fn propagation_mode(mut self, mode: PropagationMode) -> Self {
+        self.propagation_mode = mode;
+        self
+    }
+
+    pub fn max_concurrent_ensure(mut self, permits: Option<usize>) -> Self {
+        self.max_concurrent_ensure = permits.filter(|n| *n > 0);
+        self
+    }
+
+    pub fn tx_size_metrics(mut self, enable: bool) -> Self {
+        self.tx_size_metrics = enable;
+        self
+    }
+
+    pub fn eviction_archive_size(mut self, size: Option<usize>) -> Self {
+        self.eviction_archive_size = size.filter(|n| *n > 0);
+        self
+    }
+
+    pub fn rejection_log_size(mut self, size: Option<usize>) -> Self {
+        self.rejection_log_size = size.filter(|n| *n > 0);
+        self
+    }
+
+    pub fn unprepared_package(mut self, policy: UnpreparedPackage) -> Self {
+        self.unprepared_package = policy;
+        self
+    }
+
+    pub fn storage_cache_size(mut self, size: Option<usize>) -> Self {
+        self.storage_cache_size = size.filter(|n| *n > 0);
+        self
+    }
+
+    pub fn max_missing_txs(mut self, threshold: Option<usize>) -> Self {
+        self.max_missing_txs = threshold;
+        self
+    }
+
+    pub fn subscription_capacity(mut self, capacity: usize) -> Self {
+        self.subscription_capacity = capacity.max(1);
+        self
+    }
+
+    pub fn subscription_lag(mut self, policy: SubscriptionLag) -> Self {
+        self.subscription_lag = policy;
+        self
+    }
+
+    pub fn min_gas_price(mut self, price: U256) -> Self {
+        self.min_gas_price = price;
+        self
+    }
+
+    pub fn pressure_threshold(mut self, percent: Option<usize>) -> Self {
+        self.pressure_threshold = percent;
+        self
+    }
+
+    pub fn chain_id(mut self, chain_id: Option<u64>) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    pub fn zero_gas_system_script(mut self, allow: bool) -> Self {
+        self.zero_gas_system_script = allow;
+        self
+    }
+
+    pub fn size_tracking(mut self, policy: SizeTracking) -> Self {
+        self.size_tracking = policy;
+        self
+    }
+
+    pub fn max_txs_per_sender(mut self, limit: Option<usize>) -> Self {
+        self.max_txs_per_sender = limit;
+        self
+    }
+
+    pub fn max_system_script_txs(mut self, limit: Option<usize>) -> Self {
+        self.max_system_script_txs = limit;
+        self
+    }
+}
@@ -2,6 +2,8 @@
 #![allow(clippy::suspicious_else_formatting, clippy::mutable_key_type)]
 
 mod adapter;
+mod archive;
+mod config;
 mod context;
 mod pool;
 #[cfg(test)]
@@ -13,25 +15,85 @@ pub use adapter::message::{
     RPC_PULL_TXS, RPC_RESP_PULL_TXS, RPC_RESP_PULL_TXS_SYNC,
 };
 pub use adapter::DefaultMemPoolAdapter;
+pub use archive::{EvictReason, RejectionRecord};
+pub use config::{
+    MemPoolConfig, PropagationMode, SizeTracking, SubscriptionLag, UnpreparedPackage,
+};
+pub use pool::PoolStats;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
-use futures::future::try_join_all;
+use dashmap::{DashMap, DashSet};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use lru::LruCache;
+use parking_lot::{Mutex, RwLock};
 
 use common_apm::Instant;
 use core_executor::is_call_system_script;
+use protocol::codec::ProtocolCodec;
+use protocol::tokio::sync::{broadcast, Semaphore};
+use protocol::tokio::task::JoinHandle;
 use protocol::traits::{Context, MemPool, MemPoolAdapter};
-use protocol::types::{Hash, SignedTransaction, H160, H256, U256};
+use protocol::types::{Block, Hash, SignedTransaction, H160, H256, U256};
 use protocol::{async_trait, tokio, Display, ProtocolError, ProtocolErrorKind, ProtocolResult};
 
+use crate::archive::{EvictionArchive, GasPriceSamples, RejectionLog};
 use crate::context::TxContext;
 use crate::pool::PriorityPool;
+use crate::tx_wrapper::TxDigest;
+
+// The max number of txs recovering senders at the same time
+const RECOVER_CONCURRENCY: usize = 8;
+// The number of recent gas prices `gas_price_percentile` is taken over
+const GAS_PRICE_SAMPLES: usize = 1024;
+
+type EvictCallback = Box<dyn Fn(Hash, EvictReason) + Send + Sync>;
 
 pub struct MemPoolImpl<Adapter> {
-    pool:    PriorityPool,
-    adapter: Arc<Adapter>,
+    pool:      PriorityPool,
+    adapter:   Arc<Adapter>,
+    blocked:   RwLock<HashSet<H160>>,
+    // Txs held by an in-flight package, they are still pooled
+    reserved:  RwLock<HashSet<Hash>>,
+    // Txs being inserted by `insert_or_get`
+    inserting: DashSet<Hash>,
+
+    max_residence:    Option<Duration>,
+    strict_broadcast: bool,
+
+    serial_verify_threshold: usize,
+    // The max number of txs verified at the same time
+    verify_concurrency:      usize,
+    propagation_mode:        PropagationMode,
+    ensure_limit:            Option<Semaphore>,
+    tx_size_metrics:         bool,
+    eviction_archive:        Option<EvictionArchive>,
+    rejection_log:           Option<RejectionLog>,
+    gas_prices:              GasPriceSamples,
+    unprepared_package:      UnpreparedPackage,
+    // Whether `set_args` has been called
+    prepared:                AtomicBool,
+    // Set by `set_args`, unlimited before
+    max_tx_size:             AtomicUsize,
+    gas_limit:               AtomicU64,
+    storage_cache:           Option<Mutex<LruCache<Hash, SignedTransaction>>>,
+    max_missing_txs:         Option<usize>,
+    new_txs:                 broadcast::Sender<Hash>,
+    subscription_lag:        SubscriptionLag,
+    min_gas_price:           RwLock<U256>,
+    pressure_threshold:      Option<usize>,
+    zero_gas_system_script:  bool,
+    chain_id:                Option<u64>,
+    size_tracking:           SizeTracking,
+    // Encoded sizes of the pooled txs, only kept with `SizeTracking::Eager`
+    tx_sizes:                DashMap<Hash, usize>,
+    // Aborted on drop
+    reaper:                  Mutex<Option<JoinHandle<()>>>,
+    evict_callbacks:         RwLock<Vec<EvictCallback>>,
 }
 
 impl<Adapter> MemPoolImpl<Adapter>
@@ -42,10 +104,56 @@ where
         pool_size: usize,
         adapter: Adapter,
         initial_txs: Vec<SignedTransaction>,
+    ) -> Self {
+        Self::with_config(MemPoolConfig::new(pool_size), adapter, initial_txs).await
+    }
+
+    pub async fn with_config(
+        config: MemPoolConfig,
+        adapter: Adapter,
+        initial_txs: Vec<SignedTransaction>,
     ) -> Self {
         let mempool = MemPoolImpl {
-            pool:    PriorityPool::new(pool_size).await,
-            adapter: Arc::new(adapter),
+            pool:      PriorityPool::new(
+                config.pool_size,
+                config.max_txs_per_sender,
+                config.max_system_script_txs,
+            )
+            .await,
+            adapter:   Arc::new(adapter),
+            blocked:   RwLock::new(HashSet::new()),
+            reserved:  RwLock::new(HashSet::new()),
+            inserting: DashSet::new(),
+
+            max_residence:    config.max_residence,
+            strict_broadcast: config.strict_broadcast,
+
+            serial_verify_threshold: config.serial_verify_threshold,
+            verify_concurrency:      config.verify_concurrency.unwrap_or(usize::MAX),
+            propagation_mode:        config.propagation_mode,
+            ensure_limit:            config.max_concurrent_ensure.map(Semaphore::new),
+            tx_size_metrics:         config.tx_size_metrics,
+            eviction_archive:        config.eviction_archive_size.map(EvictionArchive::new),
+            rejection_log:           config.rejection_log_size.map(RejectionLog::new),
+            gas_prices:              GasPriceSamples::new(GAS_PRICE_SAMPLES),
+            unprepared_package:      config.unprepared_package,
+            prepared:                AtomicBool::new(false),
+            max_tx_size:             AtomicUsize::new(usize::MAX),
+            gas_limit:               AtomicU64::new(u64::MAX),
+            storage_cache:           config
+                .storage_cache_size
+                .map(|size| Mutex::new(LruCache::new(size))),
+            max_missing_txs:         config.max_missing_txs,
+            new_txs:                 broadcast::channel(config.subscription_capacity).0,
+            subscription_lag:        config.subscription_lag,
+            min_gas_price:           RwLock::new(config.min_gas_price),
+            pressure_threshold:      config.pressure_threshold,
+            zero_gas_system_script:  config.zero_gas_system_script,
+            chain_id:                config.chain_id,
+            size_tracking:           config.size_tracking,
+            tx_sizes:                DashMap::new(),
+            reaper:                  Mutex::new(None),
+            evict_callbacks:         RwLock::new(Vec::new()),
         };
 
         for tx in initial_txs.into_iter() {
@@ -61,14 +169,583 @@ where
         self.pool.len()
     }
 
+    /// The encoded size of a pooled tx, computed now unless it is tracked
+    /// eagerly.
+    pub fn tx_size(&self, hash: &Hash) -> Option<usize> {
+        if let Some(size) = self.tx_sizes.get(hash) {
+            return Some(*size);
+        }
+
+        let tx = self.pool.get_by_hash(hash)?;
+        tx.transaction.encode().ok().map(|bytes| bytes.len())
+    }
+
+    /// The lowest gas price a non system script tx may have to be accepted:
+    /// the static floor, raised to the cheapest pooled gas price while the
+    /// pool is filled over the pressure threshold.
+    pub fn min_acceptable_fee(&self) -> U256 {
+        let floor = *self.min_gas_price.read();
+        match self.pressure_threshold {
+            Some(percent) if self.pool.occupancy() >= percent => self
+                .pool
+                .cheapest_gas_price()
+                .map_or(floor, |price| price.max(floor)),
+            _ => floor,
+        }
+    }
+
+    /// Change the static gas price floor, the pooled txs are kept and only the
+    /// later inserts are checked against it.
+    pub fn set_min_gas_price(&self, price: U256) {
+        *self.min_gas_price.write() = price;
+    }
+
+    /// Insert txs which are already known to the network, e.g. restored from
+    /// a dump. They go through the checks of `insert_batch` and the results
+    /// are in input order, a pooled tx fails with `Dup`. Nothing is broadcast
+    /// unless `broadcast` is set.
+    pub async fn import_txs(
+        &self,
+        ctx: Context,
+        txs: Vec<SignedTransaction>,
+        broadcast: bool,
+    ) -> ProtocolResult<Vec<ProtocolResult<()>>> {
+        let res = self.insert_txs(ctx, &txs, false, broadcast).await?;
+        for (tx, r) in txs.iter().zip(res.iter()) {
+            if let Err(e) = r {
+                log::warn!(
+                    "[mempool]: import tx {:?} failed {:?}",
+                    tx.transaction.hash,
+                    e
+                );
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Insert many txs at once: they are verified in one pass and the valid
+    /// ones enter the pool under a single lock acquisition. A full pool
+    /// evicts like `insert`, the pooled txs and the repeated ones fail with
+    /// `Dup`. The results are in input order.
+    pub async fn insert_batch(
+        &self,
+        ctx: Context,
+        txs: Vec<SignedTransaction>,
+    ) -> ProtocolResult<Vec<ProtocolResult<()>>> {
+        let is_local = !ctx.is_network_origin_txs();
+        let res = self
+            .insert_txs(ctx.clone(), &txs, is_local, is_local)
+            .await?;
+        if !is_local && res.iter().any(|r| r.is_ok()) {
+            self.adapter.report_good(ctx);
+        }
+
+        Ok(res)
+    }
+
+    // The admission path of `insert` for many txs, see `insert_batch`.
+    async fn insert_txs(
+        &self,
+        ctx: Context,
+        txs: &[SignedTransaction],
+        local: bool,
+        broadcast: bool,
+    ) -> ProtocolResult<Vec<ProtocolResult<()>>> {
+        let mut seen = HashSet::with_capacity(txs.len());
+        let mut sizes = vec![None; txs.len()];
+        let mut res = txs
+            .iter()
+            .zip(sizes.iter_mut())
+            .map(|(tx, size)| {
+                let tx_hash = tx.transaction.hash;
+                if self.pool.contains(&tx_hash) || !seen.insert(tx_hash) {
+                    return Err(MemPoolError::Dup(tx_hash).into());
+                }
+
+                let is_system_script = is_call_system_script(&tx.transaction.unsigned.action);
+                *size = Some(self.check_admission(tx, is_system_script)?);
+                Ok(())
+            })
+            .collect::<Vec<ProtocolResult<()>>>();
+        for (tx, r) in txs.iter().zip(res.iter_mut()) {
+            if r.is_ok() && !is_call_system_script(&tx.transaction.unsigned.action) {
+                *r = self.check_nonce(ctx.clone(), tx).await;
+            }
+        }
+
+        let (idx, checked): (Vec<_>, Vec<_>) = txs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| res[*i].is_ok())
+            .map(|(i, tx)| (i, tx.clone()))
+            .unzip();
+        for (i, verified) in idx
+            .iter()
+            .zip(self.verify_txs(ctx.clone(), &checked, false).await?)
+        {
+            res[*i] = verified;
+        }
+
+        let (sys_txs, normal_txs): (Vec<_>, Vec<_>) = txs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| res[*i].is_ok())
+            .partition(|(_, tx)| is_call_system_script(&tx.transaction.unsigned.action));
+        for (i, tx) in sys_txs.into_iter() {
+            res[i] = self.pool.insert_system_script_tx(tx.clone());
+        }
+        let (idx, normal_txs): (Vec<_>, Vec<_>) = normal_txs
+            .into_iter()
+            .map(|(i, tx)| (i, tx.clone()))
+            .unzip();
+        let replaced = normal_txs
+            .iter()
+            .map(|tx| self.pool.conflicts_with(tx))
+            .collect::<Vec<_>>();
+        let hashes = normal_txs
+            .iter()
+            .map(|tx| tx.transaction.hash)
+            .collect::<Vec<_>>();
+        for (((i, inserted), tx_hash), replaced) in idx
+            .into_iter()
+            .zip(self.pool.insert_batch(normal_txs, local))
+            .zip(hashes.into_iter())
+            .zip(replaced.into_iter())
+        {
+            res[i] = inserted.map(|evicted| self.archive_displaced(tx_hash, evicted, replaced));
+        }
+
+        for ((tx, r), size) in txs.iter().zip(res.iter_mut()).zip(sizes.into_iter()) {
+            match r {
+                Ok(_) => {
+                    self.on_pooled(tx, size);
+                    self.record_gas_price(tx);
+                    // The tx stays pooled on a failed broadcast, as in `insert`
+                    if broadcast {
+                        *r = self.broadcast(ctx.clone(), tx.clone()).await;
+                    }
+                }
+                Err(e) => {
+                    if let Some(log) = self.rejection_log.as_ref() {
+                        log.record(tx.transaction.hash, e.to_string());
+                    }
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Recover the signer of every tx on the blocking pool without inserting
+    /// them, return the results in order. A tx signed by someone other than
+    /// its `sender` fails with `InvalidSender`.
+    pub async fn recover_senders(&self, txs: &[SignedTransaction]) -> Vec<ProtocolResult<H160>> {
+        stream::iter(txs.iter().cloned())
+            .map(|tx| async move {
+                tokio::task::spawn_blocking(move || recover_sender(&tx))
+                    .await
+                    .unwrap_or_else(|e| {
+                        log::error!("[mempool] recover sender error {:?}", e);
+                        Err(MemPoolError::VerifyBatchTransactions.into())
+                    })
+            })
+            .buffered(RECOVER_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    /// Insert the tx unless it is already pooled or being inserted by another
+    /// `insert_or_get` call. Only one of the concurrent calls with the same tx
+    /// gets `Inserted`.
+    pub async fn insert_or_get(
+        &self,
+        ctx: Context,
+        tx: SignedTransaction,
+    ) -> ProtocolResult<InsertOrGet> {
+        let tx_hash = tx.transaction.hash;
+        if self.pool.contains(&tx_hash) || !self.inserting.insert(tx_hash) {
+            return Ok(InsertOrGet::AlreadyPresent(tx_hash));
+        }
+
+        // The tx may be pooled between the check and the claim above
+        let res = if self.pool.contains(&tx_hash) {
+            Ok(InsertOrGet::AlreadyPresent(tx_hash))
+        } else {
+            self.insert(ctx, tx).await.map(|_| InsertOrGet::Inserted)
+        };
+
+        self.inserting.remove(&tx_hash);
+        res
+    }
+
+    /// Subscribe the hashes of the txs accepted by `insert`. Read it with
+    /// `next_subscribed` to apply the configured lag policy.
+    pub fn subscribe(&self) -> broadcast::Receiver<Hash> {
+        self.new_txs.subscribe()
+    }
+
+    /// Wait for the next subscribed tx hash, return `None` once the
+    /// subscription is over.
+    pub async fn next_subscribed(&self, rx: &mut broadcast::Receiver<Hash>) -> Option<Hash> {
+        loop {
+            match rx.recv().await {
+                Ok(hash) => return Some(hash),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    common_apm::metrics::mempool::MEMPOOL_SUBSCRIPTION_LAG_COUNTER.inc_by(n);
+                    log::warn!("[mempool]: subscriber lagged {} txs", n);
+
+                    if self.subscription_lag == SubscriptionLag::Close {
+                        return None;
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// The number of pooled txs which are not reserved by a package.
+    pub fn available_len(&self) -> usize {
+        let reserved = self.reserved.read();
+        let reserved = reserved
+            .iter()
+            .filter(|hash| self.pool.contains(hash))
+            .count();
+        self.len().saturating_sub(reserved)
+    }
+
+    /// Mark the pooled txs as held by an in-flight package. The reservations
+    /// are released by `release` or once the txs leave the pool on flush.
+    pub fn reserve(&self, tx_hashes: &[Hash]) {
+        let mut reserved = self.reserved.write();
+        for hash in tx_hashes.iter().filter(|hash| self.pool.contains(hash)) {
+            reserved.insert(*hash);
+        }
+    }
+
+    pub fn release(&self, tx_hashes: &[Hash]) {
+        let mut reserved = self.reserved.write();
+        for hash in tx_hashes.iter() {
+            reserved.remove(hash);
+        }
+    }
+
     pub fn co_queue_len(&self) -> usize {
         self.pool.co_queue_len()
     }
 
+    pub fn system_script_queue_len(&self) -> usize {
+        self.pool.system_script_queue_len()
+    }
+
+    pub fn distinct_senders(&self) -> usize {
+        self.pool.distinct_senders()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// All the pending txs of the address, ascending by nonce. An address
+    /// without pooled txs gets an empty vec.
+    pub async fn get_txs_by_address(
+        &self,
+        _ctx: Context,
+        address: H160,
+    ) -> ProtocolResult<Vec<SignedTransaction>> {
+        Ok(self.pool.get_txs_by_address(address))
+    }
+
+    /// All the pooled txs split into `(pending, queued)` by sender. The txs
+    /// following the on-chain account nonce without a gap are pending, the
+    /// others are queued. The txs are ascending by nonce.
+    pub async fn content(
+        &self,
+        ctx: Context,
+    ) -> ProtocolResult<(
+        HashMap<H160, Vec<SignedTransaction>>,
+        HashMap<H160, Vec<SignedTransaction>>,
+    )> {
+        let mut pending = HashMap::new();
+        let mut queued = HashMap::new();
+
+        for sender in self.pool.senders().into_iter() {
+            let nonce = self.adapter.get_account_nonce(ctx.clone(), sender).await?;
+            let (executable, rest) = split_executable(self.pool.get_txs_by_address(sender), nonce);
+            if !executable.is_empty() {
+                pending.insert(sender, executable);
+            }
+            if !rest.is_empty() {
+                queued.insert(sender, rest);
+            }
+        }
+
+        Ok((pending, queued))
+    }
+
+    /// The nonce the next tx of the address should take: the first nonce
+    /// after the account nonce, advanced over the pooled txs which follow it
+    /// without a gap.
+    pub async fn get_pending_nonce(&self, ctx: Context, address: H160) -> ProtocolResult<U256> {
+        let nonce = self.adapter.get_account_nonce(ctx, address).await?;
+        let (executable, _) = split_executable(self.pool.get_txs_by_address(address), nonce);
+        Ok(next_nonce(nonce) + executable.len())
+    }
+
+    /// The numbers of `(pending, queued)` txs of the address, split against
+    /// the on-chain account nonce like `content`.
+    pub async fn tx_counts_by_address(
+        &self,
+        ctx: Context,
+        address: H160,
+    ) -> ProtocolResult<(usize, usize)> {
+        let nonce = self.adapter.get_account_nonce(ctx, address).await?;
+        Ok(self.pool.tx_counts_by_address(address, next_nonce(nonce)))
+    }
+
+    pub fn oldest_txs(&self, n: usize) -> Vec<(Hash, Duration)> {
+        self.pool.oldest_txs(n)
+    }
+
+    /// The hashes of all the pooled txs, a snapshot for `diff_against`.
+    pub fn hashes(&self) -> HashSet<Hash> {
+        self.pool.hashes()
+    }
+
+    /// Empty the whole pool at once, e.g. before a shutdown or a resync.
+    /// Return the number of removed txs.
+    pub fn clear(&self) -> usize {
+        let removed = self.pool.clear();
+        self.reserved.write().clear();
+        self.tx_sizes.clear();
+        self.update_sender_depth();
+        self.notify_evicted(&removed, EvictReason::Flushed);
+        removed.len()
+    }
+
+    /// Every pooled tx, to be persisted on shutdown and passed back as the
+    /// `initial_txs` of the next start.
+    pub fn dump(&self) -> Vec<SignedTransaction> {
+        self.pool.dump()
+    }
+
+    /// Compare the pool with a snapshot of hashes, return the hashes pooled
+    /// since the snapshot and the ones gone since, both sorted.
+    pub fn diff_against(&self, other: &HashSet<Hash>) -> (Vec<Hash>, Vec<Hash>) {
+        let current = self.pool.hashes();
+        let mut added = current.difference(other).copied().collect::<Vec<_>>();
+        let mut removed = other.difference(&current).copied().collect::<Vec<_>>();
+        added.sort();
+        removed.sort();
+        (added, removed)
+    }
+
+    pub fn top_senders(&self, n: usize) -> Vec<(H160, usize)> {
+        self.pool.top_senders(n)
+    }
+
+    /// The most txs pooled by a single sender, also exported as the
+    /// `axon_mempool_max_sender_depth` gauge.
+    pub fn max_sender_depth(&self) -> usize {
+        self.pool.max_sender_depth()
+    }
+
+    /// The total tip a package would pay to the proposer, estimated as
+    /// `min(max_priority_fee, gas_price - base_fee) * gas_limit` per tx. Txs
+    /// not in the pool are ignored.
+    pub fn package_revenue(&self, hashes: &[Hash], base_fee: U256) -> U256 {
+        hashes
+            .iter()
+            .filter_map(|hash| self.pool.get_by_hash(hash))
+            .fold(U256::zero(), |acc, stx| {
+                // The tip is what the tx pays on top of the base fee when
+                // packaged in that order
+                let tip = TxDigest::from(&stx)
+                    .effective_gas_price(base_fee)
+                    .saturating_sub(base_fee);
+                acc.saturating_add(tip.saturating_mul(stx.transaction.unsigned.gas_limit))
+            })
+    }
+
+    pub fn conflicts_with(&self, tx: &SignedTransaction) -> Vec<Hash> {
+        self.pool.conflicts_with(tx)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
+    /// Reject the txs sent by the address from now on. The txs already in pool
+    /// are purged on the next flush, or immediately by `remove_by_sender`.
+    pub fn add_blocked(&self, address: H160) {
+        self.blocked.write().insert(address);
+    }
+
+    pub fn remove_blocked(&self, address: &H160) -> bool {
+        self.blocked.write().remove(address)
+    }
+
+    pub fn is_blocked(&self, address: &H160) -> bool {
+        self.blocked.read().contains(address)
+    }
+
+    pub fn remove_by_sender(&self, address: &H160) -> usize {
+        let removed = self.pool.remove_by_sender(address);
+        let count = removed.len();
+        self.update_sender_depth();
+        self.forget_sizes(&removed);
+        self.archive_evicted(removed, EvictReason::SenderRemoved);
+        count
+    }
+
+    /// Drop a single tx from the pool, e.g. on cancellation. Return whether it
+    /// was pooled.
+    pub fn remove_tx(&self, tx_hash: &Hash) -> bool {
+        if !self.pool.remove(tx_hash) {
+            return false;
+        }
+
+        self.update_sender_depth();
+        self.forget_sizes(std::slice::from_ref(tx_hash));
+        self.archive_evicted(vec![*tx_hash], EvictReason::Removed);
+        true
+    }
+
+    /// Remove the txs of a block committed without going through `flush`,
+    /// e.g. by fast sync, together with the pooled txs whose nonce is now
+    /// stale for the senders of the block. Return the number of removed txs.
+    pub async fn reconcile_committed_block(
+        &self,
+        ctx: Context,
+        block: &Block,
+    ) -> ProtocolResult<usize> {
+        let mut committed = Vec::with_capacity(block.tx_hashes.len());
+        let mut missing = Vec::new();
+        for hash in block.tx_hashes.iter() {
+            match self.pool.get_by_hash(hash) {
+                Some(tx) => committed.push(tx),
+                None => missing.push(*hash),
+            }
+        }
+
+        if !missing.is_empty() {
+            let txs = self
+                .adapter
+                .get_transactions_from_storage(ctx, Some(block.header.number), &missing)
+                .await?;
+            committed.extend(txs.into_iter().flatten());
+        }
+
+        let mut max_nonces: HashMap<H160, U256> = HashMap::new();
+        for tx in committed.iter() {
+            let nonce = tx.transaction.unsigned.nonce;
+            max_nonces
+                .entry(tx.sender)
+                .and_modify(|max| *max = (*max).max(nonce))
+                .or_insert(nonce);
+        }
+
+        let mut removed = self.pool.remove_by_hashes(&block.tx_hashes);
+        for (sender, nonce) in max_nonces.into_iter() {
+            removed.extend(self.pool.remove_stale_nonces(&sender, nonce));
+        }
+
+        self.update_sender_depth();
+        self.forget_sizes(&removed);
+        self.reserved
+            .write()
+            .retain(|hash| self.pool.contains(hash));
+        self.notify_evicted(&removed, EvictReason::Flushed);
+
+        Ok(removed.len())
+    }
+
+    /// Drop the txs which stay in pool longer than the configured max
+    /// residence, return the number of dropped txs.
+    pub fn sweep_expired(&self) -> usize {
+        let max_residence = match self.max_residence {
+            Some(max_residence) => max_residence,
+            None => return 0,
+        };
+
+        let removed = self.pool.remove_expired(max_residence);
+        let count = removed.len();
+        self.update_sender_depth();
+        self.forget_sizes(&removed);
+        self.archive_evicted(removed, EvictReason::Expired);
+        if count > 0 {
+            log::info!("[mempool]: sweep {} txs out of max residence", count);
+            common_apm::metrics::mempool::MEMPOOL_RESIDENCE_EXPIRED_COUNTER.inc_by(count as u64);
+        }
+
+        count
+    }
+
+    /// Run `sweep_expired` every `interval` on a background task, replacing
+    /// the previous reaper if any. The task stops once the mempool is dropped.
+    pub fn spawn_reaper(self: &Arc<Self>, interval: Duration) {
+        let weak: Weak<Self> = Arc::downgrade(self);
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let mempool = match weak.upgrade() {
+                    Some(mempool) => mempool,
+                    None => return,
+                };
+                mempool.sweep_expired();
+            }
+        });
+
+        if let Some(old) = self.reaper.lock().replace(handle) {
+            old.abort();
+        }
+    }
+
+    /// Why the tx was evicted, if it is still in the eviction archive. Always
+    /// `None` when the archive is disabled.
+    pub async fn eviction_reason(&self, hash: &Hash) -> Option<EvictReason> {
+        self.eviction_archive
+            .as_ref()
+            .and_then(|archive| archive.get(hash))
+    }
+
+    fn forget_sizes(&self, hashes: &[Hash]) {
+        for hash in hashes.iter() {
+            self.tx_sizes.remove(hash);
+        }
+    }
+
+    /// The latest `n` rejected inserts, the newest first. Always empty when
+    /// the rejection log is disabled.
+    pub fn recent_rejections(&self, n: usize) -> Vec<RejectionRecord> {
+        self.rejection_log
+            .as_ref()
+            .map(|log| log.recent(n))
+            .unwrap_or_default()
+    }
+
+    /// Register a callback run for every tx leaving the pool other than by
+    /// package, e.g. to emit the `dropped` events of a subscription. It runs
+    /// on the removing task, so it should be quick and must not register
+    /// another callback.
+    pub fn on_evict(&self, cb: impl Fn(Hash, EvictReason) + Send + Sync + 'static) {
+        self.evict_callbacks.write().push(Box::new(cb));
+    }
+
+    fn notify_evicted(&self, hashes: &[Hash], reason: EvictReason) {
+        let callbacks = self.evict_callbacks.read();
+        for hash in hashes.iter() {
+            callbacks.iter().for_each(|cb| cb(*hash, reason));
+        }
+    }
+
+    fn archive_evicted(&self, hashes: Vec<Hash>, reason: EvictReason) {
+        self.notify_evicted(&hashes, reason);
+        if let Some(archive) = self.eviction_archive.as_ref() {
+            archive.record(hashes, reason);
+        }
+    }
+
     pub fn get_adapter(&self) -> &Adapter {
         &self.adapter
     }
@@ -90,7 +767,77 @@ where
         self.adapter
             .check_storage_exist(ctx.clone(), &stx.transaction.hash)
             .await?;
-        self.pool.insert(stx)
+
+        if is_call_system_script(&stx.transaction.unsigned.action) {
+            self.pool.insert_system_script_tx(stx)
+        } else {
+            self.pool.insert(stx, false)
+        }
+    }
+
+    /// Insert a tx a trusted source, such as the restore of the txs of a
+    /// reorged block, has validated already. The adapter checks on the
+    /// signature, the account and the storage are skipped, so the caller is
+    /// responsible for the tx being valid. The duplicates and the checks of
+    /// `check_admission`, such as the chain id and the size, still apply.
+    pub fn insert_trusted(&self, tx: SignedTransaction) -> ProtocolResult<()> {
+        let tx_hash = tx.transaction.hash;
+        let is_system_script = is_call_system_script(&tx.transaction.unsigned.action);
+        let size = self.check_admission(&tx, is_system_script)?;
+        if self.pool.contains(&tx_hash) {
+            return Err(MemPoolError::Dup(tx_hash).into());
+        }
+
+        if is_system_script {
+            self.pool.insert_system_script_tx(tx.clone())?;
+        } else {
+            self.pool.insert(tx.clone(), false)?;
+        }
+
+        self.on_pooled(&tx, Some(size));
+        Ok(())
+    }
+
+    /// Run the checks of `insert` on the tx without pooling or broadcasting
+    /// it, so a wallet can tell in advance whether it would be accepted.
+    pub async fn check_tx(&self, ctx: Context, tx: &SignedTransaction) -> ProtocolResult<()> {
+        let is_system_script = is_call_system_script(&tx.transaction.unsigned.action);
+        self.check_admission(tx, is_system_script)?;
+
+        if !is_system_script {
+            self.check_nonce(ctx.clone(), tx).await?;
+        }
+        self.adapter.check_authorization(ctx.clone(), tx).await?;
+        self.adapter.check_transaction(ctx.clone(), tx).await?;
+        self.adapter
+            .check_storage_exist(ctx, &tx.transaction.hash)
+            .await
+    }
+
+    // The checks of `insert_tx` which need no adapter, returning the encoded
+    // size of the tx.
+    fn check_admission(
+        &self,
+        tx: &SignedTransaction,
+        is_system_script: bool,
+    ) -> ProtocolResult<usize> {
+        if self.is_blocked(&tx.sender) {
+            return Err(MemPoolError::BlockedSender(tx.sender).into());
+        }
+        self.check_chain_id(tx)?;
+        let size = self.check_size(tx)?;
+
+        if self.pool.reach_limit()
+            && (is_system_script
+                || !self
+                    .pool
+                    .outbids_cheapest(tx.transaction.unsigned.gas_price))
+        {
+            return Err(MemPoolError::ReachLimit(self.pool.pool_size()).into());
+        }
+
+        self.check_gas(tx, is_system_script)?;
+        Ok(size)
     }
 
     async fn insert_tx(
@@ -99,27 +846,187 @@ where
         tx: SignedTransaction,
         is_system_script: bool,
     ) -> ProtocolResult<()> {
-        let tx_hash = &tx.transaction.hash;
-        if self.pool.reach_limit() {
-            return Err(MemPoolError::ReachLimit(self.pool.pool_size()).into());
-        }
+        let size = self.check_admission(&tx, is_system_script)?;
 
+        let tx_hash = tx.transaction.hash;
+        let is_local = !ctx.is_network_origin_txs();
+        let eager = is_local && self.propagation_mode == PropagationMode::Eager;
+
+        if !is_system_script {
+            self.check_nonce(ctx.clone(), &tx).await?;
+        }
         self.adapter.check_authorization(ctx.clone(), &tx).await?;
+        if eager {
+            self.broadcast(ctx.clone(), tx.clone()).await?;
+        }
+
         self.adapter.check_transaction(ctx.clone(), &tx).await?;
         self.adapter
-            .check_storage_exist(ctx.clone(), tx_hash)
+            .check_storage_exist(ctx.clone(), &tx_hash)
             .await?;
 
         if is_system_script {
-            self.pool.insert_system_script_tx(tx.clone())?;
+            self.pool.insert_system_script_tx(tx.clone()).map_err(|e| {
+                log::error!("[mempool]: system script tx {:?} rejected {:?}", tx_hash, e);
+                e
+            })?;
         } else {
-            self.pool.insert(tx.clone())?;
+            let replaced = self.pool.conflicts_with(&tx);
+            let evicted = self.pool.insert_with_eviction(tx.clone(), is_local)?;
+            self.archive_displaced(tx_hash, evicted, replaced);
         }
 
-        if !ctx.is_network_origin_txs() {
-            self.adapter.broadcast_tx(ctx, tx).await?;
-        } else {
+        self.on_pooled(&tx, Some(size));
+        self.record_gas_price(&tx);
+
+        if !is_local {
             self.adapter.report_good(ctx);
+        } else if !eager {
+            self.broadcast(ctx, tx).await?;
+        }
+
+        Ok(())
+    }
+
+    // A nonce up to the account's is already used, a higher one than the next
+    // is queued until the gap is filled.
+    async fn check_nonce(&self, ctx: Context, tx: &SignedTransaction) -> ProtocolResult<()> {
+        let current = self.adapter.get_account_nonce(ctx, tx.sender).await?;
+        let tx_nonce = tx.transaction.unsigned.nonce;
+        if is_stale_nonce(current, tx_nonce) {
+            return Err(MemPoolError::InvalidNonce {
+                current:  u64::try_from(current).unwrap_or(u64::MAX),
+                tx_nonce: u64::try_from(tx_nonce).unwrap_or(u64::MAX),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    fn check_size(&self, tx: &SignedTransaction) -> ProtocolResult<usize> {
+        let size = tx.transaction.encode()?.len();
+        let max_tx_size = self.max_tx_size.load(Ordering::Acquire);
+        if size > max_tx_size {
+            return Err(MemPoolError::ExceedSizeLimit {
+                tx_hash: tx.transaction.hash,
+                max_tx_size,
+                size,
+            }
+            .into());
+        }
+
+        Ok(size)
+    }
+
+    fn check_chain_id(&self, tx: &SignedTransaction) -> ProtocolResult<()> {
+        match self.chain_id {
+            Some(chain_id) if chain_id != tx.transaction.chain_id => {
+                Err(MemPoolError::WrongChain(tx.transaction.hash).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_gas(&self, tx: &SignedTransaction, is_system_script: bool) -> ProtocolResult<()> {
+        let tx_hash = tx.transaction.hash;
+
+        // Such a tx can never be executed
+        if tx.transaction.unsigned.gas_limit.is_zero()
+            && !(is_system_script && self.zero_gas_system_script)
+        {
+            return Err(MemPoolError::ZeroGasLimit(tx_hash).into());
+        }
+
+        // Nor can a tx over the block gas limit be packaged
+        let gas_limit_config = self.gas_limit.load(Ordering::Acquire);
+        if tx.transaction.unsigned.gas_limit > gas_limit_config.into() {
+            return Err(MemPoolError::ExceedGasLimit {
+                tx_hash,
+                gas_limit_config,
+                gas_limit_tx: u64::try_from(tx.transaction.unsigned.gas_limit).unwrap_or(u64::MAX),
+            }
+            .into());
+        }
+
+        let tx_gas_price = tx.transaction.unsigned.gas_price;
+        let min_gas_price = self.min_acceptable_fee();
+        if !is_system_script && tx_gas_price < min_gas_price {
+            return Err(MemPoolError::Underpriced {
+                tx_gas_price,
+                min_gas_price,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// The `p` percentile, in [0, 100], of the gas prices of the recently
+    /// accepted txs, system script txs left out.
+    pub fn gas_price_percentile(&self, p: f64) -> Option<U256> {
+        self.gas_prices.percentile(p)
+    }
+
+    fn record_gas_price(&self, tx: &SignedTransaction) {
+        if is_call_system_script(&tx.transaction.unsigned.action) {
+            return;
+        }
+
+        let price = tx.transaction.unsigned.gas_price;
+        self.gas_prices.record(price);
+        common_apm::metrics::mempool::MEMPOOL_ACCEPTED_GAS_PRICE
+            .observe(u128::try_from(price).map_or(f64::MAX, |p| p as f64));
+    }
+
+    // The encoded `size` is computed here if the caller hasn't.
+    fn on_pooled(&self, tx: &SignedTransaction, size: Option<usize>) {
+        let tx_hash = tx.transaction.hash;
+
+        // Fails only if there is no subscriber
+        let _ = self.new_txs.send(tx_hash);
+        self.update_sender_depth();
+
+        let eager_size = self.size_tracking == SizeTracking::Eager;
+        if eager_size || self.tx_size_metrics {
+            let size = size.or_else(|| tx.transaction.encode().ok().map(|bytes| bytes.len()));
+            if let Some(size) = size {
+                if eager_size {
+                    self.tx_sizes.insert(tx_hash, size);
+                }
+                if self.tx_size_metrics {
+                    common_apm::metrics::mempool::MEMPOOL_TX_SIZE_HISTOGRAM.observe(size as f64);
+                }
+            }
+        }
+    }
+
+    fn update_sender_depth(&self) {
+        common_apm::metrics::mempool::MEMPOOL_SENDER_QUEUE_DEPTH
+            .set(self.pool.max_sender_depth() as i64);
+    }
+
+    // Archive the txs which left the pool to make room for the tx.
+    fn archive_displaced(&self, tx_hash: Hash, evicted: Option<Hash>, replaced: Vec<Hash>) {
+        if let Some(evicted) = evicted {
+            log::info!("[mempool]: evict tx {:?} for {:?}", evicted, tx_hash);
+            self.forget_sizes(&[evicted]);
+            self.archive_evicted(vec![evicted], EvictReason::PoolFull);
+        }
+        // The replaced tx stays in the map until the next flush
+        self.archive_evicted(replaced, EvictReason::Replaced);
+    }
+
+    async fn broadcast(&self, ctx: Context, tx: SignedTransaction) -> ProtocolResult<()> {
+        let tx_hash = tx.transaction.hash;
+
+        if let Err(e) = self.adapter.broadcast_tx(ctx, tx).await {
+            common_apm::metrics::mempool::MEMPOOL_BROADCAST_FAILURE_COUNTER.inc();
+            log::warn!("[mempool]: broadcast tx {:?} failed {:?}", tx_hash, e);
+
+            if self.strict_broadcast {
+                return Err(e);
+            }
         }
 
         Ok(())
@@ -133,26 +1040,10 @@ where
         let inst = Instant::now();
         let len = txs.len();
 
-        let futs = txs
-            .into_iter()
-            .map(|tx| {
-                let adapter = Arc::clone(&self.adapter);
-                let ctx = ctx.clone();
-
-                tokio::spawn(async move {
-                    adapter.check_authorization(ctx.clone(), &tx).await?;
-                    adapter.check_transaction(ctx.clone(), &tx).await?;
-                    adapter
-                        .check_storage_exist(ctx.clone(), &tx.transaction.hash)
-                        .await
-                })
-            })
-            .collect::<Vec<_>>();
-
-        try_join_all(futs).await.map_err(|e| {
+        if let Err(e) = self.verify_txs(ctx, &txs, true).await {
             log::error!("[mempool] verify batch txs error {:?}", e);
-            MemPoolError::VerifyBatchTransactions
-        })?;
+            return Err(MemPoolError::VerifyBatchTransactions.into());
+        }
 
         log::info!(
             "[mempool] verify txs done, size {:?} cost {:?}",
@@ -162,34 +1053,95 @@ where
         Ok(())
     }
 
-    #[cfg(test)]
-    pub fn get_tx_cache(&self) -> &PriorityPool {
-        &self.pool
+    /// Verify every tx with the adapter checks and return the results in
+    /// order, a bad tx doesn't fail the others. Unlike `ensure_order_txs`
+    /// nothing is inserted.
+    pub async fn verify_batch(
+        &self,
+        ctx: Context,
+        txs: Vec<SignedTransaction>,
+    ) -> Vec<ProtocolResult<()>> {
+        match self.verify_txs(ctx, &txs, false).await {
+            Ok(res) => res,
+            Err(_) => txs
+                .iter()
+                .map(|_| Err(MemPoolError::VerifyBatchTransactions.into()))
+                .collect(),
+        }
     }
-}
 
-#[async_trait]
-impl<Adapter> MemPool for MemPoolImpl<Adapter>
-where
-    Adapter: MemPoolAdapter + 'static,
-{
-    async fn insert(&self, ctx: Context, tx: SignedTransaction) -> ProtocolResult<()> {
-        let is_call_system_script = is_call_system_script(&tx.transaction.unsigned.action);
-        self.insert_tx(ctx, tx, is_call_system_script).await
+    /// Verify every tx and return the results in order. Batches smaller than
+    /// the serial verify threshold are verified on the current task, the
+    /// others on at most `verify_concurrency` spawned tasks at once. With
+    /// `fail_fast` no more txs are verified after the first bad one, whose
+    /// error is returned.
+    async fn verify_txs(
+        &self,
+        ctx: Context,
+        txs: &[SignedTransaction],
+        fail_fast: bool,
+    ) -> ProtocolResult<Vec<ProtocolResult<()>>> {
+        if txs.len() < self.serial_verify_threshold {
+            let mut res = Vec::with_capacity(txs.len());
+            for tx in txs.iter() {
+                match verify_tx(self.adapter.as_ref(), ctx.clone(), tx).await {
+                    Err(e) if fail_fast => return Err(e),
+                    r => res.push(r),
+                }
+            }
+            return Ok(res);
+        }
+
+        // A task is spawned only when the buffer has room for it
+        let mut res = stream::iter(txs.iter().cloned().enumerate())
+            .map(|(i, tx)| {
+                let adapter = Arc::clone(&self.adapter);
+                let ctx = ctx.clone();
+
+                tokio::spawn(async move { (i, verify_tx(adapter.as_ref(), ctx, &tx).await) })
+            })
+            .buffer_unordered(self.verify_concurrency)
+            .map(|joined| {
+                let (i, r) = joined.map_err(|e| {
+                    log::error!("[mempool] verify batch txs error {:?}", e);
+                    ProtocolError::from(MemPoolError::VerifyBatchTransactions)
+                })?;
+                match r {
+                    Err(e) if fail_fast => Err(e),
+                    r => Ok((i, r)),
+                }
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        res.sort_unstable_by_key(|(i, _)| *i);
+        Ok(res.into_iter().map(|(_, r)| r).collect())
     }
 
-    async fn package(
+    /// Package the txs ranked by their effective gas price at `base_fee`, so
+    /// that a 1559 tx is worth its priority fee over the base fee capped by
+    /// its max fee. Without a base fee this is `package`.
+    pub async fn package_with_base_fee(
         &self,
         _ctx: Context,
         gas_limit: U256,
         tx_num_limit: u64,
+        base_fee: Option<U256>,
     ) -> ProtocolResult<Vec<Hash>> {
+        if !self.prepared.load(Ordering::Acquire)
+            && self.unprepared_package == UnpreparedPackage::Reject
+        {
+            return Err(MemPoolError::Unprepared.into());
+        }
+
         log::info!(
             "[core_mempool]: {:?} txs in map while package",
             self.pool.len(),
         );
         let inst = Instant::now();
-        let txs = self.pool.package(gas_limit, tx_num_limit as usize);
+        let txs = self
+            .pool
+            .package(gas_limit, tx_num_limit as usize, base_fee);
 
         common_apm::metrics::mempool::MEMPOOL_PACKAGE_SIZE_VEC_STATIC
             .package
@@ -200,19 +1152,130 @@ where
         Ok(txs)
     }
 
+    /// Look the txs up in the pool and then in the storage cache, returning
+    /// the found txs and the hashes of the missing ones.
+    fn get_cached_txs(&self, tx_hashes: &[Hash]) -> (Vec<SignedTransaction>, Vec<Hash>) {
+        let mut missing_hashes = vec![];
+        let mut full_txs = Vec::with_capacity(tx_hashes.len());
+
+        for tx_hash in tx_hashes.iter() {
+            if let Some(tx) = self.pool.get_by_hash(tx_hash) {
+                full_txs.push(tx);
+            } else {
+                missing_hashes.push(*tx_hash);
+            }
+        }
+
+        if let Some(cache) = self.storage_cache.as_ref() {
+            let mut cache = cache.lock();
+            missing_hashes.retain(|hash| match cache.get(hash) {
+                Some(tx) => {
+                    full_txs.push(tx.clone());
+                    false
+                }
+                None => true,
+            });
+        }
+
+        (full_txs, missing_hashes)
+    }
+
+    /// Read the txs from storage in order, the found ones fill the storage
+    /// cache.
+    async fn get_storage_txs(
+        &self,
+        ctx: Context,
+        height: Option<u64>,
+        tx_hashes: &[Hash],
+    ) -> ProtocolResult<Vec<Option<SignedTransaction>>> {
+        let storage_txs = self
+            .adapter
+            .get_transactions_from_storage(ctx, height, tx_hashes)
+            .await?;
+
+        if let Some(cache) = self.storage_cache.as_ref() {
+            let mut cache = cache.lock();
+            for tx in storage_txs.iter().flatten() {
+                cache.put(tx.transaction.hash, tx.clone());
+            }
+        }
+
+        Ok(storage_txs)
+    }
+
+    #[cfg(test)]
+    pub fn get_tx_cache(&self) -> &PriorityPool {
+        &self.pool
+    }
+}
+
+#[async_trait]
+impl<Adapter> MemPool for MemPoolImpl<Adapter>
+where
+    Adapter: MemPoolAdapter + 'static,
+{
+    async fn insert(&self, ctx: Context, tx: SignedTransaction) -> ProtocolResult<()> {
+        let is_call_system_script = is_call_system_script(&tx.transaction.unsigned.action);
+        let tx_hash = tx.transaction.hash;
+
+        let res = self.insert_tx(ctx, tx, is_call_system_script).await;
+        if let (Err(e), Some(log)) = (res.as_ref(), self.rejection_log.as_ref()) {
+            log.record(tx_hash, e.to_string());
+        }
+        res
+    }
+
+    async fn package(
+        &self,
+        ctx: Context,
+        gas_limit: U256,
+        tx_num_limit: u64,
+    ) -> ProtocolResult<Vec<Hash>> {
+        self.package_with_base_fee(ctx, gas_limit, tx_num_limit, None)
+            .await
+    }
+
     async fn flush(&self, _ctx: Context, tx_hashes: &[Hash]) -> ProtocolResult<()> {
         log::info!(
             "[core_mempool]: flush mempool with {:?} tx_hashes",
             tx_hashes.len(),
         );
-        let nonce_check = |tx: &SignedTransaction| -> bool {
-            let rt = tokio::runtime::Handle::current();
-            tokio::task::block_in_place(|| {
-                rt.block_on(self.adapter.check_authorization(Context::new(), tx))
-                    .is_ok()
+        // Check the retained txs before the pool is locked, at most
+        // `verify_concurrency` at once. A tx inserted in the meantime has just
+        // passed the same check.
+        let committed = tx_hashes.iter().collect::<HashSet<_>>();
+        let retained = self
+            .pool
+            .normal_txs()
+            .into_iter()
+            .filter(|tx| !committed.contains(&tx.transaction.hash))
+            .collect::<Vec<_>>();
+        let unauthorized = stream::iter(retained.iter())
+            .map(|tx| async move {
+                self.adapter
+                    .check_authorization(Context::new(), tx)
+                    .await
+                    .err()
+                    .map(|_| tx.transaction.hash)
             })
+            .buffer_unordered(self.verify_concurrency)
+            .filter_map(|hash| async move { hash })
+            .collect::<HashSet<_>>()
+            .await;
+
+        let nonce_check = |tx: &SignedTransaction| -> bool {
+            !self.is_blocked(&tx.sender) && !unauthorized.contains(&tx.transaction.hash)
         };
-        self.pool.flush(tx_hashes, nonce_check);
+        let removed = self.pool.flush(tx_hashes, nonce_check);
+        self.update_sender_depth();
+        self.reserved
+            .write()
+            .retain(|hash| self.pool.contains(hash));
+        self.tx_sizes.retain(|hash, _| self.pool.contains(hash));
+        if let Some(cache) = self.storage_cache.as_ref() {
+            cache.lock().clear();
+        }
+        self.notify_evicted(&removed, EvictReason::Flushed);
         Ok(())
     }
 
@@ -225,27 +1288,23 @@ where
         tx_hashes: &[Hash],
     ) -> ProtocolResult<Vec<SignedTransaction>> {
         let len = tx_hashes.len();
-        let mut missing_hashes = vec![];
-        let mut full_txs = Vec::with_capacity(len);
+        let (mut full_txs, missing_hashes) = self.get_cached_txs(tx_hashes);
 
-        for tx_hash in tx_hashes.iter() {
-            if let Some(tx) = self.pool.get_by_hash(tx_hash) {
-                full_txs.push(tx);
-            } else {
-                missing_hashes.push(*tx_hash);
+        if let Some(threshold) = self.max_missing_txs {
+            if missing_hashes.len() > threshold {
+                return Err(MemPoolError::TooManyMissing {
+                    missing: missing_hashes.len(),
+                    threshold,
+                }
+                .into());
             }
         }
 
         // for push txs when local mempool is flushed, but the remote node still fetch
         // full block
         if !missing_hashes.is_empty() {
-            full_txs.extend(
-                self.adapter
-                    .get_transactions_from_storage(ctx, height, &missing_hashes)
-                    .await?
-                    .into_iter()
-                    .flatten(),
-            );
+            let storage_txs = self.get_storage_txs(ctx, height, &missing_hashes).await?;
+            full_txs.extend(storage_txs.into_iter().flatten());
         }
 
         if full_txs.len() != len {
@@ -259,6 +1318,31 @@ where
         }
     }
 
+    /// Resolve the txs like `get_full_txs` but return the unresolved hashes
+    /// along with the found txs rather than failing on them.
+    async fn get_full_txs_partial(
+        &self,
+        ctx: Context,
+        height: Option<u64>,
+        tx_hashes: &[Hash],
+    ) -> ProtocolResult<(Vec<SignedTransaction>, Vec<Hash>)> {
+        let (mut full_txs, missing_hashes) = self.get_cached_txs(tx_hashes);
+        if missing_hashes.is_empty() {
+            return Ok((full_txs, missing_hashes));
+        }
+
+        let storage_txs = self.get_storage_txs(ctx, height, &missing_hashes).await?;
+        let mut unresolved = Vec::new();
+        for (hash, tx) in missing_hashes.into_iter().zip(storage_txs.into_iter()) {
+            match tx {
+                Some(tx) => full_txs.push(tx),
+                None => unresolved.push(hash),
+            }
+        }
+
+        Ok((full_txs, unresolved))
+    }
+
     async fn ensure_order_txs(
         &self,
         ctx: Context,
@@ -267,6 +1351,12 @@ where
     ) -> ProtocolResult<()> {
         check_dup_order_hashes(order_tx_hashes)?;
 
+        // The semaphore is never closed
+        let _permit = match self.ensure_limit.as_ref() {
+            Some(limit) => Some(limit.acquire().await.expect("ensure limit closed")),
+            None => None,
+        };
+
         let unknown_hashes = self.show_unknown_txs(order_tx_hashes).await;
         if !unknown_hashes.is_empty() {
             let unknown_len = unknown_hashes.len();
@@ -277,6 +1367,7 @@ where
 
             // Make sure response signed_txs is the same size of request hashes.
             if txs.len() != unknown_len {
+                self.adapter.report_bad(ctx);
                 return Err(MemPoolError::EnsureBreak {
                     require:  unknown_len,
                     response: txs.len(),
@@ -287,13 +1378,17 @@ where
             self.verify_tx_in_parallel(ctx.clone(), txs.clone()).await?;
 
             for signed_tx in txs.into_iter() {
-                self.pool.insert(signed_tx)?;
+                self.pool.insert(signed_tx, false)?;
             }
 
             self.adapter.report_good(ctx);
         }
 
-        Ok(())
+        check_dup_order_nonces(
+            order_tx_hashes
+                .iter()
+                .filter_map(|hash| self.pool.get_by_hash(hash)),
+        )
     }
 
     async fn get_tx_count_by_address(&self, _ctx: Context, address: H160) -> ProtocolResult<usize> {
@@ -303,7 +1398,42 @@ where
     fn set_args(&self, context: Context, state_root: H256, gas_limit: u64, max_tx_size: u64) {
         self.adapter
             .set_args(context, state_root, gas_limit, max_tx_size);
+        self.max_tx_size
+            .store(max_tx_size as usize, Ordering::Release);
+        self.gas_limit.store(gas_limit, Ordering::Release);
+        self.prepared.store(true, Ordering::Release);
+    }
+}
+
+impl<Adapter> Drop for MemPoolImpl<Adapter> {
+    fn drop(&mut self) {
+        if let Some(reaper) = self.reaper.get_mut().take() {
+            reaper.abort();
+        }
+    }
+}
+
+async fn verify_tx<Adapter: MemPoolAdapter>(
+    adapter: &Adapter,
+    ctx: Context,
+    tx: &SignedTransaction,
+) -> ProtocolResult<()> {
+    adapter.check_authorization(ctx.clone(), tx).await?;
+    adapter.check_transaction(ctx.clone(), tx).await?;
+    adapter.check_storage_exist(ctx, &tx.transaction.hash).await
+}
+
+fn recover_sender(tx: &SignedTransaction) -> ProtocolResult<H160> {
+    let signer = SignedTransaction::try_from(tx.transaction.clone())?.sender;
+    if signer != tx.sender {
+        return Err(MemPoolError::InvalidSender {
+            tx_hash: tx.transaction.hash,
+            signer,
+        }
+        .into());
     }
+
+    Ok(signer)
 }
 
 fn check_dup_order_hashes(order_tx_hashes: &[Hash]) -> ProtocolResult<()> {
@@ -320,6 +1450,59 @@ fn check_dup_order_hashes(order_tx_hashes: &[Hash]) -> ProtocolResult<()> {
     Ok(())
 }
 
+/// The nonce the first tx of an account takes. The on-chain account nonce
+/// is the one of its last committed tx, `check_authorization` rejects the txs
+/// at or below it as stale.
+pub(crate) fn next_nonce(account_nonce: U256) -> U256 {
+    account_nonce.saturating_add(U256::one())
+}
+
+pub(crate) fn is_stale_nonce(account_nonce: U256, tx_nonce: U256) -> bool {
+    tx_nonce <= account_nonce
+}
+
+// The txs ascending by nonce are executable one after another from
+// `next_nonce(account_nonce)` until the first gap.
+fn split_executable(
+    txs: Vec<SignedTransaction>,
+    account_nonce: U256,
+) -> (Vec<SignedTransaction>, Vec<SignedTransaction>) {
+    let mut next = next_nonce(account_nonce);
+    txs.into_iter().partition(|tx| {
+        if tx.transaction.unsigned.nonce != next {
+            return false;
+        }
+        next += U256::one();
+        true
+    })
+}
+
+// Distinct txs of the same sender and nonce can't be committed together, an
+// order containing them is malformed.
+fn check_dup_order_nonces<I: IntoIterator<Item = SignedTransaction>>(
+    order_txs: I,
+) -> ProtocolResult<()> {
+    let mut dup_set = HashSet::new();
+
+    for tx in order_txs.into_iter() {
+        if !dup_set.insert((tx.sender, tx.transaction.unsigned.nonce)) {
+            return Err(MemPoolError::EnsureDupNonce {
+                sender: tx.sender,
+                nonce:  tx.transaction.unsigned.nonce,
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InsertOrGet {
+    Inserted,
+    AlreadyPresent(Hash),
+}
+
 pub enum TxType {
     NewTx,
     ProposeTx,
@@ -358,12 +1541,18 @@ pub enum MemPoolError {
     #[display(fmt = "Tx: {:?} inserts failed", _0)]
     Insert(Hash),
 
+    #[display(fmt = "System script tx: {:?} inserts failed", _0)]
+    SystemScriptInsert(Hash),
+
     #[display(fmt = "Mempool reaches limit: {}", _0)]
     ReachLimit(usize),
 
     #[display(fmt = "Tx: {:?} exists in pool", _0)]
     Dup(Hash),
 
+    #[display(fmt = "Sender: {:?} is blocked", _0)]
+    BlockedSender(H160),
+
     #[display(fmt = "Pull txs, require: {}, response: {}", require, response)]
     EnsureBreak { require: usize, response: usize },
 
@@ -373,14 +1562,28 @@ pub enum MemPoolError {
     )]
     EnsureDup(Hash),
 
+    #[display(
+        fmt = "There are order transactions of sender {:?} with the same nonce {}",
+        sender,
+        nonce
+    )]
+    EnsureDupNonce { sender: H160, nonce: U256 },
+
     #[display(fmt = "Fetch full txs, require: {}, response: {}", require, response)]
     MisMatch { require: usize, response: usize },
 
+    #[display(
+        fmt = "Fetch full txs, {} txs missing over the threshold {}",
+        missing,
+        threshold
+    )]
+    TooManyMissing { missing: usize, threshold: usize },
+
     #[display(fmt = "Tx inserts candidate_queue failed, len: {}", _0)]
     InsertCandidate(usize),
 
-    #[display(fmt = "Tx: {:?} check authorization error {:?}", tx_hash, err_info)]
-    CheckAuthorization { tx_hash: Hash, err_info: String },
+    #[display(fmt = "Tx: {:?} check authorization error {}", _1, _0)]
+    Authorization(AuthFailure, Hash),
 
     #[display(fmt = "Check_hash failed, expect: {:?}, get: {:?}", expect, actual)]
     CheckHash { expect: Hash, actual: Hash },
@@ -391,6 +1594,9 @@ pub enum MemPoolError {
     #[display(fmt = "Tx: {:?} doesn't match our chain id", _0)]
     WrongChain(Hash),
 
+    #[display(fmt = "Tx: {:?} calls unknown recipient {:?}", tx_hash, to)]
+    UnknownRecipient { tx_hash: Hash, to: H160 },
+
     #[display(fmt = "Tx: {:?} timeout {}", tx_hash, timeout)]
     Timeout { tx_hash: Hash, timeout: u64 },
 
@@ -402,10 +1608,52 @@ pub enum MemPoolError {
 
     #[display(fmt = "Encode transaction to JSON failed")]
     EncodeJson,
+
+    #[display(fmt = "Package before the state root is set")]
+    Unprepared,
+
+    #[display(
+        fmt = "Tx: {:?} is signed by {:?} rather than its sender",
+        tx_hash,
+        signer
+    )]
+    InvalidSender { tx_hash: Hash, signer: H160 },
+
+    #[display(fmt = "Tx: {:?} has a zero gas limit", _0)]
+    ZeroGasLimit(Hash),
+
+    #[display(
+        fmt = "Tx gas price {} is below the min gas price {}",
+        tx_gas_price,
+        min_gas_price
+    )]
+    Underpriced {
+        tx_gas_price:  U256,
+        min_gas_price: U256,
+    },
+
+    #[display(fmt = "Sender {:?} reaches the limit of {} pooled txs", address, limit)]
+    SenderLimit { address: H160, limit: usize },
 }
 
 impl Error for MemPoolError {}
 
+/// Why the adapter rejects the authorization of a tx.
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum AuthFailure {
+    #[display(fmt = "bad signature")]
+    BadSignature,
+
+    #[display(fmt = "stale nonce {}, current nonce {}", tx, current)]
+    StaleNonce { current: U256, tx: U256 },
+
+    #[display(fmt = "insufficient balance {}, need {}", have, need)]
+    InsufficientBalance { have: U256, need: U256 },
+
+    #[display(fmt = "{}", _0)]
+    Other(String),
+}
+
 impl From<MemPoolError> for ProtocolError {
     fn from(error: MemPoolError) -> ProtocolError {
         ProtocolError::new(ProtocolErrorKind::Mempool, Box::new(error))
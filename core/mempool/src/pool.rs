@@ -1,6 +1,7 @@
-use std::collections::{BTreeMap, BinaryHeap};
-use std::sync::{atomic::Ordering, Arc};
-use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crossbeam_queue::ArrayQueue;
 use dashmap::DashMap;
@@ -13,25 +14,51 @@ use protocol::ProtocolResult;
 use crate::tx_wrapper::{TxPtr, TxWrapper};
 use crate::MemPoolError;
 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub len:                     usize,
+    pub co_queue_len:            usize,
+    pub system_script_queue_len: usize,
+    pub distinct_senders:        usize,
+    pub total_gas_limit:         U256,
+    pub min_gas_price:           Option<U256>,
+    pub max_gas_price:           Option<U256>,
+}
+
 pub struct PriorityPool {
-    sys_tx_bucket:  SystemScriptTxBucket,
-    occupied_nonce: DashMap<H160, BTreeMap<U256, TxPtr>>,
-    co_queue:       Arc<ArrayQueue<TxPtr>>,
-    real_queue:     Arc<Mutex<BinaryHeap<TxPtr>>>,
-    tx_map:         DashMap<Hash, SignedTransaction>,
+    sys_tx_bucket:    SystemScriptTxBucket,
+    occupied_nonce:   DashMap<H160, BTreeMap<U256, TxPtr>>,
+    co_queue:         Arc<ArrayQueue<TxPtr>>,
+    real_queue:       Arc<Mutex<BinaryHeap<TxPtr>>>,
+    // The pooled txs cheapest first, the dropped ones are skipped lazily. The
+    // normal txs are inserted under its lock.
+    by_price:         Mutex<BTreeSet<TxPtr>>,
+    tx_map:           DashMap<Hash, SignedTransaction>,
+    sender_limit:     Option<usize>,
+    sys_tx_limit:     Option<usize>,
+    // Raised on insert, recounted when txs leave the pool
+    max_sender_depth: AtomicUsize,
 
     flush_lock: Arc<RwLock<()>>,
 }
 
 impl PriorityPool {
-    pub async fn new(size: usize) -> Self {
+    pub async fn new(
+        size: usize,
+        sender_limit: Option<usize>,
+        sys_tx_limit: Option<usize>,
+    ) -> Self {
         let pool = PriorityPool {
-            sys_tx_bucket:  SystemScriptTxBucket::new(),
+            sys_tx_bucket: SystemScriptTxBucket::new(),
             occupied_nonce: DashMap::new(),
-            co_queue:       Arc::new(ArrayQueue::new(size)),
-            real_queue:     Arc::new(Mutex::new(BinaryHeap::with_capacity(size * 2))),
-            tx_map:         DashMap::new(),
-            flush_lock:     Arc::new(RwLock::new(())),
+            co_queue: Arc::new(ArrayQueue::new(size)),
+            real_queue: Arc::new(Mutex::new(BinaryHeap::with_capacity(size * 2))),
+            by_price: Mutex::new(BTreeSet::new()),
+            tx_map: DashMap::new(),
+            sender_limit,
+            sys_tx_limit,
+            max_sender_depth: AtomicUsize::new(0),
+            flush_lock: Arc::new(RwLock::new(())),
         };
 
         let co_queue = Arc::clone(&pool.co_queue);
@@ -54,6 +81,26 @@ impl PriorityPool {
         pool
     }
 
+    /// The pooled txs of the address, ascending by nonce.
+    pub fn get_txs_by_address(&self, address: H160) -> Vec<SignedTransaction> {
+        let _flushing = self.flush_lock.read();
+
+        self.occupied_nonce
+            .get(&address)
+            .map(|set| {
+                set.values()
+                    .filter(|ptr| !ptr.is_dropped())
+                    .filter_map(|ptr| self.tx_map.get(ptr.hash()).map(|tx| tx.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The addresses with pooled txs, system script txs excluded.
+    pub fn senders(&self) -> Vec<H160> {
+        self.occupied_nonce.iter().map(|kv| *kv.key()).collect()
+    }
+
     pub fn get_tx_count_by_address(&self, address: H160) -> usize {
         if let Some(set) = self.occupied_nonce.get(&address) {
             return set
@@ -65,30 +112,314 @@ impl PriorityPool {
         0usize
     }
 
+    /// Split the txs of the address into `(pending, queued)`. The contiguous
+    /// run starting at `next_nonce` is pending and everything else is queued.
+    pub fn tx_counts_by_address(&self, address: H160, next_nonce: U256) -> (usize, usize) {
+        let set = match self.occupied_nonce.get(&address) {
+            Some(set) => set,
+            None => return (0, 0),
+        };
+
+        let (mut pending, mut queued) = (0usize, 0usize);
+        let mut next_nonce = next_nonce;
+
+        for nonce in set
+            .iter()
+            .filter(|(_, ptr)| !ptr.is_dropped())
+            .map(|(nonce, _)| *nonce)
+        {
+            if queued == 0 && nonce == next_nonce {
+                pending += 1;
+                next_nonce += U256::one();
+            } else {
+                queued += 1;
+            }
+        }
+
+        (pending, queued)
+    }
+
+    /// Return the hashes of the pooled txs which occupy the same sender and
+    /// nonce as the given tx and would be replaced by it.
+    pub fn conflicts_with(&self, stx: &SignedTransaction) -> Vec<Hash> {
+        self.occupied_nonce
+            .get(&stx.sender)
+            .and_then(|set| {
+                set.get(&stx.transaction.unsigned.nonce)
+                    .filter(|ptr| !ptr.is_dropped() && ptr.hash() != &stx.transaction.hash)
+                    .map(|ptr| vec![*ptr.hash()])
+            })
+            .unwrap_or_default()
+    }
+
+    /// Fails with `SystemScriptInsert` once the system script txs reach the
+    /// limit, a pooled tx can still be inserted again.
     pub fn insert_system_script_tx(&self, stx: SignedTransaction) -> ProtocolResult<()> {
         let _flushing = self.flush_lock.read();
+        let hash = stx.transaction.hash;
+        if let Some(limit) = self.sys_tx_limit {
+            if self.sys_tx_bucket.tx_count() >= limit && !self.sys_tx_bucket.contains(&hash) {
+                return Err(MemPoolError::SystemScriptInsert(hash).into());
+            }
+        }
+
         self.sys_tx_bucket.insert(stx);
         Ok(())
     }
 
-    pub fn insert(&self, stx: SignedTransaction) -> ProtocolResult<()> {
+    /// Insert the tx, a `local` one was submitted to this node and goes ahead
+    /// of the network ones in `package`.
+    pub fn insert(&self, stx: SignedTransaction, local: bool) -> ProtocolResult<()> {
+        // This lock is necessary to avoid mismatch error triggered by the concurrent
+        // operation of tx insertion and flush.
+        let _flushing = self.flush_lock.read();
+        let mut by_price = self.by_price.lock();
         if self.reach_limit() {
             return Err(MemPoolError::ReachLimit(self.tx_map.len()).into());
         }
 
-        // This lock is necessary to avoid mismatch error triggered by the concurrent
-        // operation of tx insertion and flush.
+        self.insert_unlocked(stx, local, &mut by_price)
+    }
+
+    /// Insert all the txs like `insert_with_eviction` while holding the locks
+    /// once. The results are in input order.
+    pub fn insert_batch(
+        &self,
+        stxs: Vec<SignedTransaction>,
+        local: bool,
+    ) -> Vec<ProtocolResult<Option<Hash>>> {
         let _flushing = self.flush_lock.read();
+        let mut by_price = self.by_price.lock();
+
+        stxs.into_iter()
+            .map(|stx| self.insert_with_eviction_unlocked(stx, local, &mut by_price))
+            .collect()
+    }
+
+    // A sender at the limit may only replace its pooled nonces.
+    fn check_sender_limit(
+        &self,
+        set: &BTreeMap<U256, TxPtr>,
+        sender: H160,
+        nonce: U256,
+    ) -> ProtocolResult<()> {
+        let limit = match self.sender_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let replace = set.get(&nonce).map_or(false, |ptr| !ptr.is_dropped());
+        if !replace && set.values().filter(|ptr| !ptr.is_dropped()).count() >= limit {
+            return Err(MemPoolError::SenderLimit {
+                address: sender,
+                limit,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    fn insert_unlocked(
+        &self,
+        stx: SignedTransaction,
+        local: bool,
+        by_price: &mut BTreeSet<TxPtr>,
+    ) -> ProtocolResult<()> {
+        let tx_wrapper = TxWrapper::new(stx, Instant::now(), local);
+        let ptr = tx_wrapper.ptr();
+
+        // The limit is checked under the entry lock of the sender, so that its
+        // concurrent inserts can't exceed it together
+        let mut set = self
+            .occupied_nonce
+            .entry(ptr.sender)
+            .or_insert_with(BTreeMap::new);
+        if let Err(e) = self.check_sender_limit(&set, ptr.sender, ptr.nonce) {
+            drop(set);
+            self.occupied_nonce
+                .remove_if(&ptr.sender, |_, set| set.is_empty());
+            return Err(e);
+        }
+        self.occupy(&mut set, tx_wrapper.ptr());
+        drop(set);
 
-        let tx_wrapper = TxWrapper::from(stx);
         let _ = self.co_queue.push(tx_wrapper.ptr());
-        self.occupy_nonce(tx_wrapper.ptr());
+        // A dropped ptr of the same tx may still be there
+        by_price.replace(tx_wrapper.ptr());
         self.tx_map
             .insert(tx_wrapper.hash(), tx_wrapper.into_signed_transaction());
         Ok(())
     }
 
-    pub fn package(&self, _gas_limit: U256, limit: usize) -> Vec<Hash> {
+    /// Insert the tx, when the pool is full make room by evicting the cheapest
+    /// pooled tx if the new one pays strictly more. Return the hash of the
+    /// evicted tx. The eviction and the insert are done under the same lock,
+    /// so no concurrent insert takes the freed room.
+    pub fn insert_with_eviction(
+        &self,
+        stx: SignedTransaction,
+        local: bool,
+    ) -> ProtocolResult<Option<Hash>> {
+        let _flushing = self.flush_lock.read();
+        self.insert_with_eviction_unlocked(stx, local, &mut self.by_price.lock())
+    }
+
+    fn insert_with_eviction_unlocked(
+        &self,
+        stx: SignedTransaction,
+        local: bool,
+        by_price: &mut BTreeSet<TxPtr>,
+    ) -> ProtocolResult<Option<Hash>> {
+        if !self.reach_limit() {
+            return self.insert_unlocked(stx, local, by_price).map(|_| None);
+        }
+
+        // Don't evict for a tx that can't get in anyway
+        if let Some(set) = self.occupied_nonce.get(&stx.sender) {
+            self.check_sender_limit(&set, stx.sender, stx.transaction.unsigned.nonce)?;
+        }
+
+        let evicted = match cheapest(by_price) {
+            Some(ptr) if ptr.gas_price < stx.transaction.unsigned.gas_price => ptr.hash,
+            _ => return Err(MemPoolError::ReachLimit(self.tx_map.len()).into()),
+        };
+
+        self.remove_unlocked(&evicted);
+        self.recount_max_sender_depth();
+        self.insert_unlocked(stx, local, by_price)?;
+        Ok(Some(evicted))
+    }
+
+    /// The gas price of the tx `insert_with_eviction` would evict first.
+    pub fn cheapest_gas_price(&self) -> Option<U256> {
+        let _flushing = self.flush_lock.read();
+        cheapest(&mut self.by_price.lock()).map(|ptr| ptr.gas_price)
+    }
+
+    /// Whether a tx paying `gas_price` may evict a pooled tx when the pool is
+    /// full.
+    pub fn outbids_cheapest(&self, gas_price: U256) -> bool {
+        let _flushing = self.flush_lock.read();
+        cheapest(&mut self.by_price.lock()).map_or(false, |ptr| ptr.gas_price < gas_price)
+    }
+
+    /// Remove all txs sent by the address, return the hashes of removed txs.
+    pub fn remove_by_sender(&self, address: &H160) -> Vec<Hash> {
+        let _flushing = self.flush_lock.read();
+
+        let mut removed = Vec::new();
+        if let Some((_, set)) = self.occupied_nonce.remove(address) {
+            for ptr in set.values() {
+                ptr.set_dropped();
+                if self.tx_map.remove(ptr.hash()).is_some() {
+                    removed.push(*ptr.hash());
+                }
+            }
+        }
+        self.recount_max_sender_depth();
+
+        removed
+    }
+
+    /// Remove a single tx, return whether it was pooled.
+    pub fn remove(&self, hash: &Hash) -> bool {
+        !self.remove_by_hashes(std::slice::from_ref(hash)).is_empty()
+    }
+
+    /// Remove the txs of the hashes, return the hashes of removed txs.
+    pub fn remove_by_hashes(&self, hashes: &[Hash]) -> Vec<Hash> {
+        let _flushing = self.flush_lock.read();
+
+        let removed = hashes
+            .iter()
+            .filter(|hash| self.remove_unlocked(hash))
+            .copied()
+            .collect();
+        self.recount_max_sender_depth();
+
+        removed
+    }
+
+    fn remove_unlocked(&self, hash: &Hash) -> bool {
+        if self.sys_tx_bucket.contains(hash) {
+            self.sys_tx_bucket.flush(std::slice::from_ref(hash));
+            return true;
+        }
+
+        let (_, stx) = match self.tx_map.remove(hash) {
+            Some(kv) => kv,
+            None => return false,
+        };
+        let nonce = stx.transaction.unsigned.nonce;
+        if let Some(mut set) = self.occupied_nonce.get_mut(&stx.sender) {
+            if set.get(&nonce).map_or(false, |ptr| ptr.hash() == hash) {
+                if let Some(ptr) = set.remove(&nonce) {
+                    ptr.set_dropped();
+                }
+            }
+        }
+        self.occupied_nonce
+            .remove_if(&stx.sender, |_, set| set.is_empty());
+        true
+    }
+
+    /// Remove the txs of the address whose nonce is not above `nonce`, return
+    /// the hashes of removed txs.
+    pub fn remove_stale_nonces(&self, address: &H160, nonce: U256) -> Vec<Hash> {
+        let _flushing = self.flush_lock.read();
+
+        let mut removed = Vec::new();
+        if let Some(mut set) = self.occupied_nonce.get_mut(address) {
+            let fresh = match nonce.checked_add(U256::one()) {
+                Some(next) => set.split_off(&next),
+                None => BTreeMap::new(),
+            };
+            for ptr in std::mem::replace(&mut *set, fresh).values() {
+                ptr.set_dropped();
+                if self.tx_map.remove(ptr.hash()).is_some() {
+                    removed.push(*ptr.hash());
+                }
+            }
+        }
+        self.occupied_nonce
+            .remove_if(address, |_, set| set.is_empty());
+        self.recount_max_sender_depth();
+
+        removed
+    }
+
+    /// Remove the txs which stay in pool longer than `max_residence`, return
+    /// the hashes of removed txs.
+    pub fn remove_expired(&self, max_residence: Duration) -> Vec<Hash> {
+        let _flushing = self.flush_lock.read();
+
+        let mut removed = Vec::new();
+        self.occupied_nonce.retain(|_, set| {
+            set.retain(|_, ptr| {
+                if ptr.inserted_at.elapsed() <= max_residence {
+                    return true;
+                }
+
+                ptr.set_dropped();
+                if self.tx_map.remove(ptr.hash()).is_some() {
+                    removed.push(*ptr.hash());
+                }
+                false
+            });
+            !set.is_empty()
+        });
+        self.recount_max_sender_depth();
+
+        removed
+    }
+
+    /// The system script txs go first, then the local txs and then the network
+    /// ones, each ordered by gas price descending, nonce ascending and finally
+    /// hash ascending, so that the order doesn't depend on the insert order.
+    /// With a `base_fee` the txs are ranked by their effective gas price at it
+    /// instead of their max fee.
+    pub fn package(&self, _gas_limit: U256, limit: usize, base_fee: Option<U256>) -> Vec<Hash> {
         let _flushing = self.flush_lock.read();
 
         let mut ret = self.sys_tx_bucket.package();
@@ -98,17 +429,9 @@ impl PriorityPool {
             txs.for_each(|p_tx| q.push(p_tx));
         }
 
-        ret.extend(
-            q.iter()
-                .filter_map(|ptr| {
-                    if ptr.is_dropped() {
-                        None
-                    } else {
-                        Some(ptr.hash)
-                    }
-                })
-                .take(limit),
-        );
+        let mut ptrs = q.iter().filter(|ptr| !ptr.is_dropped()).collect::<Vec<_>>();
+        ptrs.sort_unstable_by(|a, b| b.cmp_at(a, base_fee));
+        ret.extend(ptrs.into_iter().take(limit).map(|ptr| ptr.hash));
         ret
     }
 
@@ -120,6 +443,41 @@ impl PriorityPool {
         self.co_queue.len()
     }
 
+    pub fn distinct_senders(&self) -> usize {
+        self.occupied_nonce.len()
+    }
+
+    /// Gather the statistics under the flush lock so that they are consistent
+    /// with each other.
+    pub fn stats(&self) -> PoolStats {
+        let _flushing = self.flush_lock.write();
+
+        let mut stats = PoolStats {
+            len: self.tx_map.len(),
+            co_queue_len: self.co_queue.len(),
+            system_script_queue_len: self.sys_tx_bucket.len(),
+            distinct_senders: self.occupied_nonce.len(),
+            ..Default::default()
+        };
+
+        for kv in self.tx_map.iter() {
+            let tx = &kv.value().transaction.unsigned;
+            stats.total_gas_limit = stats.total_gas_limit.saturating_add(tx.gas_limit);
+            stats.min_gas_price = Some(
+                stats
+                    .min_gas_price
+                    .map_or(tx.gas_price, |price| price.min(tx.gas_price)),
+            );
+            stats.max_gas_price = Some(
+                stats
+                    .max_gas_price
+                    .map_or(tx.gas_price, |price| price.max(tx.gas_price)),
+            );
+        }
+
+        stats
+    }
+
     pub fn contains(&self, hash: &Hash) -> bool {
         let _flushing = self.flush_lock.read();
         self.tx_map.contains_key(hash) || self.sys_tx_bucket.contains(hash)
@@ -133,6 +491,55 @@ impl PriorityPool {
         self.co_queue.capacity() / 2
     }
 
+    /// How full the pool is, in percent of the size `reach_limit` checks.
+    pub fn occupancy(&self) -> usize {
+        self.len() * 100 / self.co_queue.capacity().max(1)
+    }
+
+    /// The hashes of all the pooled txs.
+    pub fn hashes(&self) -> HashSet<Hash> {
+        let _flushing = self.flush_lock.read();
+
+        let mut hashes = self
+            .tx_map
+            .iter()
+            .map(|kv| *kv.key())
+            .collect::<HashSet<_>>();
+        hashes.extend(self.sys_tx_bucket.hashes());
+        hashes
+    }
+
+    /// Every pooled tx, the system script ones first and the others ordered
+    /// by sender and nonce. Taken under the flush lock so concurrent inserts
+    /// are either wholly in or out.
+    pub fn dump(&self) -> Vec<SignedTransaction> {
+        let _flushing = self.flush_lock.write();
+
+        let mut txs = self.sys_tx_bucket.txs();
+        let mut normal = self
+            .tx_map
+            .iter()
+            .map(|kv| kv.value().clone())
+            .collect::<Vec<_>>();
+        normal.sort_unstable_by(|a, b| {
+            a.sender.cmp(&b.sender).then_with(|| {
+                a.transaction
+                    .unsigned
+                    .nonce
+                    .cmp(&b.transaction.unsigned.nonce)
+            })
+        });
+        txs.extend(normal);
+        txs
+    }
+
+    /// The pooled txs other than the system script ones, which are the txs
+    /// `flush` runs its check on.
+    pub fn normal_txs(&self) -> Vec<SignedTransaction> {
+        let _flushing = self.flush_lock.read();
+        self.tx_map.iter().map(|kv| kv.value().clone()).collect()
+    }
+
     pub fn get_by_hash(&self, hash: &Hash) -> Option<SignedTransaction> {
         let _flushing = self.flush_lock.read();
 
@@ -142,29 +549,138 @@ impl PriorityPool {
         }
     }
 
-    pub fn flush<F: Fn(&SignedTransaction) -> bool>(&self, hashes: &[Hash], nonce_check: F) {
+    /// Return the `n` longest waiting txs with their ages, the oldest first.
+    pub fn oldest_txs(&self, n: usize) -> Vec<(Hash, Duration)> {
+        let _flushing = self.flush_lock.read();
+
+        let mut txs = self
+            .occupied_nonce
+            .iter()
+            .flat_map(|kv| {
+                kv.value()
+                    .values()
+                    .filter(|ptr| !ptr.is_dropped())
+                    .map(|ptr| (ptr.hash, ptr.inserted_at))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        txs.sort_unstable_by_key(|(_, inserted_at)| *inserted_at);
+
+        let now = Instant::now();
+        txs.into_iter()
+            .take(n)
+            .map(|(hash, inserted_at)| (hash, now.saturating_duration_since(inserted_at)))
+            .collect()
+    }
+
+    /// Return the `n` senders with the most pooled txs, the largest count
+    /// first. Senders with the same count are ordered by address.
+    pub fn top_senders(&self, n: usize) -> Vec<(H160, usize)> {
+        let _flushing = self.flush_lock.read();
+
+        let mut senders = self
+            .occupied_nonce
+            .iter()
+            .map(|kv| {
+                let count = kv.value().values().filter(|ptr| !ptr.is_dropped()).count();
+                (*kv.key(), count)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect::<Vec<_>>();
+        senders.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        senders.truncate(n);
+        senders
+    }
+
+    /// The most txs pooled by a single sender. A removal racing with an
+    /// insert may leave it low until the next insert of that sender.
+    pub fn max_sender_depth(&self) -> usize {
+        self.max_sender_depth.load(Ordering::Relaxed)
+    }
+
+    fn recount_max_sender_depth(&self) {
+        let depth = self
+            .occupied_nonce
+            .iter()
+            .map(|kv| kv.value().len())
+            .max()
+            .unwrap_or(0);
+        self.max_sender_depth.store(depth, Ordering::Relaxed);
+    }
+
+    /// Drop the committed txs and the ones failing `nonce_check`, return their
+    /// hashes. The txs already replaced by the same nonce are dropped too but
+    /// left out of the return.
+    pub fn flush<F: Fn(&SignedTransaction) -> bool>(
+        &self,
+        hashes: &[Hash],
+        nonce_check: F,
+    ) -> Vec<Hash> {
         let _flushing = self.flush_lock.write();
-        let residual = self.get_residual(hashes, nonce_check);
+        let mut removed = hashes
+            .iter()
+            .filter(|hash| self.sys_tx_bucket.contains(hash))
+            .copied()
+            .collect::<Vec<_>>();
+        let residual = self
+            .get_residual(hashes, nonce_check, &mut removed)
+            .map(|tx| {
+                let (inserted_at, local) = self
+                    .pooled_ptr(&tx)
+                    .map_or((Instant::now(), false), |ptr| (ptr.inserted_at, ptr.local));
+                (tx, inserted_at, local)
+            })
+            .collect::<Vec<_>>();
         self.occupied_nonce.clear();
+        self.max_sender_depth.store(0, Ordering::Relaxed);
         self.sys_tx_bucket.flush(hashes);
 
         let mut q = self.real_queue.lock();
-        for tx in residual {
-            let tx_wrapper = TxWrapper::from(tx);
+        let mut by_price = self.by_price.lock();
+        by_price.clear();
+        for (tx, inserted_at, local) in residual {
+            let tx_wrapper = TxWrapper::new(tx, inserted_at, local);
             self.occupy_nonce(tx_wrapper.ptr());
+            by_price.insert(tx_wrapper.ptr());
             q.push(tx_wrapper.ptr());
         }
+
+        removed
+    }
+
+    /// Remove every pooled tx, system script ones included, return the hashes
+    /// of the removed txs.
+    pub fn clear(&self) -> Vec<Hash> {
+        let _flushing = self.flush_lock.write();
+
+        let mut removed = self.sys_tx_bucket.hashes();
+        removed.extend(self.tx_map.iter().map(|kv| *kv.key()));
+        for kv in self.occupied_nonce.iter() {
+            kv.value().values().for_each(|ptr| ptr.set_dropped());
+        }
+        self.occupied_nonce.clear();
+        self.real_queue.lock().clear();
+        self.by_price.lock().clear();
+        pop_all_item(Arc::clone(&self.co_queue)).for_each(|ptr| ptr.set_dropped());
+        self.tx_map.clear();
+        self.sys_tx_bucket.clear();
+        self.max_sender_depth.store(0, Ordering::Relaxed);
+
+        removed
     }
 
     fn get_residual<F: Fn(&SignedTransaction) -> bool>(
         &self,
         hashes: &[Hash],
         nonce_check: F,
+        removed: &mut Vec<Hash>,
     ) -> impl Iterator<Item = SignedTransaction> + '_ {
         let mut q = self.real_queue.lock();
 
         for hash in hashes {
-            self.tx_map.remove(hash);
+            if self.tx_map.remove(hash).is_some() {
+                removed.push(*hash);
+            }
         }
 
         for tx_ptr in q.drain().chain(pop_all_item(Arc::clone(&self.co_queue))) {
@@ -172,20 +688,39 @@ impl PriorityPool {
                 self.tx_map.remove(tx_ptr.hash());
             }
         }
-        self.tx_map.retain(|_, v| nonce_check(v));
+        self.tx_map.retain(|hash, v| {
+            let keep = nonce_check(v);
+            if !keep {
+                removed.push(*hash);
+            }
+            keep
+        });
 
         self.tx_map.iter().map(|kv| kv.value().clone())
     }
 
+    fn pooled_ptr(&self, stx: &SignedTransaction) -> Option<TxPtr> {
+        self.occupied_nonce.get(&stx.sender).and_then(|set| {
+            set.get(&stx.transaction.unsigned.nonce)
+                .filter(|ptr| ptr.hash == stx.transaction.hash)
+                .cloned()
+        })
+    }
+
     fn occupy_nonce(&self, tx_ptr: TxPtr) {
-        if let Some(old_ptr) = self
+        let mut set = self
             .occupied_nonce
             .entry(tx_ptr.sender)
-            .or_insert_with(BTreeMap::new)
-            .insert(tx_ptr.nonce, tx_ptr)
-        {
+            .or_insert_with(BTreeMap::new);
+        self.occupy(&mut set, tx_ptr);
+    }
+
+    fn occupy(&self, set: &mut BTreeMap<U256, TxPtr>, tx_ptr: TxPtr) {
+        if let Some(old_ptr) = set.insert(tx_ptr.nonce, tx_ptr) {
             old_ptr.set_dropped();
         }
+        self.max_sender_depth
+            .fetch_max(set.len(), Ordering::Relaxed);
     }
 
     #[cfg(test)]
@@ -193,7 +728,6 @@ impl PriorityPool {
         self.real_queue.lock().len()
     }
 
-    #[cfg(test)]
     pub fn system_script_queue_len(&self) -> usize {
         self.sys_tx_bucket.len()
     }
@@ -234,6 +768,20 @@ impl SystemScriptTxBucket {
             .collect()
     }
 
+    pub fn txs(&self) -> Vec<SignedTransaction> {
+        self.tx_buckets
+            .iter()
+            .flat_map(|kv| kv.value().values().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    pub fn hashes(&self) -> Vec<Hash> {
+        self.tx_buckets
+            .iter()
+            .flat_map(|kv| kv.value().keys().copied().collect::<Vec<_>>())
+            .collect()
+    }
+
     pub fn get_tx_by_hash(&self, hash: &Hash) -> Option<SignedTransaction> {
         if let Some(data) = self.hash_data_map.get(hash) {
             if let Some(tx_map) = self.tx_buckets.get(data.value()) {
@@ -262,10 +810,31 @@ impl SystemScriptTxBucket {
         false
     }
 
-    #[cfg(test)]
     pub fn len(&self) -> usize {
         self.tx_buckets.len()
     }
+
+    pub fn tx_count(&self) -> usize {
+        self.hash_data_map.len()
+    }
+
+    pub fn clear(&self) {
+        self.hash_data_map.clear();
+        self.tx_buckets.clear();
+    }
+}
+
+// The network txs, the lowest gas price and then the highest nonce go first,
+// the reverse of the package order.
+fn cheapest(by_price: &mut BTreeSet<TxPtr>) -> Option<TxPtr> {
+    while let Some(ptr) = by_price.first() {
+        if !ptr.is_dropped() {
+            return Some(Arc::clone(ptr));
+        }
+        by_price.pop_first();
+    }
+
+    None
 }
 
 fn pop_all_item<T>(queue: Arc<ArrayQueue<T>>) -> impl Iterator<Item = T> {
@@ -1,60 +1,1992 @@
 use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 
+use parking_lot::Mutex;
 use test::Bencher;
 
-use protocol::types::Hasher;
+use protocol::types::{Block, Hasher};
+
+use crate::context::TxContext;
 
 use super::*;
 
-macro_rules! insert {
-    (normal($pool_size: expr, $input: expr, $output: expr)) => {
-        insert!(inner($pool_size, 1, $input, 0, $output));
-    };
-    (repeat($repeat: expr, $input: expr, $output: expr)) => {
-        insert!(inner($input * 10, $repeat, $input, 0, $output));
-    };
-    (invalid($valid: expr, $invalid: expr, $output: expr)) => {
-        insert!(inner($valid * 10, 1, $valid, $invalid, $output));
-    };
-    (inner($pool_size: expr, $repeat: expr, $valid: expr, $invalid: expr, $output: expr)) => {
-        let mempool =
-            Arc::new(new_mempool($pool_size, TIMEOUT_GAP, CYCLE_LIMIT, MAX_TX_SIZE).await);
-        let txs = mock_txs($valid, $invalid, TIMEOUT);
-        for _ in 0..$repeat {
-            concurrent_insert(txs.clone(), Arc::clone(&mempool)).await;
+macro_rules! insert {
+    (normal($pool_size: expr, $input: expr, $output: expr)) => {
+        insert!(inner($pool_size, 1, $input, 0, $output));
+    };
+    (repeat($repeat: expr, $input: expr, $output: expr)) => {
+        insert!(inner($input * 10, $repeat, $input, 0, $output));
+    };
+    (invalid($valid: expr, $invalid: expr, $output: expr)) => {
+        insert!(inner($valid * 10, 1, $valid, $invalid, $output));
+    };
+    (inner($pool_size: expr, $repeat: expr, $valid: expr, $invalid: expr, $output: expr)) => {
+        let mempool =
+            Arc::new(new_mempool($pool_size, TIMEOUT_GAP, CYCLE_LIMIT, MAX_TX_SIZE).await);
+        let txs = mock_txs($valid, $invalid, TIMEOUT);
+        for _ in 0..$repeat {
+            concurrent_insert(txs.clone(), Arc::clone(&mempool)).await;
+        }
+        assert_eq!(mempool.get_tx_cache().len(), $output);
+    };
+}
+
+#[test]
+fn test_dup_order_hashes() {
+    let hashes = vec![
+        Hasher::digest(Bytes::from("test1")),
+        Hasher::digest(Bytes::from("test2")),
+        Hasher::digest(Bytes::from("test3")),
+        Hasher::digest(Bytes::from("test4")),
+        Hasher::digest(Bytes::from("test2")),
+    ];
+    assert!(check_dup_order_hashes(&hashes).is_err());
+
+    let hashes = vec![
+        Hasher::digest(Bytes::from("test1")),
+        Hasher::digest(Bytes::from("test2")),
+        Hasher::digest(Bytes::from("test3")),
+        Hasher::digest(Bytes::from("test4")),
+    ];
+    assert!(check_dup_order_hashes(&hashes).is_ok());
+}
+
+#[tokio::test]
+async fn test_insert() {
+    // 1. insertion under pool size.
+    insert!(normal(100, 100, 100));
+
+    // 2. invalid insertion
+    insert!(invalid(80, 10, 80));
+}
+
+#[tokio::test]
+async fn test_content() {
+    let txs = mock_sender_txs(&[5, 6, 8]);
+    let sender = txs[0].sender;
+    let adapter = HashMemPoolAdapter::new();
+    adapter.account_nonces.insert(sender, U256::from(4));
+    let mempool = MemPoolImpl::with_config(MemPoolConfig::new(POOL_SIZE), adapter, vec![]).await;
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+
+    let (pending, queued) = mempool.content(Context::new()).await.unwrap();
+    let nonces = |txs: &Vec<SignedTransaction>| {
+        txs.iter()
+            .map(|tx| tx.transaction.unsigned.nonce.as_u64())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(nonces(&pending[&sender]), vec![5, 6]);
+    assert_eq!(nonces(&queued[&sender]), vec![8]);
+}
+
+#[tokio::test]
+async fn test_gas_price_percentile() {
+    let mempool = default_mempool().await;
+    assert_eq!(mempool.gas_price_percentile(50.0), None);
+
+    let prices = [30, 10, 50, 20, 40];
+    for tx in mock_gas_price_txs(&prices) {
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+    // System script txs are left out
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let tx = mock_system_script_signed_tx(&priv_key, &priv_key.pub_key(), TIMEOUT, 0, true);
+    mempool.insert(Context::new(), tx).await.unwrap();
+
+    assert_eq!(mempool.gas_price_percentile(50.0), Some(U256::from(30)));
+    assert_eq!(mempool.gas_price_percentile(0.0), Some(U256::from(10)));
+    assert_eq!(mempool.gas_price_percentile(100.0), Some(U256::from(50)));
+    assert_eq!(mempool.gas_price_percentile(101.0), None);
+}
+
+#[tokio::test]
+async fn test_insert_trusted() {
+    let txs = default_mock_txs(2);
+    let config = MemPoolConfig::new(POOL_SIZE).chain_id(Some(txs[0].transaction.chain_id));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let adapter = mempool.get_adapter();
+
+    // Not even the signature is checked
+    let mut unsigned = mock_txs(0, 1, TIMEOUT).remove(0);
+    unsigned.transaction.chain_id = txs[0].transaction.chain_id;
+    unsigned.transaction = unsigned.transaction.hash();
+    mempool.insert_trusted(txs[0].clone()).unwrap();
+    mempool.insert_trusted(unsigned.clone()).unwrap();
+    assert_eq!(mempool.len(), 2);
+    assert_eq!(adapter.auth_checks.load(Ordering::SeqCst), 0);
+
+    assert_mempool_err(mempool.insert_trusted(txs[0].clone()), "Dup");
+    assert_mempool_err(mempool.insert_trusted(txs[1].clone()), "WrongChain");
+    assert_eq!(mempool.len(), 2);
+
+    mempool.verify_batch(Context::new(), vec![unsigned]).await;
+    assert_eq!(adapter.auth_checks.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_verify_batch() {
+    let mut txs = mock_txs(4, 0, TIMEOUT);
+    txs.insert(2, mock_txs(0, 1, TIMEOUT).remove(0));
+
+    // Verified serially and in parallel
+    for threshold in [usize::MAX, 0] {
+        let config = MemPoolConfig::new(POOL_SIZE).serial_verify_threshold(threshold);
+        let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+
+        let res = mempool.verify_batch(Context::new(), txs.clone()).await;
+        assert_eq!(res.len(), txs.len());
+        assert_eq!(res.iter().filter(|r| r.is_err()).count(), 1);
+        assert!(res[2].is_err());
+        assert_eq!(mempool.len(), 0);
+    }
+}
+
+#[tokio::test]
+async fn test_pending_nonce() {
+    let mut txs = mock_sender_txs(&[2, 3, 4, 6]);
+    let stale = txs.remove(0);
+    let sender = stale.sender;
+    let adapter = HashMemPoolAdapter::new();
+    adapter.account_nonces.insert(sender, U256::from(2));
+    let mempool = MemPoolImpl::with_config(MemPoolConfig::new(POOL_SIZE), adapter, vec![]).await;
+    assert_eq!(
+        mempool
+            .get_pending_nonce(Context::new(), sender)
+            .await
+            .unwrap(),
+        U256::from(3)
+    );
+
+    // The adapter accepts the nonce handed out, but not the one before
+    let adapter = mempool.get_adapter();
+    assert!(adapter
+        .check_authorization(Context::new(), &txs[0])
+        .await
+        .is_ok());
+    assert_mempool_err(
+        adapter.check_authorization(Context::new(), &stale).await,
+        "StaleNonce",
+    );
+
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    assert_eq!(
+        mempool
+            .get_pending_nonce(Context::new(), sender)
+            .await
+            .unwrap(),
+        U256::from(5)
+    );
+}
+
+#[tokio::test]
+async fn test_stale_nonce() {
+    let txs = mock_sender_txs(&[3, 5, 8]);
+    let sender = txs[0].sender;
+    let adapter = HashMemPoolAdapter::new();
+    adapter.account_nonces.insert(sender, U256::from(4));
+    let mempool = MemPoolImpl::with_config(MemPoolConfig::new(POOL_SIZE), adapter, vec![]).await;
+
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[0].clone()).await,
+        "InvalidNonce",
+    );
+    for tx in txs[1..].iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+
+    // The future nonce waits behind the gap
+    let (pending, queued) = mempool.content(Context::new()).await.unwrap();
+    assert_eq!(pending[&sender], vec![txs[1].clone()]);
+    assert_eq!(queued[&sender], vec![txs[2].clone()]);
+
+    // Nor does the batch path take the stale nonce
+    let res = mempool
+        .insert_batch(Context::new(), vec![txs[0].clone()])
+        .await
+        .unwrap();
+    assert_mempool_err(res.into_iter().next().unwrap(), "InvalidNonce");
+}
+
+#[tokio::test]
+async fn test_stale_nonce_over_u64() {
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let mut raw = mock_transaction(0, false);
+    raw.nonce = U256::from(u64::MAX) + 1;
+    let tx = sign_tx(&priv_key, &priv_key.pub_key(), raw, true);
+    let adapter = HashMemPoolAdapter::new();
+    adapter.account_nonces.insert(tx.sender, U256::MAX);
+    let mempool = MemPoolImpl::with_config(MemPoolConfig::new(POOL_SIZE), adapter, vec![]).await;
+
+    assert_mempool_err(
+        mempool.insert(Context::new(), tx).await,
+        &format!(
+            "InvalidNonce {{ current: {}, tx_nonce: {} }}",
+            u64::MAX,
+            u64::MAX
+        ),
+    );
+}
+
+#[test]
+fn test_is_stale_nonce() {
+    assert!(is_stale_nonce(U256::from(4), U256::from(3)));
+    assert!(is_stale_nonce(U256::from(4), U256::from(4)));
+    assert!(!is_stale_nonce(U256::from(4), U256::from(5)));
+    // No nonce follows the last one
+    assert!(is_stale_nonce(U256::MAX, U256::MAX));
+}
+
+#[tokio::test]
+async fn test_auth_failures() {
+    let failures = vec![
+        AuthFailure::BadSignature,
+        AuthFailure::StaleNonce {
+            current: 5u64.into(),
+            tx:      4u64.into(),
+        },
+        AuthFailure::InsufficientBalance {
+            have: 1u64.into(),
+            need: 21000u64.into(),
+        },
+        AuthFailure::Other("Invalid system script transaction".to_string()),
+    ];
+
+    for failure in failures.into_iter() {
+        let tx = default_mock_txs(1).remove(0);
+        let adapter = HashMemPoolAdapter::auth_failure(failure.clone());
+        let mempool =
+            MemPoolImpl::with_config(MemPoolConfig::new(POOL_SIZE), adapter, vec![]).await;
+
+        let res = mempool.insert(Context::new(), tx).await;
+        assert_mempool_err(res, &format!("Authorization({:?}", failure));
+        assert!(mempool.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_tx_counts_by_address() {
+    let mempool = default_mempool().await;
+    let txs = mock_sender_txs(&[5, 6, 8]);
+    let sender = txs[0].sender;
+
+    for tx in txs.into_iter() {
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+
+    assert_eq!(
+        mempool
+            .get_tx_count_by_address(Context::new(), sender)
+            .await
+            .unwrap(),
+        3
+    );
+    let counts = |address| mempool.tx_counts_by_address(Context::new(), address);
+    mempool
+        .get_adapter()
+        .account_nonces
+        .insert(sender, U256::from(4));
+    assert_eq!(counts(sender).await.unwrap(), (2, 1));
+    assert_eq!(counts(H160::default()).await.unwrap(), (0, 0));
+
+    // Nothing is pending behind a gap after the committed nonce
+    mempool
+        .get_adapter()
+        .account_nonces
+        .insert(sender, U256::from(3));
+    assert_eq!(counts(sender).await.unwrap(), (0, 3));
+}
+
+#[tokio::test]
+async fn test_top_senders() {
+    let mempool = default_mempool().await;
+    let groups = vec![
+        mock_sender_txs(&[0, 1]),
+        mock_sender_txs(&[0, 1, 2, 3]),
+        mock_sender_txs(&[0]),
+        mock_sender_txs(&[0, 1]),
+    ];
+
+    for tx in groups.iter().flatten() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+
+    let top = mempool.top_senders(3);
+    assert_eq!(top.len(), 3);
+    assert_eq!(top[0], (groups[1][0].sender, 4));
+
+    // The two senders with 2 txs tie and are ordered by address
+    let mut tied = vec![groups[0][0].sender, groups[3][0].sender];
+    tied.sort();
+    assert_eq!(top[1], (tied[0], 2));
+    assert_eq!(top[2], (tied[1], 2));
+
+    assert_eq!(mempool.top_senders(10).len(), 4);
+    assert!(mempool.top_senders(0).is_empty());
+}
+
+#[tokio::test]
+async fn test_insert_or_get() {
+    let mempool = Arc::new(default_mempool().await);
+    let tx = default_mock_txs(1).remove(0);
+    let tx_hash = tx.transaction.hash;
+
+    let handles = (0..5)
+        .map(|_| {
+            let mempool = Arc::clone(&mempool);
+            let tx = tx.clone();
+            tokio::spawn(async move { mempool.insert_or_get(Context::new(), tx).await })
+        })
+        .collect::<Vec<_>>();
+    let res = futures::future::join_all(handles)
+        .await
+        .into_iter()
+        .map(|r| r.unwrap().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        res.iter().filter(|r| **r == InsertOrGet::Inserted).count(),
+        1
+    );
+    assert_eq!(
+        res.iter()
+            .filter(|r| **r == InsertOrGet::AlreadyPresent(tx_hash))
+            .count(),
+        4
+    );
+    assert_eq!(mempool.len(), 1);
+    assert_eq!(
+        mempool.insert_or_get(Context::new(), tx).await.unwrap(),
+        InsertOrGet::AlreadyPresent(tx_hash)
+    );
+}
+
+#[tokio::test]
+async fn test_eviction_archive() {
+    let config = MemPoolConfig::new(POOL_SIZE)
+        .max_residence(Some(Duration::from_millis(50)))
+        .eviction_archive_size(Some(2));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let txs = default_mock_txs(3);
+    let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(mempool.sweep_expired(), 1);
+    assert_eq!(
+        mempool.eviction_reason(&hashes[0]).await,
+        Some(EvictReason::Expired)
+    );
+
+    for tx in txs[1..].iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+        assert_eq!(mempool.remove_by_sender(&tx.sender), 1);
+    }
+
+    // The archive holds 2 records, the oldest one aged out
+    assert_eq!(mempool.eviction_reason(&hashes[0]).await, None);
+    for hash in hashes[1..].iter() {
+        assert_eq!(
+            mempool.eviction_reason(hash).await,
+            Some(EvictReason::SenderRemoved)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_import_txs() {
+    let mempool = default_mempool().await;
+    let txs = mock_txs(10, 2, TIMEOUT);
+
+    let res = mempool
+        .import_txs(Context::new(), txs.clone(), false)
+        .await
+        .unwrap();
+    assert_eq!(
+        res.iter().map(Result::is_ok).collect::<Vec<_>>(),
+        [vec![true; 10], vec![false; 2]].concat()
+    );
+    assert_eq!(mempool.len(), 10);
+    assert!(mempool.get_adapter().network_txs.is_empty());
+
+    // The pooled txs fail, and so does a stale nonce as on insert
+    let new_txs = default_mock_txs(2);
+    let stale = mock_sender_txs(&[1, 2]);
+    mempool
+        .get_adapter()
+        .account_nonces
+        .insert(stale[0].sender, U256::from(1));
+    let res = mempool
+        .import_txs(
+            Context::new(),
+            [txs[..1].to_vec(), new_txs, stale].concat(),
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.iter().map(Result::is_ok).collect::<Vec<_>>(), [
+        false, true, true, false, true
+    ]);
+    let mut res = res.into_iter();
+    assert_mempool_err(res.next().unwrap(), "Dup");
+    assert_mempool_err(res.nth(2).unwrap(), "InvalidNonce");
+    assert_eq!(mempool.len(), 13);
+    assert_eq!(mempool.get_adapter().network_txs.len(), 3);
+}
+
+#[tokio::test]
+async fn test_dump() {
+    let mempool = default_mempool().await;
+    let txs = [mock_txs(10, 0, TIMEOUT), mock_sender_txs(&[0, 1, 2])].concat();
+    for tx in txs {
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let sys_tx = mock_system_script_signed_tx(&priv_key, &priv_key.pub_key(), TIMEOUT, 0, true);
+    mempool.insert(Context::new(), sys_tx).await.unwrap();
+
+    let dump = mempool.dump();
+    assert_eq!(dump.len(), 14);
+
+    let restored = MemPoolImpl::new(POOL_SIZE, HashMemPoolAdapter::new(), dump).await;
+    assert_eq!(restored.hashes(), mempool.hashes());
+    assert_eq!(restored.len(), mempool.len());
+    assert_eq!(
+        restored.system_script_queue_len(),
+        mempool.system_script_queue_len()
+    );
+}
+
+#[tokio::test]
+async fn test_clear() {
+    let mempool = default_mempool().await;
+    for tx in default_mock_txs(10) {
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let sys_tx = mock_system_script_signed_tx(&priv_key, &priv_key.pub_key(), TIMEOUT, 0, true);
+    mempool.insert(Context::new(), sys_tx).await.unwrap();
+
+    assert_eq!(mempool.clear(), 11);
+    assert_eq!(mempool.len(), 0);
+    assert_eq!(mempool.get_tx_cache().co_queue_len(), 0);
+    assert_eq!(mempool.system_script_queue_len(), 0);
+    assert!(mempool
+        .package(Context::new(), CYCLE_LIMIT.into(), TX_NUM_LIMIT)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_storage_cache() {
+    let config = MemPoolConfig::new(POOL_SIZE).storage_cache_size(Some(16));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let adapter = mempool.get_adapter();
+    let tx = default_mock_txs(1).remove(0);
+    let hashes = vec![tx.transaction.hash];
+    adapter.storage_txs.insert(tx.transaction.hash, tx);
+
+    for _ in 0..2 {
+        let txs = mempool
+            .get_full_txs(Context::new(), None, &hashes)
+            .await
+            .unwrap();
+        assert_eq!(txs[0].transaction.hash, hashes[0]);
+    }
+    assert_eq!(adapter.storage_reads.load(Ordering::SeqCst), 1);
+
+    mempool.flush(Context::new(), &[]).await.unwrap();
+    mempool
+        .get_full_txs(Context::new(), None, &hashes)
+        .await
+        .unwrap();
+    assert_eq!(adapter.storage_reads.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_check_tx() {
+    let mut txs = mock_sender_txs(&[4, 5]);
+    txs.extend(mock_txs(0, 1, TIMEOUT));
+    let adapter = HashMemPoolAdapter::new();
+    adapter.account_nonces.insert(txs[0].sender, U256::from(4));
+    let mempool = MemPoolImpl::with_config(MemPoolConfig::new(POOL_SIZE), adapter, vec![]).await;
+
+    // The stale nonce and the bad signature fail
+    for (tx, ok) in txs.into_iter().zip([false, true, false]) {
+        let checked = mempool.check_tx(Context::new(), &tx).await;
+        assert_eq!(checked.is_ok(), ok);
+        assert_eq!(mempool.len(), 0);
+
+        let inserted = mempool.insert(Context::new(), tx).await;
+        assert_eq!(inserted.is_ok(), ok);
+    }
+    assert_eq!(mempool.len(), 1);
+}
+
+#[tokio::test]
+async fn test_max_missing_txs() {
+    let config = MemPoolConfig::new(POOL_SIZE).max_missing_txs(Some(2));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let adapter = mempool.get_adapter();
+    let txs = default_mock_txs(4);
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    for tx in txs[1..].iter() {
+        adapter.storage_txs.insert(tx.transaction.hash, tx.clone());
+    }
+    let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+    let full_txs = mempool
+        .get_full_txs(Context::new(), None, &hashes[..3])
+        .await
+        .unwrap();
+    assert_eq!(full_txs.len(), 3);
+    assert_eq!(adapter.storage_reads.load(Ordering::SeqCst), 1);
+
+    assert_mempool_err(
+        mempool.get_full_txs(Context::new(), None, &hashes).await,
+        "TooManyMissing",
+    );
+    assert_eq!(adapter.storage_reads.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_get_full_txs_partial() {
+    let mempool = default_mempool().await;
+    let adapter = mempool.get_adapter();
+    let txs = default_mock_txs(6);
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    adapter
+        .storage_txs
+        .insert(txs[1].transaction.hash, txs[1].clone());
+    adapter
+        .storage_txs
+        .insert(txs[2].transaction.hash, txs[2].clone());
+    let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+    let (full_txs, missing) = mempool
+        .get_full_txs_partial(Context::new(), None, &hashes)
+        .await
+        .unwrap();
+    assert_eq!(full_txs, txs[..3].to_vec());
+    assert_eq!(missing, hashes[3..].to_vec());
+
+    assert_mempool_err(
+        mempool.get_full_txs(Context::new(), None, &hashes).await,
+        "MisMatch",
+    );
+}
+
+#[tokio::test]
+async fn test_subscribe() {
+    let mempool = default_mempool().await;
+    let mut rx = mempool.subscribe();
+    // A dropped subscriber doesn't hold the inserts back
+    drop(mempool.subscribe());
+
+    let txs = default_mock_txs(6);
+    for (i, tx) in txs.iter().enumerate() {
+        let ctx = if i % 2 == 0 {
+            Context::new()
+        } else {
+            Context::new().mark_network_origin_new_txs()
+        };
+        mempool.insert(ctx, tx.clone()).await.unwrap();
+    }
+
+    for tx in txs.iter() {
+        assert_eq!(
+            mempool.next_subscribed(&mut rx).await,
+            Some(tx.transaction.hash)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_subscription_lag() {
+    let lagged = &common_apm::metrics::mempool::MEMPOOL_SUBSCRIPTION_LAG_COUNTER;
+    let txs = default_mock_txs(4);
+
+    let config = MemPoolConfig::new(POOL_SIZE).subscription_capacity(2);
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let mut rx = mempool.subscribe();
+    let before = lagged.get();
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+
+    // The two oldest hashes are dropped
+    assert_eq!(
+        mempool.next_subscribed(&mut rx).await,
+        Some(txs[2].transaction.hash)
+    );
+    assert_eq!(
+        mempool.next_subscribed(&mut rx).await,
+        Some(txs[3].transaction.hash)
+    );
+    assert!(lagged.get() - before >= 2);
+
+    let config = MemPoolConfig::new(POOL_SIZE)
+        .subscription_capacity(2)
+        .subscription_lag(SubscriptionLag::Close);
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let mut rx = mempool.subscribe();
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    assert_eq!(mempool.next_subscribed(&mut rx).await, None);
+}
+
+#[tokio::test]
+async fn test_recover_senders() {
+    let mempool = default_mempool().await;
+    let mut txs = mock_txs(12, 1, TIMEOUT);
+    // Tamper a signed tx
+    txs[3].transaction.unsigned.value = 100u64.into();
+
+    let res = mempool.recover_senders(&txs).await;
+    assert_eq!(res.len(), txs.len());
+    for (i, (tx, res)) in txs.iter().zip(res.into_iter()).enumerate() {
+        match i {
+            3 => assert_mempool_err(res, "InvalidSender"),
+            12 => assert!(res.is_err()),
+            _ => assert_eq!(res.unwrap(), tx.sender),
+        }
+    }
+    assert!(mempool.is_empty());
+}
+
+#[tokio::test]
+async fn test_min_acceptable_fee() {
+    let mempool = default_mempool().await;
+    assert_eq!(mempool.min_acceptable_fee(), U256::zero());
+
+    let config = MemPoolConfig::new(POOL_SIZE).min_gas_price(5u64.into());
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    assert_eq!(mempool.min_acceptable_fee(), U256::from(5u64));
+
+    let txs = mock_gas_price_txs(&[4, 5, 6]);
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[0].clone()).await,
+        "Underpriced { tx_gas_price: 4, min_gas_price: 5 }",
+    );
+    for tx in txs[1..].iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    assert_eq!(mempool.len(), 2);
+
+    // System script txs are exempt, the mocked ones are priced 1
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let tx = mock_system_script_signed_tx(&priv_key, &priv_key.pub_key(), TIMEOUT, 0, true);
+    mempool.insert(Context::new(), tx).await.unwrap();
+    assert_eq!(mempool.len(), 3);
+}
+
+#[tokio::test]
+async fn test_pressure_floor() {
+    let config = MemPoolConfig::new(10)
+        .min_gas_price(2u64.into())
+        .pressure_threshold(Some(50));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let txs = mock_gas_price_txs(&[3, 4, 5, 6, 7]);
+    for tx in txs[..4].iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    assert_eq!(mempool.min_acceptable_fee(), U256::from(2u64));
+
+    // Under pressure the floor is the cheapest pooled tx
+    mempool
+        .insert(Context::new(), txs[4].clone())
+        .await
+        .unwrap();
+    assert_eq!(mempool.min_acceptable_fee(), U256::from(3u64));
+    assert_mempool_err(
+        mempool
+            .insert(Context::new(), mock_gas_price_txs(&[2]).remove(0))
+            .await,
+        "Underpriced { tx_gas_price: 2, min_gas_price: 3 }",
+    );
+    mempool.remove_tx(&txs[0].transaction.hash);
+    mempool
+        .insert(Context::new(), mock_gas_price_txs(&[5]).remove(0))
+        .await
+        .unwrap();
+    assert_eq!(mempool.min_acceptable_fee(), U256::from(4u64));
+
+    // Drained below the threshold only the static floor is left
+    for tx in txs[1..3].iter() {
+        mempool.remove_tx(&tx.transaction.hash);
+    }
+    assert_eq!(mempool.min_acceptable_fee(), U256::from(2u64));
+}
+
+#[tokio::test]
+async fn test_system_script_insert_failure() {
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let txs = (0..3)
+        .map(|nonce| {
+            mock_system_script_signed_tx(&priv_key, &priv_key.pub_key(), TIMEOUT, nonce, true)
+        })
+        .collect::<Vec<_>>();
+    let config = MemPoolConfig::new(POOL_SIZE).max_system_script_txs(Some(2));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+
+    for tx in txs[..2].iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[2].clone()).await,
+        "SystemScriptInsert",
+    );
+    assert!(!mempool.get_tx_cache().contains(&txs[2].transaction.hash));
+
+    // Normal txs don't count towards the limit
+    let tx = default_mock_txs(1).remove(0);
+    mempool.insert(Context::new(), tx).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_wrong_chain() {
+    let txs = default_mock_txs(2);
+    let config = MemPoolConfig::new(POOL_SIZE).chain_id(Some(txs[0].transaction.chain_id));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    assert_ne!(txs[1].transaction.chain_id, txs[0].transaction.chain_id);
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[1].clone()).await,
+        "WrongChain",
+    );
+    assert_eq!(mempool.len(), 1);
+}
+
+#[tokio::test]
+async fn test_set_min_gas_price() {
+    let mempool = default_mempool().await;
+    let txs = mock_gas_price_txs(&[4, 5, 6, 7]);
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+
+    mempool.set_min_gas_price(6u64.into());
+    assert_eq!(mempool.min_acceptable_fee(), U256::from(6u64));
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[1].clone()).await,
+        "Underpriced",
+    );
+    mempool
+        .insert(Context::new(), txs[2].clone())
+        .await
+        .unwrap();
+    // The txs pooled under the former floor stay
+    assert_eq!(mempool.len(), 2);
+
+    mempool.set_min_gas_price(U256::zero());
+    mempool
+        .insert(Context::new(), txs[1].clone())
+        .await
+        .unwrap();
+    mempool
+        .insert(Context::new(), txs[3].clone())
+        .await
+        .unwrap();
+    assert_eq!(mempool.len(), 4);
+}
+
+#[tokio::test]
+async fn test_zero_gas_limit() {
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    let zero_gas_tx = |is_call_system_script| {
+        let mut raw = mock_transaction(0, is_call_system_script);
+        raw.gas_limit = U256::zero();
+        sign_tx(&priv_key, &pub_key, raw, true)
+    };
+
+    let mempool = default_mempool().await;
+    assert_mempool_err(
+        mempool.insert(Context::new(), zero_gas_tx(false)).await,
+        "ZeroGasLimit",
+    );
+    assert_mempool_err(
+        mempool.insert(Context::new(), zero_gas_tx(true)).await,
+        "ZeroGasLimit",
+    );
+
+    let config = MemPoolConfig::new(POOL_SIZE).zero_gas_system_script(true);
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    assert_mempool_err(
+        mempool.insert(Context::new(), zero_gas_tx(false)).await,
+        "ZeroGasLimit",
+    );
+    mempool
+        .insert(Context::new(), zero_gas_tx(true))
+        .await
+        .unwrap();
+    assert_eq!(mempool.len(), 1);
+}
+
+#[tokio::test]
+async fn test_ensure_dup_nonce() {
+    let mempool = default_mempool().await;
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    // Same sender and nonce, distinct data
+    let tx_a = mock_signed_tx(&priv_key, &pub_key, TIMEOUT, 0, true);
+    let tx_b = mock_signed_tx(&priv_key, &pub_key, TIMEOUT, 0, true);
+    let tx_c = mock_signed_tx(&priv_key, &pub_key, TIMEOUT, 1, true);
+    let hashes = [
+        tx_a.transaction.hash,
+        tx_b.transaction.hash,
+        tx_c.transaction.hash,
+    ];
+    assert_ne!(hashes[0], hashes[1]);
+
+    for tx in [tx_a, tx_b, tx_c] {
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+
+    mempool
+        .ensure_order_txs(Context::new(), None, &[hashes[0], hashes[2]])
+        .await
+        .unwrap();
+    assert_mempool_err(
+        mempool
+            .ensure_order_txs(Context::new(), None, &hashes)
+            .await,
+        "EnsureDupNonce",
+    );
+}
+
+#[tokio::test]
+async fn test_reconcile_committed_block() {
+    let mempool = default_mempool().await;
+    let keys = (0..3)
+        .map(|_| Secp256k1RecoverablePrivateKey::generate(&mut OsRng))
+        .collect::<Vec<_>>();
+    let sender_txs = |key: &Secp256k1RecoverablePrivateKey, nonces: std::ops::Range<u64>| {
+        nonces
+            .map(|nonce| mock_signed_tx(key, &key.pub_key(), TIMEOUT, nonce, true))
+            .collect::<Vec<_>>()
+    };
+    let txs_a = sender_txs(&keys[0], 0..4);
+    let txs_b = sender_txs(&keys[1], 0..2);
+    let txs_c = sender_txs(&keys[2], 0..2);
+
+    for tx in txs_a.iter().chain(txs_b.iter()).chain(txs_c[..1].iter()) {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    assert_eq!(mempool.len(), 7);
+
+    // The nonce 1 tx of sender c was only seen by storage
+    let stored = txs_c[1].clone();
+    mempool
+        .get_adapter()
+        .storage_txs
+        .insert(stored.transaction.hash, stored);
+
+    let mut block = Block::default();
+    block.tx_hashes = vec![
+        txs_a[1].transaction.hash,
+        txs_b[0].transaction.hash,
+        txs_c[1].transaction.hash,
+        Hasher::digest(Bytes::from("unknown")),
+    ];
+    let removed = mempool
+        .reconcile_committed_block(Context::new(), &block)
+        .await
+        .unwrap();
+
+    // a: nonce 0 and 1, b: nonce 0, c: nonce 0
+    assert_eq!(removed, 4);
+    assert_eq!(mempool.len(), 3);
+    let pool = mempool.get_tx_cache();
+    assert_eq!(pool.get_tx_count_by_address(txs_a[0].sender), 2);
+    assert_eq!(pool.get_tx_count_by_address(txs_b[0].sender), 1);
+    assert_eq!(pool.get_tx_count_by_address(txs_c[0].sender), 0);
+    for tx in [&txs_a[2], &txs_a[3], &txs_b[1]] {
+        assert!(pool.contains(&tx.transaction.hash));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_diff_against() {
+    let mempool = default_mempool().await;
+    let txs = default_mock_txs(6);
+    let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+    for tx in txs[..4].iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+
+    let snapshot = mempool.hashes();
+    assert_eq!(snapshot.len(), 4);
+    assert_eq!(mempool.diff_against(&snapshot), (vec![], vec![]));
+
+    for tx in txs[4..].iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    mempool.flush(Context::new(), &hashes[..1]).await.unwrap();
+
+    let (added, removed) = mempool.diff_against(&snapshot);
+    let mut expect_added = hashes[4..].to_vec();
+    expect_added.sort();
+    assert_eq!(added, expect_added);
+    assert_eq!(removed, vec![hashes[0]]);
+}
+
+#[tokio::test]
+async fn test_recent_rejections() {
+    let config = MemPoolConfig::new(POOL_SIZE).rejection_log_size(Some(3));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let txs = default_mock_txs(4);
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    assert!(mempool.recent_rejections(10).is_empty());
+
+    // Invalid signature, zero gas limit, blocked sender
+    let invalid = mock_txs(0, 1, TIMEOUT).remove(0);
+    let _ = mempool.insert(Context::new(), invalid.clone()).await;
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let mut raw = mock_transaction(0, false);
+    raw.gas_limit = U256::zero();
+    let zero_gas = sign_tx(&priv_key, &priv_key.pub_key(), raw, true);
+    let _ = mempool.insert(Context::new(), zero_gas.clone()).await;
+    mempool.add_blocked(txs[1].sender);
+    let _ = mempool.insert(Context::new(), txs[1].clone()).await;
+
+    let reasons = |records: Vec<RejectionRecord>| {
+        records
+            .into_iter()
+            .map(|r| (r.hash, r.reason))
+            .collect::<Vec<_>>()
+    };
+    let records = reasons(mempool.recent_rejections(10));
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].0, txs[1].transaction.hash);
+    assert!(records[0].1.contains("BlockedSender"));
+    assert_eq!(records[1].0, zero_gas.transaction.hash);
+    assert!(records[1].1.contains("ZeroGasLimit"));
+    assert_eq!(records[2].0, invalid.transaction.hash);
+
+    // The oldest record ages out
+    mempool.add_blocked(txs[2].sender);
+    let _ = mempool.insert(Context::new(), txs[2].clone()).await;
+    let records = reasons(mempool.recent_rejections(10));
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].0, txs[2].transaction.hash);
+    assert!(records
+        .iter()
+        .all(|(hash, _)| hash != &invalid.transaction.hash));
+    assert_eq!(mempool.recent_rejections(1).len(), 1);
+}
+
+#[tokio::test]
+async fn test_on_evict() {
+    let config = MemPoolConfig::new(4).max_residence(Some(Duration::from_millis(50)));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let records = Arc::clone(&evicted);
+    mempool.on_evict(move |hash, reason| records.lock().push((hash, reason)));
+    let take = || std::mem::take(&mut *evicted.lock());
+
+    let txs = mock_sender_txs(&[0, 0]);
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    assert_eq!(take(), vec![(
+        txs[0].transaction.hash,
+        EvictReason::Replaced
+    )]);
+    // The replaced tx is not reported again
+    mempool.flush(Context::new(), &[]).await.unwrap();
+    assert!(take().is_empty());
+
+    mempool
+        .flush(Context::new(), &[txs[1].transaction.hash])
+        .await
+        .unwrap();
+    assert_eq!(take(), vec![(
+        txs[1].transaction.hash,
+        EvictReason::Flushed
+    )]);
+
+    let txs = mock_gas_price_txs(&[1, 1, 1, 1, 1, 2]);
+    for tx in txs[..5].iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    mempool
+        .insert(Context::new(), txs[5].clone())
+        .await
+        .unwrap();
+    let pool_full = take();
+    assert_eq!(pool_full.len(), 1);
+    assert_eq!(pool_full[0].1, EvictReason::PoolFull);
+
+    let pooled = txs
+        .iter()
+        .filter(|tx| mempool.get_tx_cache().contains(&tx.transaction.hash))
+        .collect::<Vec<_>>();
+    assert!(mempool.remove_tx(&pooled[0].transaction.hash));
+    assert_eq!(take(), vec![(
+        pooled[0].transaction.hash,
+        EvictReason::Removed
+    )]);
+    assert_eq!(mempool.remove_by_sender(&pooled[1].sender), 1);
+    assert_eq!(take(), vec![(
+        pooled[1].transaction.hash,
+        EvictReason::SenderRemoved
+    )]);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let expired = mempool.sweep_expired();
+    let reasons = take();
+    assert_eq!(reasons.len(), expired);
+    assert!(reasons
+        .iter()
+        .all(|(_, reason)| *reason == EvictReason::Expired));
+
+    let tx = default_mock_txs(1).remove(0);
+    mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    assert_eq!(mempool.clear(), 1);
+    assert_eq!(take(), vec![(tx.transaction.hash, EvictReason::Flushed)]);
+}
+
+#[tokio::test]
+async fn test_insert_with_eviction() {
+    let config = MemPoolConfig::new(4).eviction_archive_size(Some(8));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let cheap_txs = mock_gas_price_txs(&[1; 5]);
+    let cheap_hashes = cheap_txs
+        .iter()
+        .map(|tx| tx.transaction.hash)
+        .collect::<HashSet<_>>();
+    for tx in cheap_txs.into_iter() {
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+    let full_len = mempool.len();
+    assert!(mempool.get_tx_cache().reach_limit());
+
+    // No cheaper tx to evict
+    let txs = mock_gas_price_txs(&[1, 5, 5]);
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[0].clone()).await,
+        "ReachLimit",
+    );
+
+    mempool
+        .insert(Context::new(), txs[1].clone())
+        .await
+        .unwrap();
+    assert_eq!(mempool.len(), full_len);
+    let evicted = cheap_hashes
+        .iter()
+        .filter(|hash| !mempool.get_tx_cache().contains(hash))
+        .collect::<Vec<_>>();
+    assert_eq!(evicted.len(), 1);
+    assert_eq!(
+        mempool.eviction_reason(evicted[0]).await,
+        Some(EvictReason::PoolFull)
+    );
+
+    let evicted = mempool
+        .get_tx_cache()
+        .insert_with_eviction(txs[2].clone(), false)
+        .unwrap();
+    assert!(cheap_hashes.contains(&evicted.unwrap()));
+    assert_eq!(mempool.len(), full_len);
+
+    // The removed txs are not evicted again
+    let cheapest = cheap_hashes
+        .iter()
+        .find(|hash| mempool.get_tx_cache().contains(hash))
+        .copied()
+        .unwrap();
+    mempool.get_tx_cache().remove(&cheapest);
+    let txs = mock_gas_price_txs(&[5, 6]);
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    let evicted = mempool
+        .get_tx_cache()
+        .insert_with_eviction(txs[1].clone(), false)
+        .unwrap()
+        .unwrap();
+    assert!(cheap_hashes.contains(&evicted));
+    assert_ne!(evicted, cheapest);
+    assert_eq!(mempool.len(), full_len);
+
+    // Every concurrent eviction gets the room it freed
+    let mempool = Arc::new(mempool);
+    let handles = mock_gas_price_txs(&vec![9; full_len])
+        .into_iter()
+        .map(|tx| {
+            let mempool = Arc::clone(&mempool);
+            std::thread::spawn(move || mempool.get_tx_cache().insert_with_eviction(tx, false))
+        })
+        .collect::<Vec<_>>();
+    for handle in handles.into_iter() {
+        assert!(handle.join().unwrap().unwrap().is_some());
+    }
+    assert_eq!(mempool.len(), full_len);
+}
+
+#[tokio::test]
+async fn test_get_txs_by_address() {
+    let mempool = default_mempool().await;
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    let txs = [2u64, 0, 1]
+        .iter()
+        .map(|nonce| mock_signed_tx(&priv_key, &pub_key, TIMEOUT, *nonce, true))
+        .collect::<Vec<_>>();
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    mempool
+        .insert(Context::new(), default_mock_txs(1).remove(0))
+        .await
+        .unwrap();
+
+    let pending = mempool
+        .get_txs_by_address(Context::new(), txs[0].sender)
+        .await
+        .unwrap();
+    let nonces = pending
+        .iter()
+        .map(|tx| tx.transaction.unsigned.nonce.as_u64())
+        .collect::<Vec<_>>();
+    assert_eq!(nonces, vec![0, 1, 2]);
+    assert!(pending.iter().all(|tx| tx.sender == txs[0].sender));
+
+    let unknown = mempool
+        .get_txs_by_address(Context::new(), H160::from_low_u64_be(1))
+        .await
+        .unwrap();
+    assert!(unknown.is_empty());
+}
+
+#[tokio::test]
+async fn test_spawn_reaper() {
+    let config = MemPoolConfig::new(POOL_SIZE).max_residence(Some(Duration::from_millis(50)));
+    let mempool =
+        Arc::new(MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await);
+    mempool.spawn_reaper(Duration::from_millis(20));
+
+    for tx in default_mock_txs(3).into_iter() {
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+    assert_eq!(mempool.len(), 3);
+
+    // Max residence plus a reap cycle, with some slack
+    tokio::time::sleep(Duration::from_millis(120)).await;
+    assert_eq!(mempool.len(), 0);
+}
+
+#[tokio::test]
+async fn test_insert_batch() {
+    let mempool = default_mempool().await;
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    let mut zero_gas = mock_transaction(3, false);
+    zero_gas.gas_limit = U256::zero();
+    let other_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let blocked = mock_signed_tx(&other_key, &other_key.pub_key(), TIMEOUT, 0, true);
+    mempool.add_blocked(blocked.sender);
+
+    let txs = vec![
+        mock_signed_tx(&priv_key, &pub_key, TIMEOUT, 0, true),
+        mock_signed_tx(&priv_key, &pub_key, TIMEOUT, 1, false),
+        mock_signed_tx(&priv_key, &pub_key, TIMEOUT, 2, true),
+        sign_tx(&priv_key, &pub_key, zero_gas, true),
+        blocked,
+    ];
+    let res = mempool
+        .insert_batch(Context::new(), txs.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(res.len(), txs.len());
+    assert!(res[0].is_ok());
+    assert!(res[1].is_err());
+    assert!(res[2].is_ok());
+    assert!(res[3]
+        .as_ref()
+        .unwrap_err()
+        .to_string()
+        .contains("ZeroGasLimit"));
+    assert!(res[4]
+        .as_ref()
+        .unwrap_err()
+        .to_string()
+        .contains("BlockedSender"));
+    assert_eq!(mempool.len(), 2);
+    let pool = mempool.get_tx_cache();
+    assert!(pool.get_by_hash(&txs[0].transaction.hash).is_some());
+    assert!(pool.get_by_hash(&txs[2].transaction.hash).is_some());
+}
+
+#[tokio::test]
+async fn test_insert_batch_dup_and_eviction() {
+    let config = MemPoolConfig::new(4).eviction_archive_size(Some(8));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let cheap_txs = mock_gas_price_txs(&[1; 5]);
+    for tx in cheap_txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    let full_len = mempool.len();
+    let broadcast = mempool.get_adapter().network_txs.len();
+    let mut rx = mempool.subscribe();
+
+    // The repeated and the pooled txs are neither notified nor broadcast again
+    let tx = mock_gas_price_txs(&[5]).remove(0);
+    let res = mempool
+        .insert_batch(Context::new(), vec![
+            tx.clone(),
+            tx.clone(),
+            cheap_txs[0].clone(),
+        ])
+        .await
+        .unwrap();
+    let mut res = res.into_iter();
+    assert!(res.next().unwrap().is_ok());
+    assert_mempool_err(res.next().unwrap(), "Dup");
+    assert_mempool_err(res.next().unwrap(), "Dup");
+    assert_eq!(rx.try_recv().unwrap(), tx.transaction.hash);
+    assert!(rx.try_recv().is_err());
+    assert_eq!(mempool.get_adapter().network_txs.len(), broadcast + 1);
+
+    // The full pool made room like `insert`
+    assert_eq!(mempool.len(), full_len);
+    let evicted = cheap_txs
+        .iter()
+        .map(|tx| tx.transaction.hash)
+        .filter(|hash| !mempool.get_tx_cache().contains(hash))
+        .collect::<Vec<_>>();
+    assert_eq!(evicted.len(), 1);
+    assert_eq!(
+        mempool.eviction_reason(&evicted[0]).await,
+        Some(EvictReason::PoolFull)
+    );
+}
+
+#[tokio::test]
+async fn test_available_len() {
+    let mempool = default_mempool().await;
+    let txs = default_mock_txs(5);
+    let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+    for tx in txs.into_iter() {
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+    assert_eq!(mempool.available_len(), 5);
+
+    mempool.reserve(&hashes[..3]);
+    // Reserving twice or reserving an unknown tx changes nothing
+    mempool.reserve(&[hashes[0], Hash::default()]);
+    assert_eq!(mempool.len(), 5);
+    assert_eq!(mempool.available_len(), 2);
+
+    mempool.release(&hashes[..2]);
+    assert_eq!(mempool.available_len(), 4);
+
+    mempool.release(&hashes[2..3]);
+    assert_eq!(mempool.available_len(), mempool.len());
+}
+
+#[tokio::test]
+async fn test_blocked_sender() {
+    let mempool = default_mempool().await;
+    let txs = mock_sender_txs(&[1, 2, 3]);
+    let sender = txs[0].sender;
+
+    mempool.add_blocked(sender);
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[0].clone()).await,
+        "BlockedSender",
+    );
+    assert_eq!(mempool.len(), 0);
+
+    assert!(mempool.remove_blocked(&sender));
+    for tx in txs.into_iter() {
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+    assert_eq!(mempool.len(), 3);
+
+    mempool.add_blocked(sender);
+    assert_eq!(mempool.remove_by_sender(&sender), 3);
+    assert_eq!(mempool.len(), 0);
+}
+
+#[tokio::test]
+async fn test_remove_tx() {
+    let mempool = default_mempool().await;
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    let txs = (0..3)
+        .map(|nonce| mock_signed_tx(&priv_key, &pub_key, TIMEOUT, nonce, true))
+        .collect::<Vec<_>>();
+    let sender = txs[0].sender;
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    assert_eq!(mempool.get_tx_cache().get_tx_count_by_address(sender), 3);
+
+    assert!(mempool.remove_tx(&txs[1].transaction.hash));
+    assert!(!mempool.remove_tx(&txs[1].transaction.hash));
+    assert_eq!(mempool.len(), 2);
+    assert_eq!(mempool.get_tx_cache().get_tx_count_by_address(sender), 2);
+
+    let pool = mempool.get_tx_cache();
+    assert!(pool.get_by_hash(&txs[0].transaction.hash).is_some());
+    assert!(pool.get_by_hash(&txs[1].transaction.hash).is_none());
+    assert!(pool.get_by_hash(&txs[2].transaction.hash).is_some());
+}
+
+#[tokio::test]
+async fn test_sender_limit() {
+    let config = MemPoolConfig::new(POOL_SIZE).max_txs_per_sender(Some(2));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let txs = mock_sender_txs(&[0, 1, 2]);
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    mempool
+        .insert(Context::new(), txs[1].clone())
+        .await
+        .unwrap();
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[2].clone()).await,
+        "SenderLimit",
+    );
+
+    // Another sender is not affected
+    mempool
+        .insert(Context::new(), default_mock_txs(1).remove(0))
+        .await
+        .unwrap();
+    assert_eq!(mempool.len(), 3);
+
+    // Nor do concurrent inserts of a sender exceed the limit together
+    let mempool = Arc::new(mempool);
+    let handles = mock_sender_txs(&[0, 1, 2, 3, 4, 5, 6, 7])
+        .into_iter()
+        .map(|tx| {
+            let mempool = Arc::clone(&mempool);
+            std::thread::spawn(move || mempool.get_tx_cache().insert(tx, false))
+        })
+        .collect::<Vec<_>>();
+    let inserted = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .filter(Result::is_ok)
+        .count();
+    assert_eq!(inserted, 2);
+}
+
+#[tokio::test]
+async fn test_max_sender_depth() {
+    let mempool = default_mempool().await;
+    assert_eq!(mempool.max_sender_depth(), 0);
+
+    let txs = mock_sender_txs(&[0, 1, 2]);
+    for (i, tx) in txs.iter().enumerate() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+        assert_eq!(mempool.max_sender_depth(), i + 1);
+    }
+    mempool
+        .insert(Context::new(), default_mock_txs(1).remove(0))
+        .await
+        .unwrap();
+    assert_eq!(mempool.max_sender_depth(), 3);
+
+    let committed = txs[..2]
+        .iter()
+        .map(|tx| tx.transaction.hash)
+        .collect::<Vec<_>>();
+    mempool.flush(Context::new(), &committed).await.unwrap();
+    assert_eq!(mempool.max_sender_depth(), 1);
+
+    mempool.remove_tx(&txs[2].transaction.hash);
+    assert_eq!(mempool.max_sender_depth(), 1);
+}
+
+#[tokio::test]
+async fn test_sender_limit_replace_and_remove() {
+    let config = MemPoolConfig::new(POOL_SIZE).max_txs_per_sender(Some(2));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    let sender = mock_signed_tx(&priv_key, &pub_key, TIMEOUT, 0, true).sender;
+    let tx = |nonce, gas_price: u64| {
+        let mut raw = mock_transaction(nonce, false);
+        raw.gas_price = gas_price.into();
+        sign_tx(&priv_key, &pub_key, raw, true)
+    };
+
+    let pooled = [tx(0, 1), tx(1, 1)];
+    for tx in pooled.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+
+    // Replace a pooled nonce at the limit
+    mempool.insert(Context::new(), tx(1, 2)).await.unwrap();
+    assert_eq!(mempool.get_tx_cache().get_tx_count_by_address(sender), 2);
+    assert_mempool_err(
+        mempool.insert(Context::new(), tx(2, 1)).await,
+        "SenderLimit",
+    );
+
+    assert!(mempool.remove_tx(&pooled[0].transaction.hash));
+    mempool.insert(Context::new(), tx(2, 1)).await.unwrap();
+    assert_eq!(mempool.get_tx_cache().get_tx_count_by_address(sender), 2);
+}
+
+#[tokio::test]
+async fn test_oldest_txs() {
+    let mempool = default_mempool().await;
+    let txs = default_mock_txs(3);
+
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let oldest = mempool.oldest_txs(2);
+    assert_eq!(oldest.len(), 2);
+    assert_eq!(oldest[0].0, txs[0].transaction.hash);
+    assert_eq!(oldest[1].0, txs[1].transaction.hash);
+    assert!(oldest[0].1 > oldest[1].1);
+    assert!(oldest[1].1 >= Duration::from_millis(20));
+}
+
+#[tokio::test]
+async fn test_pool_stats() {
+    let mempool = Arc::new(default_mempool().await);
+    let mut txs = mock_gas_price_txs(&[3, 1, 7, 5]);
+    txs.extend(mock_sender_txs(&[1, 2]));
+    txs.extend(mock_sys_txs(2));
+    concurrent_insert(txs, Arc::clone(&mempool)).await;
+    // Wait for the co-queue to be drained so that it is stable between calls.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let stats = mempool.stats();
+    assert_eq!(stats.len, mempool.len());
+    assert_eq!(stats.len, 6);
+    assert_eq!(stats.co_queue_len, mempool.co_queue_len());
+    assert_eq!(
+        stats.system_script_queue_len,
+        mempool.system_script_queue_len()
+    );
+    assert_eq!(stats.system_script_queue_len, 2);
+    assert_eq!(stats.distinct_senders, mempool.distinct_senders());
+    assert_eq!(stats.distinct_senders, 5);
+    assert_eq!(stats.total_gas_limit, U256::from(6u64));
+    assert_eq!(stats.min_gas_price, Some(U256::one()));
+    assert_eq!(stats.max_gas_price, Some(U256::from(7u64)));
+}
+
+#[tokio::test]
+async fn test_sweep_expired() {
+    let config = MemPoolConfig::new(POOL_SIZE).max_residence(Some(Duration::from_millis(50)));
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+
+    let txs = default_mock_txs(2);
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    mempool
+        .insert(Context::new(), txs[1].clone())
+        .await
+        .unwrap();
+
+    assert_eq!(mempool.sweep_expired(), 1);
+    assert_eq!(mempool.len(), 1);
+    assert!(!mempool.get_tx_cache().contains(&txs[0].transaction.hash));
+    assert!(mempool.get_tx_cache().contains(&txs[1].transaction.hash));
+}
+
+#[tokio::test]
+async fn test_broadcast_failure() {
+    let txs = default_mock_txs(2);
+
+    let mempool = MemPoolImpl::new(POOL_SIZE, HashMemPoolAdapter::broadcast_fail(), vec![]).await;
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    assert_eq!(mempool.len(), 1);
+    assert!(mempool.get_tx_cache().contains(&txs[0].transaction.hash));
+
+    let config = MemPoolConfig::new(POOL_SIZE).strict_broadcast(true);
+    let mempool =
+        MemPoolImpl::with_config(config, HashMemPoolAdapter::broadcast_fail(), vec![]).await;
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[1].clone()).await,
+        "Internal",
+    );
+    assert_eq!(mempool.len(), 1);
+}
+
+#[tokio::test]
+async fn test_propagation_mode() {
+    // The second tx has a bad signature and fails only `check_transaction`
+    let txs = mock_txs(1, 1, TIMEOUT);
+    let bad_hash = txs[1].transaction.hash;
+
+    let mempool = default_mempool().await;
+    assert_mempool_err(
+        mempool.insert(Context::new(), txs[1].clone()).await,
+        "VerifySignature",
+    );
+    assert!(!mempool.get_adapter().network_txs.contains_key(&bad_hash));
+
+    let config = MemPoolConfig::new(POOL_SIZE).propagation_mode(PropagationMode::Eager);
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+    assert!(mempool
+        .insert(Context::new(), txs[1].clone())
+        .await
+        .is_err());
+    assert!(mempool.get_adapter().network_txs.contains_key(&bad_hash));
+    assert_eq!(mempool.len(), 0);
+
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+    assert!(mempool
+        .get_adapter()
+        .network_txs
+        .contains_key(&txs[0].transaction.hash));
+    assert_eq!(mempool.len(), 1);
+}
+
+#[tokio::test]
+async fn test_tx_size_metrics() {
+    let histogram = &common_apm::metrics::mempool::MEMPOOL_TX_SIZE_HISTOGRAM;
+    let config = MemPoolConfig::new(POOL_SIZE).tx_size_metrics(true);
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    let (count, sum) = (histogram.get_sample_count(), histogram.get_sample_sum());
+
+    let mut total_size = 0;
+    for (nonce, data_len) in [0usize, 100, 500].iter().enumerate() {
+        let mut raw = mock_transaction(nonce as u64, false);
+        raw.data = Bytes::from(vec![1u8; *data_len]);
+        let tx = sign_tx(&priv_key, &pub_key, raw, true);
+        total_size += tx.transaction.encode().unwrap().len();
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+
+    // The other tests leave the metric disabled
+    assert_eq!(histogram.get_sample_count() - count, 3);
+    assert_eq!(histogram.get_sample_sum() - sum, total_size as f64);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_size_tracking() {
+    let txs = default_mock_txs(3);
+    let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+    for policy in [SizeTracking::Eager, SizeTracking::Lazy] {
+        let config = MemPoolConfig::new(POOL_SIZE).size_tracking(policy);
+        let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+        for tx in txs.iter() {
+            mempool.insert(Context::new(), tx.clone()).await.unwrap();
         }
-        assert_eq!(mempool.get_tx_cache().len(), $output);
+        let tracked = if policy == SizeTracking::Eager { 3 } else { 0 };
+        assert_eq!(mempool.tx_sizes.len(), tracked);
+
+        for tx in txs.iter() {
+            assert_eq!(
+                mempool.tx_size(&tx.transaction.hash),
+                Some(tx.transaction.encode().unwrap().len())
+            );
+        }
+
+        mempool.flush(Context::new(), &hashes[..2]).await.unwrap();
+        assert_eq!(mempool.tx_size(&hashes[0]), None);
+        assert!(mempool.tx_size(&hashes[2]).is_some());
+        assert!(mempool.tx_sizes.len() <= 1);
+    }
+}
+
+#[tokio::test]
+async fn test_unprepared_package() {
+    let txs = default_mock_txs(2);
+
+    let mempool = default_mempool().await;
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    let package = mempool
+        .package(Context::new(), CYCLE_LIMIT.into(), TX_NUM_LIMIT)
+        .await
+        .unwrap();
+    assert_eq!(package.len(), 2);
+
+    let config = MemPoolConfig::new(POOL_SIZE).unprepared_package(UnpreparedPackage::Reject);
+    let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), txs).await;
+    assert_mempool_err(
+        mempool
+            .package(Context::new(), CYCLE_LIMIT.into(), TX_NUM_LIMIT)
+            .await,
+        "Unprepared",
+    );
+
+    mempool.set_args(Context::new(), H256::default(), CYCLE_LIMIT, MAX_TX_SIZE);
+    let package = mempool
+        .package(Context::new(), CYCLE_LIMIT.into(), TX_NUM_LIMIT)
+        .await
+        .unwrap();
+    assert_eq!(package.len(), 2);
+}
+
+#[tokio::test]
+async fn test_exceed_size_limit() {
+    let mempool = default_mempool().await;
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    let mut raw = mock_transaction(0, false);
+    raw.data = random_bytes(MAX_TX_SIZE as usize * 2);
+    let tx = sign_tx(&priv_key, &pub_key, raw, true);
+    let size = tx.transaction.encode().unwrap().len();
+
+    // Unlimited until the first `set_args`
+    mempool.check_tx(Context::new(), &tx).await.unwrap();
+
+    mempool.set_args(Context::new(), H256::default(), CYCLE_LIMIT, MAX_TX_SIZE);
+    assert_mempool_err(
+        mempool.insert(Context::new(), tx.clone()).await,
+        &format!(
+            "ExceedSizeLimit {{ tx_hash: {:?}, max_tx_size: {}, size: {} }}",
+            tx.transaction.hash, MAX_TX_SIZE, size
+        ),
+    );
+
+    // Nor do the other insert paths take it
+    let res = mempool
+        .insert_batch(Context::new(), vec![tx.clone()])
+        .await
+        .unwrap();
+    assert_mempool_err(res.into_iter().next().unwrap(), "ExceedSizeLimit");
+    let res = mempool
+        .import_txs(Context::new(), vec![tx.clone()], false)
+        .await
+        .unwrap();
+    assert_mempool_err(res.into_iter().next().unwrap(), "ExceedSizeLimit");
+    assert_mempool_err(mempool.insert_trusted(tx.clone()), "ExceedSizeLimit");
+    assert_eq!(mempool.get_adapter().auth_checks.load(Ordering::SeqCst), 1);
+    assert!(mempool.is_empty());
+
+    let tx = default_mock_txs(1).remove(0);
+    mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    assert_eq!(
+        mempool.tx_size(&tx.transaction.hash),
+        Some(tx.transaction.encode().unwrap().len())
+    );
+}
+
+#[tokio::test]
+async fn test_exceed_gas_limit() {
+    let mempool = default_mempool().await;
+    mempool.set_args(Context::new(), H256::default(), 1000, MAX_TX_SIZE);
+    let gas_limit_tx = |gas_limit: U256| {
+        let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+        let pub_key = priv_key.pub_key();
+        let mut raw = mock_transaction(0, false);
+        raw.gas_limit = gas_limit;
+        sign_tx(&priv_key, &pub_key, raw, true)
     };
+
+    // A tx may take the whole block
+    let tx = gas_limit_tx(1000u64.into());
+    mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    assert!(mempool.get_tx_cache().contains(&tx.transaction.hash));
+
+    let tx = gas_limit_tx(1001u64.into());
+    assert_mempool_err(
+        mempool.insert(Context::new(), tx.clone()).await,
+        &format!(
+            "ExceedGasLimit {{ tx_hash: {:?}, gas_limit_config: 1000, gas_limit_tx: 1001 }}",
+            tx.transaction.hash
+        ),
+    );
+    assert_eq!(mempool.len(), 1);
+
+    assert_mempool_err(
+        mempool
+            .insert(Context::new(), gas_limit_tx(U256::max_value()))
+            .await,
+        &format!("gas_limit_tx: {} }}", u64::MAX),
+    );
 }
 
-#[test]
-fn test_dup_order_hashes() {
-    let hashes = vec![
-        Hasher::digest(Bytes::from("test1")),
-        Hasher::digest(Bytes::from("test2")),
-        Hasher::digest(Bytes::from("test3")),
-        Hasher::digest(Bytes::from("test4")),
-        Hasher::digest(Bytes::from("test2")),
-    ];
-    assert!(check_dup_order_hashes(&hashes).is_err());
+#[tokio::test]
+async fn test_package_tie_break() {
+    let mut txs = mock_gas_price_txs(&[7; 6]);
+    txs.extend(default_mock_txs(4));
+    let mut expect = txs.clone();
+    expect.sort_by(|a, b| {
+        let (a, b) = (&a.transaction, &b.transaction);
+        b.unsigned
+            .gas_price
+            .cmp(&a.unsigned.gas_price)
+            .then(a.unsigned.nonce.cmp(&b.unsigned.nonce))
+            .then(a.hash.cmp(&b.hash))
+    });
+    let expect = expect
+        .iter()
+        .map(|tx| tx.transaction.hash)
+        .collect::<Vec<_>>();
 
-    let hashes = vec![
-        Hasher::digest(Bytes::from("test1")),
-        Hasher::digest(Bytes::from("test2")),
-        Hasher::digest(Bytes::from("test3")),
-        Hasher::digest(Bytes::from("test4")),
-    ];
-    assert!(check_dup_order_hashes(&hashes).is_ok());
+    let first = default_mempool().await;
+    let second = default_mempool().await;
+    for tx in txs.iter() {
+        first.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+    for tx in txs.iter().rev() {
+        second.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+
+    for mempool in [&first, &second] {
+        for _ in 0..3 {
+            let package = mempool
+                .package(Context::new(), CYCLE_LIMIT.into(), TX_NUM_LIMIT)
+                .await
+                .unwrap();
+            assert_eq!(package, expect);
+        }
+    }
 }
 
 #[tokio::test]
-async fn test_insert() {
-    // 1. insertion under pool size.
-    insert!(normal(100, 100, 100));
+async fn test_package_local_first() {
+    let mempool = default_mempool().await;
+    let remote_txs = mock_gas_price_txs(&[10; 5]);
+    for tx in remote_txs.iter() {
+        mempool
+            .insert(Context::new().mark_network_origin_new_txs(), tx.clone())
+            .await
+            .unwrap();
+    }
+    let local_tx = mock_gas_price_txs(&[1]).remove(0);
+    mempool
+        .insert(Context::new(), local_tx.clone())
+        .await
+        .unwrap();
 
-    // 2. invalid insertion
-    insert!(invalid(80, 10, 80));
+    let package = mempool
+        .package(Context::new(), CYCLE_LIMIT.into(), 3)
+        .await
+        .unwrap();
+    assert_eq!(package.len(), 3);
+    assert_eq!(package[0], local_tx.transaction.hash);
+
+    // The origin survives a flush
+    mempool
+        .flush(Context::new(), &[remote_txs[0].transaction.hash])
+        .await
+        .unwrap();
+    let package = mempool
+        .package(Context::new(), CYCLE_LIMIT.into(), 3)
+        .await
+        .unwrap();
+    assert_eq!(package[0], local_tx.transaction.hash);
+}
+
+#[tokio::test]
+async fn test_package_revenue() {
+    let mempool = default_mempool().await;
+    let base_fee = U256::from(10u64);
+    // (gas_price, max_priority_fee, gas_limit)
+    let fees = [(15u64, 2u64, 100u64), (12, 5, 50), (8, 3, 30)];
+
+    let mut hashes = Vec::new();
+    for (gas_price, tip, gas_limit) in fees.iter() {
+        let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+        let pub_key = priv_key.pub_key();
+        let mut raw = mock_transaction(0, false);
+        raw.gas_price = (*gas_price).into();
+        raw.max_priority_fee_per_gas = (*tip).into();
+        raw.gas_limit = (*gas_limit).into();
+
+        let tx = sign_tx(&priv_key, &pub_key, raw, true);
+        hashes.push(tx.transaction.hash);
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+    hashes.push(Hasher::digest(Bytes::from_static(b"unknown")));
+
+    // 2 * 100 + min(2, 5) * 50, the last tx can not cover the base fee
+    let expect = U256::from(2u64 * 100 + 2 * 50);
+    assert_eq!(mempool.package_revenue(&hashes, base_fee), expect);
+    assert_eq!(
+        mempool.package_revenue(&hashes[..1], base_fee),
+        200u64.into()
+    );
+}
+
+#[tokio::test]
+async fn test_package_with_base_fee() {
+    let mempool = default_mempool().await;
+    // (gas_price, max_priority_fee), the first one is a legacy tx
+    let fees = [(14u64, 14u64), (30, 2), (11, 5)];
+
+    let mut hashes = Vec::new();
+    for (gas_price, tip) in fees.iter() {
+        let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+        let pub_key = priv_key.pub_key();
+        let mut raw = mock_transaction(0, false);
+        raw.gas_price = (*gas_price).into();
+        raw.max_priority_fee_per_gas = (*tip).into();
+
+        let tx = sign_tx(&priv_key, &pub_key, raw, true);
+        hashes.push(tx.transaction.hash);
+        mempool.insert(Context::new(), tx).await.unwrap();
+    }
+
+    // Effective gas prices are 14, 12 and 11 at a base fee of 10
+    let package = mempool
+        .package_with_base_fee(
+            Context::new(),
+            CYCLE_LIMIT.into(),
+            TX_NUM_LIMIT,
+            Some(10u64.into()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(package, hashes);
+
+    let package = mempool
+        .package(Context::new(), CYCLE_LIMIT.into(), TX_NUM_LIMIT)
+        .await
+        .unwrap();
+    assert_eq!(package, vec![hashes[1], hashes[0], hashes[2]]);
+}
+
+#[tokio::test]
+async fn test_conflicts_with() {
+    let mempool = default_mempool().await;
+    let txs = mock_sender_txs(&[5, 5, 6]);
+
+    mempool
+        .insert(Context::new(), txs[0].clone())
+        .await
+        .unwrap();
+
+    assert_eq!(mempool.conflicts_with(&txs[1]), vec![
+        txs[0].transaction.hash
+    ]);
+    assert!(mempool.conflicts_with(&txs[2]).is_empty());
+    assert!(mempool.conflicts_with(&txs[0]).is_empty());
+}
+
+#[tokio::test]
+async fn test_verify_tx_strategies() {
+    for threshold in [0, usize::MAX] {
+        let config = MemPoolConfig::new(POOL_SIZE)
+            .serial_verify_threshold(threshold)
+            .verify_concurrency(Some(1));
+        let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+        let adapter = mempool.get_adapter();
+
+        assert!(mempool
+            .verify_tx_in_parallel(Context::new(), default_mock_txs(3))
+            .await
+            .is_ok());
+        assert_mempool_err(
+            mempool
+                .verify_tx_in_parallel(Context::new(), mock_txs(2, 1, TIMEOUT))
+                .await,
+            "VerifyBatchTransactions",
+        );
+
+        // A bad first tx fails the order without verifying the rest
+        adapter.auth_checks.store(0, Ordering::SeqCst);
+        let txs = [mock_txs(0, 1, TIMEOUT), default_mock_txs(5)].concat();
+        assert_mempool_err(
+            mempool.verify_tx_in_parallel(Context::new(), txs).await,
+            "VerifyBatchTransactions",
+        );
+        assert_eq!(adapter.auth_checks.load(Ordering::SeqCst), 1);
+    }
+}
+
+#[tokio::test]
+async fn test_verify_concurrency() {
+    let config = MemPoolConfig::new(POOL_SIZE)
+        .serial_verify_threshold(0)
+        .verify_concurrency(Some(3));
+    let adapter = HashMemPoolAdapter::verify_delay(Duration::from_millis(20));
+    let mempool = MemPoolImpl::with_config(config, adapter, vec![]).await;
+
+    assert!(mempool
+        .verify_tx_in_parallel(Context::new(), default_mock_txs(12))
+        .await
+        .is_ok());
+    assert_eq!(
+        mempool.get_adapter().peak_verifying.load(Ordering::SeqCst),
+        3
+    );
+
+    // The results keep the order of the txs
+    let res = mempool
+        .verify_batch(Context::new(), mock_txs(2, 2, TIMEOUT))
+        .await;
+    assert_eq!(res.iter().map(Result::is_ok).collect::<Vec<_>>(), vec![
+        true, true, false, false
+    ]);
+}
+
+#[tokio::test]
+async fn test_verify_txs_fail_fast() {
+    let txs = [mock_txs(0, 1, TIMEOUT), default_mock_txs(9)].concat();
+
+    // Serially and in parallel
+    for threshold in [usize::MAX, 0] {
+        let config = MemPoolConfig::new(POOL_SIZE)
+            .serial_verify_threshold(threshold)
+            .verify_concurrency(Some(1));
+        let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+        let adapter = mempool.get_adapter();
+
+        assert!(mempool
+            .verify_txs(Context::new(), &txs, true)
+            .await
+            .is_err());
+        assert_eq!(adapter.auth_checks.load(Ordering::SeqCst), 1);
+
+        let res = mempool
+            .verify_txs(Context::new(), &txs, false)
+            .await
+            .unwrap();
+        assert_eq!(res.iter().filter(|r| r.is_err()).count(), 1);
+        assert_eq!(adapter.auth_checks.load(Ordering::SeqCst), 11);
+    }
 }
 
 macro_rules! package {
@@ -186,6 +2118,29 @@ async fn test_flush_with_concurrent_insert() {
     }
 }
 
+// Checking the retained txs used to block in place, which panics on the
+// current thread runtime
+#[tokio::test]
+async fn test_flush_current_thread() {
+    let mempool = default_mempool().await;
+    let txs = default_mock_txs(10);
+    for tx in txs.iter() {
+        mempool.insert(Context::new(), tx.clone()).await.unwrap();
+    }
+
+    let (remove_txs, retain_txs) = txs.split_at(4);
+    let remove_hashes = remove_txs
+        .iter()
+        .map(|tx| tx.transaction.hash)
+        .collect::<Vec<_>>();
+    mempool.flush(Context::new(), &remove_hashes).await.unwrap();
+
+    assert_eq!(mempool.len(), retain_txs.len());
+    for tx in retain_txs.iter() {
+        assert!(mempool.get_tx_cache().contains(&tx.transaction.hash));
+    }
+}
+
 macro_rules! ensure_order_txs {
     ($in_pool: expr, $out_pool: expr) => {
         let mempool = &Arc::new(default_mempool().await);
@@ -213,6 +2168,66 @@ async fn test_ensure_order_txs() {
     ensure_order_txs!(0, 100);
 }
 
+#[tokio::test]
+async fn test_ensure_short_pull() {
+    let mempool = default_mempool().await;
+    let txs = default_mock_txs(4);
+    // The peer only has half of the txs
+    for tx in txs[..2].iter() {
+        mempool
+            .get_adapter()
+            .network_txs
+            .insert(tx.transaction.hash, tx.clone());
+    }
+    let hashes = txs.iter().map(|tx| tx.transaction.hash).collect::<Vec<_>>();
+
+    assert_mempool_err(
+        mempool
+            .ensure_order_txs(Context::new(), None, &hashes)
+            .await,
+        "EnsureBreak",
+    );
+    assert_eq!(mempool.get_adapter().bad_reports.load(Ordering::SeqCst), 1);
+    assert!(mempool.is_empty());
+
+    mempool
+        .ensure_order_txs(Context::new(), None, &hashes[..2])
+        .await
+        .unwrap();
+    assert_eq!(mempool.get_adapter().bad_reports.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_max_concurrent_ensure() {
+    let config = MemPoolConfig::new(POOL_SIZE).max_concurrent_ensure(Some(2));
+    let adapter = HashMemPoolAdapter::pull_delay(Duration::from_millis(50));
+    let mempool = MemPoolImpl::with_config(config, adapter, vec![]).await;
+
+    let txs = default_mock_txs(6);
+    for tx in txs.iter() {
+        mempool
+            .get_adapter()
+            .network_txs
+            .insert(tx.transaction.hash, tx.clone());
+    }
+
+    // Every call pulls its own unknown tx
+    let hashes = txs
+        .iter()
+        .map(|tx| vec![tx.transaction.hash])
+        .collect::<Vec<_>>();
+    let res = futures::future::join_all(
+        hashes
+            .iter()
+            .map(|h| mempool.ensure_order_txs(Context::new(), None, h)),
+    )
+    .await;
+
+    assert!(res.into_iter().all(|r| r.is_ok()));
+    assert_eq!(mempool.get_adapter().peak_pulling.load(Ordering::SeqCst), 2);
+    assert_eq!(mempool.len(), 6);
+}
+
 #[rustfmt::skip]
 /// Bench in Intel(R) Core(TM) i7-4770HQ CPU @ 2.20GHz (8 x 2200):
 /// test tests::mempool::bench_check_sig             ... bench:   2,881,140 ns/iter (+/- 907,215)
@@ -308,6 +2323,155 @@ fn bench_insert_serial_1000(b: &mut Bencher) {
     })
 }
 
+fn bench_verify(b: &mut Bencher, size: usize, serial_threshold: usize) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = MemPoolConfig::new(POOL_SIZE).serial_verify_threshold(serial_threshold);
+    let mempool = runtime.block_on(MemPoolImpl::with_config(
+        config,
+        HashMemPoolAdapter::new(),
+        vec![],
+    ));
+    let txs = default_mock_txs(size);
+
+    b.iter(|| {
+        runtime
+            .block_on(mempool.verify_tx_in_parallel(Context::new(), txs.clone()))
+            .unwrap();
+    });
+}
+
+#[bench]
+fn bench_verify_spawn_1(b: &mut Bencher) {
+    bench_verify(b, 1, 0);
+}
+
+#[bench]
+fn bench_verify_serial_1(b: &mut Bencher) {
+    bench_verify(b, 1, usize::MAX);
+}
+
+#[bench]
+fn bench_verify_spawn_2(b: &mut Bencher) {
+    bench_verify(b, 2, 0);
+}
+
+#[bench]
+fn bench_verify_serial_2(b: &mut Bencher) {
+    bench_verify(b, 2, usize::MAX);
+}
+
+#[bench]
+fn bench_verify_spawn_10(b: &mut Bencher) {
+    bench_verify(b, 10, 0);
+}
+
+#[bench]
+fn bench_verify_serial_10(b: &mut Bencher) {
+    bench_verify(b, 10, usize::MAX);
+}
+
+#[bench]
+fn bench_verify_spawn_100(b: &mut Bencher) {
+    bench_verify(b, 100, 0);
+}
+
+#[bench]
+fn bench_verify_serial_100(b: &mut Bencher) {
+    bench_verify(b, 100, usize::MAX);
+}
+
+fn bench_verify_30000(b: &mut Bencher, concurrency: Option<usize>) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = MemPoolConfig::new(POOL_SIZE)
+        .serial_verify_threshold(0)
+        .verify_concurrency(concurrency);
+    let mempool = runtime.block_on(MemPoolImpl::with_config(
+        config,
+        HashMemPoolAdapter::new(),
+        vec![],
+    ));
+    let txs = default_mock_txs(30000);
+
+    b.iter(|| {
+        runtime
+            .block_on(mempool.verify_tx_in_parallel(Context::new(), txs.clone()))
+            .unwrap();
+    });
+}
+
+#[bench]
+fn bench_verify_30000_unbounded(b: &mut Bencher) {
+    bench_verify_30000(b, None);
+}
+
+#[bench]
+fn bench_verify_30000_bounded(b: &mut Bencher) {
+    bench_verify_30000(b, Some(64));
+}
+
+fn bench_insert_many(b: &mut Bencher, batch: bool) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let txs = default_mock_txs(100);
+
+    b.iter(|| {
+        runtime.block_on(async {
+            let mempool = default_mempool().await;
+            if batch {
+                mempool
+                    .insert_batch(Context::new(), txs.clone())
+                    .await
+                    .unwrap();
+            } else {
+                for tx in txs.iter() {
+                    mempool.insert(Context::new(), tx.clone()).await.unwrap();
+                }
+            }
+        })
+    });
+}
+
+#[bench]
+fn bench_insert_loop_100(b: &mut Bencher) {
+    bench_insert_many(b, false);
+}
+
+#[bench]
+fn bench_insert_batch_100(b: &mut Bencher) {
+    bench_insert_many(b, true);
+}
+
+fn bench_insert_large(b: &mut Bencher, policy: SizeTracking) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let txs = (0..100)
+        .map(|_| {
+            let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+            let mut raw = mock_transaction(0, false);
+            raw.data = random_bytes(64 * 1024);
+            sign_tx(&priv_key, &priv_key.pub_key(), raw, true)
+        })
+        .collect::<Vec<_>>();
+
+    b.iter(|| {
+        let config = MemPoolConfig::new(POOL_SIZE).size_tracking(policy);
+        runtime.block_on(async {
+            let mempool = MemPoolImpl::with_config(config, HashMemPoolAdapter::new(), vec![]).await;
+            for tx in txs.iter() {
+                mempool.insert(Context::new(), tx.clone()).await.unwrap();
+            }
+        })
+    });
+}
+
+#[bench]
+fn bench_insert_large_eager_size(b: &mut Bencher) {
+    bench_insert_large(b, SizeTracking::Eager);
+}
+
+#[bench]
+fn bench_insert_large_lazy_size(b: &mut Bencher) {
+    bench_insert_large(b, SizeTracking::Lazy);
+}
+
 #[bench]
 fn bench_package(b: &mut Bencher) {
     let runtime = tokio::runtime::Runtime::new().unwrap();
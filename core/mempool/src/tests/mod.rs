@@ -2,7 +2,9 @@ extern crate test;
 
 mod mempool;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use dashmap::DashMap;
 use rand::random;
@@ -17,11 +19,15 @@ use protocol::codec::ProtocolCodec;
 use protocol::traits::{Context, MemPool, MemPoolAdapter};
 use protocol::types::{
     public_to_address, recover_intact_pub_key, Bytes, Hash, Hasher, Public, SignedTransaction,
-    Transaction, TransactionAction, UnverifiedTransaction, H256, U256,
+    Transaction, TransactionAction, UnverifiedTransaction, H160, H256, U256,
 };
 use protocol::{async_trait, tokio, ProtocolResult};
 
-use crate::{adapter::AdapterError, check_dup_order_hashes, MemPoolError, MemPoolImpl};
+use crate::{
+    adapter::AdapterError, check_dup_order_hashes, is_stale_nonce, AuthFailure, EvictReason,
+    InsertOrGet, MemPoolConfig, MemPoolError, MemPoolImpl, PropagationMode, RejectionRecord,
+    SizeTracking, SubscriptionLag, UnpreparedPackage,
+};
 
 const CYCLE_LIMIT: u64 = 1_000_000;
 const TX_NUM_LIMIT: u64 = 10_000;
@@ -32,13 +38,75 @@ const TIMEOUT: u64 = 1000;
 const TIMEOUT_GAP: u64 = 100;
 
 pub struct HashMemPoolAdapter {
-    network_txs: DashMap<Hash, SignedTransaction>,
+    network_txs:    DashMap<Hash, SignedTransaction>,
+    broadcast_fail: bool,
+
+    pull_delay:   Option<Duration>,
+    pulling:      AtomicUsize,
+    peak_pulling: AtomicUsize,
+
+    verify_delay:   Option<Duration>,
+    verifying:      AtomicUsize,
+    peak_verifying: AtomicUsize,
+
+    storage_txs:   DashMap<Hash, SignedTransaction>,
+    storage_reads: AtomicUsize,
+    auth_checks:   AtomicUsize,
+    auth_failure:  Option<AuthFailure>,
+    bad_reports:   AtomicUsize,
+
+    account_nonces: DashMap<H160, U256>,
 }
 
 impl HashMemPoolAdapter {
     fn new() -> HashMemPoolAdapter {
         HashMemPoolAdapter {
-            network_txs: DashMap::new(),
+            network_txs:    DashMap::new(),
+            broadcast_fail: false,
+
+            pull_delay:   None,
+            pulling:      AtomicUsize::new(0),
+            peak_pulling: AtomicUsize::new(0),
+
+            verify_delay:   None,
+            verifying:      AtomicUsize::new(0),
+            peak_verifying: AtomicUsize::new(0),
+
+            storage_txs:   DashMap::new(),
+            storage_reads: AtomicUsize::new(0),
+            auth_checks:   AtomicUsize::new(0),
+            auth_failure:  None,
+            bad_reports:   AtomicUsize::new(0),
+
+            account_nonces: DashMap::new(),
+        }
+    }
+
+    fn verify_delay(delay: Duration) -> HashMemPoolAdapter {
+        HashMemPoolAdapter {
+            verify_delay: Some(delay),
+            ..Self::new()
+        }
+    }
+
+    fn auth_failure(failure: AuthFailure) -> HashMemPoolAdapter {
+        HashMemPoolAdapter {
+            auth_failure: Some(failure),
+            ..Self::new()
+        }
+    }
+
+    fn broadcast_fail() -> HashMemPoolAdapter {
+        HashMemPoolAdapter {
+            broadcast_fail: true,
+            ..Self::new()
+        }
+    }
+
+    fn pull_delay(delay: Duration) -> HashMemPoolAdapter {
+        HashMemPoolAdapter {
+            pull_delay: Some(delay),
+            ..Self::new()
         }
     }
 }
@@ -51,6 +119,13 @@ impl MemPoolAdapter for HashMemPoolAdapter {
         _height: Option<u64>,
         tx_hashes: Vec<Hash>,
     ) -> ProtocolResult<Vec<SignedTransaction>> {
+        if let Some(delay) = self.pull_delay {
+            let pulling = self.pulling.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak_pulling.fetch_max(pulling, Ordering::SeqCst);
+            tokio::time::sleep(delay).await;
+            self.pulling.fetch_sub(1, Ordering::SeqCst);
+        }
+
         let mut vec = Vec::with_capacity(tx_hashes.len());
         for hash in tx_hashes {
             if let Some(tx) = self.network_txs.get(&hash) {
@@ -61,6 +136,10 @@ impl MemPoolAdapter for HashMemPoolAdapter {
     }
 
     async fn broadcast_tx(&self, _ctx: Context, tx: SignedTransaction) -> ProtocolResult<()> {
+        if self.broadcast_fail {
+            return Err(AdapterError::Internal.into());
+        }
+
         self.network_txs.insert(tx.transaction.hash, tx);
         Ok(())
     }
@@ -68,12 +147,35 @@ impl MemPoolAdapter for HashMemPoolAdapter {
     async fn check_authorization(
         &self,
         _ctx: Context,
-        _tx: &SignedTransaction,
+        tx: &SignedTransaction,
     ) -> ProtocolResult<()> {
+        self.auth_checks.fetch_add(1, Ordering::SeqCst);
+        if let Some(failure) = self.auth_failure.clone() {
+            return Err(MemPoolError::Authorization(failure, tx.transaction.hash).into());
+        }
+
+        // Only the accounts with a mocked nonce are checked
+        if let Some(current) = self.account_nonces.get(&tx.sender).map(|nonce| *nonce) {
+            if is_stale_nonce(current, tx.transaction.unsigned.nonce) {
+                let failure = AuthFailure::StaleNonce {
+                    current,
+                    tx: tx.transaction.unsigned.nonce,
+                };
+                return Err(MemPoolError::Authorization(failure, tx.transaction.hash).into());
+            }
+        }
+
         Ok(())
     }
 
     async fn check_transaction(&self, _ctx: Context, tx: &SignedTransaction) -> ProtocolResult<()> {
+        if let Some(delay) = self.verify_delay {
+            let verifying = self.verifying.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak_verifying.fetch_max(verifying, Ordering::SeqCst);
+            tokio::time::sleep(delay).await;
+            self.verifying.fetch_sub(1, Ordering::SeqCst);
+        }
+
         check_hash(tx)?;
         check_sig(tx)
     }
@@ -86,24 +188,48 @@ impl MemPoolAdapter for HashMemPoolAdapter {
         Ok(CURRENT_HEIGHT)
     }
 
+    async fn get_account_nonce(&self, _ctx: Context, address: H160) -> ProtocolResult<U256> {
+        Ok(self
+            .account_nonces
+            .get(&address)
+            .map(|nonce| *nonce)
+            .unwrap_or_default())
+    }
+
     async fn get_transactions_from_storage(
         &self,
         _ctx: Context,
         _height: Option<u64>,
-        _tx_hashes: &[Hash],
+        tx_hashes: &[Hash],
     ) -> ProtocolResult<Vec<Option<SignedTransaction>>> {
-        Ok(vec![])
+        self.storage_reads.fetch_add(1, Ordering::SeqCst);
+        Ok(tx_hashes
+            .iter()
+            .map(|hash| self.storage_txs.get(hash).map(|tx| tx.clone()))
+            .collect())
     }
 
     fn set_args(&self, _context: Context, _state_root: H256, _gas_limit: u64, _max_tx_size: u64) {}
 
     fn report_good(&self, _ctx: Context) {}
+
+    fn report_bad(&self, _ctx: Context) {
+        self.bad_reports.fetch_add(1, Ordering::SeqCst);
+    }
 }
 
 pub fn default_mock_txs(size: usize) -> Vec<SignedTransaction> {
     mock_txs(size, 0, TIMEOUT)
 }
 
+pub fn mock_call_tx(to: H160) -> SignedTransaction {
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    let mut raw = mock_transaction(0, false);
+    raw.action = TransactionAction::Call(to);
+    sign_tx(&priv_key, &pub_key, raw, true)
+}
+
 pub fn mock_sys_txs(size: usize) -> Vec<SignedTransaction> {
     (0..size)
         .map(|i| {
@@ -124,6 +250,20 @@ fn mock_txs(valid_size: usize, invalid_size: usize, timeout: u64) -> Vec<SignedT
         .collect()
 }
 
+fn mock_sender_txs(nonces: &[u64]) -> Vec<SignedTransaction> {
+    let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+    let pub_key = priv_key.pub_key();
+    nonces
+        .iter()
+        .map(|nonce| mock_signed_tx(&priv_key, &pub_key, TIMEOUT, *nonce, true))
+        .collect()
+}
+
+fn assert_mempool_err<T: std::fmt::Debug>(res: ProtocolResult<T>, variant: &str) {
+    let err = res.unwrap_err().to_string();
+    assert!(err.contains(variant), "expect {}, got {}", variant, err);
+}
+
 fn default_mempool_sync() -> MemPoolImpl<HashMemPoolAdapter> {
     let rt = tokio::runtime::Runtime::new().unwrap();
     rt.block_on(default_mempool())
@@ -282,31 +422,7 @@ fn mock_signed_tx(
     nonce: u64,
     valid: bool,
 ) -> SignedTransaction {
-    let raw = mock_transaction(nonce, false);
-    let mut tx = UnverifiedTransaction {
-        unsigned:  raw,
-        signature: None,
-        chain_id:  random::<u64>(),
-        hash:      Default::default(),
-    };
-
-    let signature = if valid {
-        Secp256k1Recoverable::sign_message(tx.signature_hash().as_bytes(), &priv_key.to_bytes())
-            .unwrap()
-            .to_bytes()
-    } else {
-        Bytes::copy_from_slice([0u8; 65].as_ref())
-    };
-
-    tx.signature = Some(signature.into());
-
-    let pub_key = Public::from_slice(&pub_key.to_uncompressed_bytes()[1..65]);
-
-    SignedTransaction {
-        transaction: tx.hash(),
-        sender:      public_to_address(&pub_key),
-        public:      Some(pub_key),
-    }
+    sign_tx(priv_key, pub_key, mock_transaction(nonce, false), valid)
 }
 
 fn mock_system_script_signed_tx(
@@ -316,7 +432,28 @@ fn mock_system_script_signed_tx(
     nonce: u64,
     valid: bool,
 ) -> SignedTransaction {
-    let raw = mock_transaction(nonce, true);
+    sign_tx(priv_key, pub_key, mock_transaction(nonce, true), valid)
+}
+
+fn mock_gas_price_txs(gas_prices: &[u64]) -> Vec<SignedTransaction> {
+    gas_prices
+        .iter()
+        .map(|gas_price| {
+            let priv_key = Secp256k1RecoverablePrivateKey::generate(&mut OsRng);
+            let pub_key = priv_key.pub_key();
+            let mut raw = mock_transaction(0, false);
+            raw.gas_price = (*gas_price).into();
+            sign_tx(&priv_key, &pub_key, raw, true)
+        })
+        .collect()
+}
+
+fn sign_tx(
+    priv_key: &Secp256k1RecoverablePrivateKey,
+    pub_key: &Secp256k1RecoverablePublicKey,
+    raw: Transaction,
+    valid: bool,
+) -> SignedTransaction {
     let mut tx = UnverifiedTransaction {
         unsigned:  raw,
         signature: None,
@@ -1,6 +1,7 @@
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use protocol::types::{Hash, SignedTransaction, H160, U256};
 
@@ -11,11 +12,18 @@ pub struct TxWrapper(TxPtr, SignedTransaction);
 
 impl From<SignedTransaction> for TxWrapper {
     fn from(stx: SignedTransaction) -> Self {
-        TxWrapper(get_tx_ptr(&stx), stx)
+        TxWrapper::new(stx, Instant::now(), false)
     }
 }
 
 impl TxWrapper {
+    pub fn new(stx: SignedTransaction, inserted_at: Instant, local: bool) -> Self {
+        let mut digest = TxDigest::from(&stx);
+        digest.inserted_at = inserted_at;
+        digest.local = local;
+        TxWrapper(Arc::new(digest), stx)
+    }
+
     pub fn ptr(&self) -> TxPtr {
         Arc::clone(&self.0)
     }
@@ -31,12 +39,17 @@ impl TxWrapper {
 
 #[derive(Debug)]
 pub struct TxDigest {
-    pub hash:      Hash,
-    pub gas_price: U256,
-    pub nonce:     U256,
-    pub sender:    H160,
-
-    pub is_dropped: AtomicBool,
+    pub hash:         Hash,
+    // The max fee per gas
+    pub gas_price:    U256,
+    pub priority_fee: U256,
+    pub nonce:        U256,
+    pub sender:       H160,
+    // Submitted to this node rather than received from the network
+    pub local:        bool,
+
+    pub inserted_at: Instant,
+    pub is_dropped:  AtomicBool,
 }
 
 impl PartialEq for TxDigest {
@@ -55,26 +68,21 @@ impl PartialOrd for TxDigest {
 
 impl Ord for TxDigest {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.gas_price != other.gas_price {
-            return self.gas_price.cmp(&other.gas_price);
-        }
-
-        match self.nonce.cmp(&other.nonce) {
-            Ordering::Greater => Ordering::Less,
-            Ordering::Less => Ordering::Greater,
-            Ordering::Equal => Ordering::Equal,
-        }
+        self.cmp_at(other, None)
     }
 }
 
 impl From<&SignedTransaction> for TxDigest {
     fn from(stx: &SignedTransaction) -> Self {
         TxDigest {
-            hash:       stx.transaction.hash,
-            gas_price:  stx.transaction.unsigned.gas_price,
-            nonce:      stx.transaction.unsigned.nonce,
-            sender:     stx.sender,
-            is_dropped: AtomicBool::new(false),
+            hash:         stx.transaction.hash,
+            gas_price:    stx.transaction.unsigned.gas_price,
+            priority_fee: stx.transaction.unsigned.max_priority_fee_per_gas,
+            nonce:        stx.transaction.unsigned.nonce,
+            sender:       stx.sender,
+            local:        false,
+            inserted_at:  Instant::now(),
+            is_dropped:   AtomicBool::new(false),
         }
     }
 }
@@ -91,10 +99,39 @@ impl TxDigest {
     pub fn set_dropped(&self) {
         self.is_dropped.swap(true, AtomicOrdering::Acquire);
     }
-}
 
-fn get_tx_ptr(stx: &SignedTransaction) -> TxPtr {
-    Arc::new(stx.into())
+    /// The price per gas the tx pays at `base_fee`. A legacy tx carries its
+    /// gas price in both fee fields, which makes it pay its gas price.
+    pub fn effective_gas_price(&self, base_fee: U256) -> U256 {
+        self.gas_price
+            .min(base_fee.saturating_add(self.priority_fee))
+    }
+
+    /// The order of `Ord`, with the txs priced at `base_fee` if any instead
+    /// of at their max fee.
+    pub fn cmp_at(&self, other: &Self, base_fee: Option<U256>) -> Ordering {
+        if self.local != other.local {
+            return self.local.cmp(&other.local);
+        }
+
+        let (price, other_price) = match base_fee {
+            Some(base_fee) => (
+                self.effective_gas_price(base_fee),
+                other.effective_gas_price(base_fee),
+            ),
+            None => (self.gas_price, other.gas_price),
+        };
+        if price != other_price {
+            return price.cmp(&other_price);
+        }
+
+        if self.nonce != other.nonce {
+            return other.nonce.cmp(&self.nonce);
+        }
+
+        // The lower hash goes first so that the order is total
+        other.hash.cmp(&self.hash)
+    }
 }
 
 #[cfg(test)]
@@ -110,11 +147,27 @@ mod tests {
     }
 
     fn mock_tx_digest(gas_price: u64, nonce: u64) -> Arc<TxDigest> {
+        mock_origin_tx_digest(gas_price, nonce, false)
+    }
+
+    fn mock_origin_tx_digest(gas_price: u64, nonce: u64, local: bool) -> Arc<TxDigest> {
+        mock_fee_tx_digest(gas_price, gas_price, nonce, local)
+    }
+
+    fn mock_fee_tx_digest(
+        gas_price: u64,
+        priority_fee: u64,
+        nonce: u64,
+        local: bool,
+    ) -> Arc<TxDigest> {
         Arc::new(TxDigest {
-            hash:       rand_hash(),
-            gas_price:  gas_price.into(),
-            nonce:      nonce.into(),
-            sender:     H160::default(),
+            hash: rand_hash(),
+            gas_price: gas_price.into(),
+            priority_fee: priority_fee.into(),
+            nonce: nonce.into(),
+            sender: H160::default(),
+            local,
+            inserted_at: Instant::now(),
             is_dropped: AtomicBool::new(false),
         })
     }
@@ -137,4 +190,36 @@ mod tests {
         assert_eq!(heap.pop().unwrap(), tx_3);
         assert_eq!(heap.pop().unwrap(), tx_1);
     }
+
+    #[test]
+    fn test_tx_digest_local_first() {
+        let local = mock_origin_tx_digest(1, 10, true);
+        let remote = mock_origin_tx_digest(3, 0, false);
+
+        let mut heap = BinaryHeap::new();
+        heap.push(Arc::clone(&remote));
+        heap.push(Arc::clone(&local));
+
+        assert_eq!(heap.pop().unwrap(), local);
+        assert_eq!(heap.pop().unwrap(), remote);
+    }
+
+    #[test]
+    fn test_tx_digest_effective_price() {
+        let legacy = mock_origin_tx_digest(14, 0, false);
+        let tip = mock_fee_tx_digest(30, 2, 0, false);
+        let capped = mock_fee_tx_digest(11, 5, 0, false);
+
+        assert_eq!(legacy.effective_gas_price(10.into()), 14.into());
+        assert_eq!(tip.effective_gas_price(10.into()), 12.into());
+        assert_eq!(capped.effective_gas_price(10.into()), 11.into());
+
+        let mut digests = vec![&capped, &tip, &legacy];
+        digests.sort_by(|a, b| b.cmp_at(a, Some(10.into())));
+        assert_eq!(digests, vec![&legacy, &tip, &capped]);
+
+        // Ranked by the max fee without a base fee
+        digests.sort_by(|a, b| b.cmp_at(a, None));
+        assert_eq!(digests, vec![&tip, &legacy, &capped]);
+    }
 }
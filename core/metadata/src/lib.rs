@@ -45,7 +45,7 @@ where
         block_number % (**EPOCH_LEN.load()) == 0
     }
 
-    fn update_metadata(&self, _ctx: Context, header: &Header) -> ProtocolResult<()> {
+    fn update_metadata(&self, _ctx: Context, header: &Header) -> ProtocolResult<Metadata> {
         let epoch = self.calc_epoch(header.number) + 1;
         let metadata = self.query_evm_metadata(epoch, header)?;
         let boundary = epoch.saturating_sub(20);
@@ -53,11 +53,11 @@ where
 
         let mut cache = self.metadata_cache.write();
         cache.retain(|&k, _| k < boundary);
-        cache.insert(epoch, metadata);
+        cache.insert(epoch, metadata.clone());
 
         common_apm::metrics::network::NETWORK_TAGGED_CONSENSUS_PEERS.set(consensus_count as i64);
 
-        Ok(())
+        Ok(metadata)
     }
 
     fn get_metadata(&self, _ctx: Context, header: &Header) -> ProtocolResult<Metadata> {
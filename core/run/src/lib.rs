@@ -27,15 +27,16 @@ use core_consensus::message::{
 };
 use core_consensus::status::{CurrentStatus, StatusAgent};
 use core_consensus::{
-    util::OverlordCrypto, ConsensusWal, DurationConfig, Node, OverlordConsensus,
+    util::OverlordCrypto, ConsensusWal, DurationConfig, ExecPanicPolicy, Node, OverlordConsensus,
     OverlordConsensusAdapter, OverlordSynchronization, SignedTxsWAL,
 };
 use core_cross_client::DefaultCrossAdapter;
 use core_executor::{AxonExecutor, AxonExecutorAdapter, MPTTrie, RocksTrieDB};
 use core_interoperation::InteroperationImpl;
 use core_mempool::{
-    DefaultMemPoolAdapter, MemPoolImpl, NewTxsHandler, PullTxsHandler, END_GOSSIP_NEW_TXS,
-    RPC_PULL_TXS, RPC_RESP_PULL_TXS, RPC_RESP_PULL_TXS_SYNC,
+    DefaultMemPoolAdapter, MemPoolConfig, MemPoolImpl, NewTxsHandler, PropagationMode,
+    PullTxsHandler, SizeTracking, UnpreparedPackage, END_GOSSIP_NEW_TXS, RPC_PULL_TXS,
+    RPC_RESP_PULL_TXS, RPC_RESP_PULL_TXS_SYNC,
 };
 use core_metadata::{MetadataAdapterImpl, MetadataController};
 use core_network::{
@@ -219,11 +220,14 @@ impl Axon {
 
         // Init trie db
         let path_state = config.data_path_for_state();
-        let trie_db = Arc::new(RocksTrieDB::new(
-            path_state,
-            config.rocksdb.max_open_files,
-            config.executor.triedb_cache_size,
-        )?);
+        let trie_db = Arc::new(
+            RocksTrieDB::new(
+                path_state,
+                config.rocksdb.max_open_files,
+                config.executor.triedb_cache_size,
+            )?
+            .sync_interval(config.executor.triedb_sync_interval),
+        );
 
         // Init full transactions wal
         let txs_wal_path = config.data_path_for_txs_wal().to_str().unwrap().to_string();
@@ -273,14 +277,43 @@ impl Axon {
             config.mempool.pool_size as usize,
             config.mempool.broadcast_txs_size,
             config.mempool.broadcast_txs_interval,
-        );
+        )
+        .verify_cache(config.mempool.verify_cache)
+        .recipient_check(config.mempool.recipient_check)
+        .storage_read_concurrency(config.mempool.storage_read_concurrency);
+        let mempool_config = MemPoolConfig::new(config.mempool.pool_size as usize)
+            .max_residence(config.mempool.max_residence.map(Duration::from_secs))
+            .strict_broadcast(config.mempool.strict_broadcast)
+            .verify_concurrency(config.mempool.verify_concurrency)
+            .max_concurrent_ensure(config.mempool.max_concurrent_ensure)
+            .tx_size_metrics(config.mempool.tx_size_metrics)
+            .eviction_archive_size(config.mempool.eviction_archive_size)
+            .rejection_log_size(config.mempool.rejection_log_size)
+            .storage_cache_size(config.mempool.storage_cache_size)
+            .max_missing_txs(config.mempool.max_missing_txs)
+            .min_gas_price(config.mempool.min_gas_price.into())
+            .pressure_threshold(config.mempool.pressure_threshold)
+            .zero_gas_system_script(config.mempool.zero_gas_system_script)
+            .max_txs_per_sender(config.mempool.max_txs_per_sender)
+            .max_system_script_txs(config.mempool.max_system_script_txs)
+            .chain_id(Some(self.genesis.block.header.chain_id))
+            .size_tracking(if config.mempool.lazy_size_tracking {
+                SizeTracking::Lazy
+            } else {
+                SizeTracking::Eager
+            })
+            .unprepared_package(if config.mempool.reject_unprepared {
+                UnpreparedPackage::Reject
+            } else {
+                UnpreparedPackage::Ungated
+            })
+            .propagation_mode(if config.mempool.eager_propagation {
+                PropagationMode::Eager
+            } else {
+                PropagationMode::Strict
+            });
         let mempool = Arc::new(
-            MemPoolImpl::new(
-                config.mempool.pool_size as usize,
-                mempool_adapter,
-                current_stxs.clone(),
-            )
-            .await,
+            MemPoolImpl::with_config(mempool_config, mempool_adapter, current_stxs.clone()).await,
         );
 
         let monitor_mempool = Arc::clone(&mempool);
@@ -292,6 +325,7 @@ impl Axon {
                 MEMPOOL_CO_QUEUE_LEN.set(monitor_mempool.len() as i64);
             }
         });
+        mempool.spawn_reaper(Duration::from_millis(1000));
 
         // self private key
         let hex_privkey = hex_decode(&config.privkey.as_string_trim0x())?;
@@ -433,7 +467,18 @@ impl Axon {
             Arc::new(cross_handle),
             Arc::clone(&metadata_controller),
             Arc::clone(&crypto),
-        )?;
+        )?
+        .save_txs_chunk_size(config.consensus.save_txs_chunk_size)
+        .sync_proof_concurrency(config.consensus.sync_proof_concurrency)
+        .live_proof_concurrency(config.consensus.live_proof_concurrency)
+        .tx_read_concurrency(config.consensus.tx_read_concurrency)
+        .rpc_timeout(config.consensus.rpc_timeout.map(Duration::from_secs))
+        .vote_threshold(config.consensus.vote_threshold)?
+        .exec_panic_policy(if config.consensus.raise_exec_panic {
+            ExecPanicPolicy::Raise
+        } else {
+            ExecPanicPolicy::Catch
+        });
 
         let consensus_adapter = Arc::new(consensus_adapter);
 
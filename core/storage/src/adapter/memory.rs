@@ -1,6 +1,7 @@
-use std::collections::{hash_map, HashMap};
+use std::collections::{btree_map, BTreeMap, HashMap};
 use std::error::Error;
 use std::marker::PhantomData;
+use std::ops::Bound;
 use std::sync::Arc;
 
 use parking_lot::RwLock;
@@ -12,7 +13,49 @@ use protocol::traits::{
 use protocol::types::Bytes;
 use protocol::{async_trait, Display, From, ProtocolError, ProtocolErrorKind, ProtocolResult};
 
-type Category = HashMap<Vec<u8>, Vec<u8>>;
+// Sorted by the encoded key, which `prepare_range_iter` relies on
+pub type Category = BTreeMap<Vec<u8>, Vec<u8>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IterDirection {
+    Forward,
+    Reverse,
+}
+
+/// A write to the category of a schema, the key and value are encoded
+/// already. Build it with `CategoryOp::insert` or `CategoryOp::remove`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CategoryOp {
+    Insert {
+        category: String,
+        key:      Vec<u8>,
+        value:    Vec<u8>,
+    },
+    Remove {
+        category: String,
+        key:      Vec<u8>,
+    },
+}
+
+impl CategoryOp {
+    pub fn insert<S: StorageSchema>(
+        key: <S as StorageSchema>::Key,
+        val: <S as StorageSchema>::Value,
+    ) -> ProtocolResult<Self> {
+        Ok(CategoryOp::Insert {
+            category: S::category().to_string(),
+            key:      key.encode()?.to_vec(),
+            value:    val.encode()?.to_vec(),
+        })
+    }
+
+    pub fn remove<S: StorageSchema>(key: <S as StorageSchema>::Key) -> ProtocolResult<Self> {
+        Ok(CategoryOp::Remove {
+            category: S::category().to_string(),
+            key:      key.encode()?.to_vec(),
+        })
+    }
+}
 
 #[derive(Debug)]
 pub struct MemoryAdapter {
@@ -25,6 +68,97 @@ impl MemoryAdapter {
             db: Arc::new(RwLock::new(HashMap::new())),
         }
     }
+
+    /// Rebuild an adapter from the categories taken by `export`.
+    pub fn from_snapshot(snapshot: HashMap<String, Category>) -> Self {
+        MemoryAdapter {
+            db: Arc::new(RwLock::new(snapshot)),
+        }
+    }
+
+    /// A copy of all the categories, which the later writes don't change.
+    pub fn export(&self) -> HashMap<String, Category> {
+        self.db.read().clone()
+    }
+
+    /// Apply the ops in order under a single write lock, so a reader sees
+    /// either none or all of them whatever categories they span.
+    pub fn batch_modify_multi(&self, ops: Vec<CategoryOp>) {
+        let mut db = self.db.write();
+
+        for op in ops.into_iter() {
+            match op {
+                CategoryOp::Insert {
+                    category,
+                    key,
+                    value,
+                } => {
+                    db.entry(category)
+                        .or_insert_with(Category::new)
+                        .insert(key, value);
+                }
+                CategoryOp::Remove { category, key } => {
+                    db.entry(category)
+                        .or_insert_with(Category::new)
+                        .remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Iterate the category in key order, walking from `from` to `to`, both
+    /// inclusive, in the given direction. A missing bound runs to the end of
+    /// the category.
+    pub fn prepare_range_iter<'a, 'b: 'a, S: StorageSchema + 'static>(
+        &'b self,
+        from: Option<<S as StorageSchema>::Key>,
+        to: Option<<S as StorageSchema>::Key>,
+        direction: IterDirection,
+    ) -> ProtocolResult<Box<dyn IntoIteratorByRef<S> + 'a>> {
+        let bound = |key: Option<<S as StorageSchema>::Key>| -> ProtocolResult<_> {
+            Ok(match key {
+                Some(key) => Bound::Included(key.encode()?.to_vec()),
+                None => Bound::Unbounded,
+            })
+        };
+        let (from, to) = (bound(from)?, bound(to)?);
+        let (lower, upper) = match direction {
+            IterDirection::Forward => (from, to),
+            IterDirection::Reverse => (to, from),
+        };
+
+        {
+            self.db
+                .write()
+                .entry(S::category().to_string())
+                .or_insert_with(Category::new);
+        }
+
+        Ok(Box::new(MemoryRangeIntoIterator {
+            inner: self.db.read(),
+            lower,
+            upper,
+            direction,
+            pin_s: PhantomData::<S>,
+        }))
+    }
+
+    /// Iterate a copy of the category in key order. The lock is only held
+    /// while copying, so writers don't wait for the iteration and it sees
+    /// none of their writes. The copy costs as much memory as the category.
+    pub fn snapshot_iter<S: StorageSchema>(&self) -> MemorySnapshotIterator<S> {
+        let category = self
+            .db
+            .read()
+            .get(&S::category().to_string())
+            .cloned()
+            .unwrap_or_default();
+
+        MemorySnapshotIterator {
+            inner: category.into_iter(),
+            pin_s: PhantomData::<S>,
+        }
+    }
 }
 
 impl Default for MemoryAdapter {
@@ -35,8 +169,10 @@ impl Default for MemoryAdapter {
     }
 }
 
+type RawIter<'a> = Box<dyn Iterator<Item = (&'a Vec<u8>, &'a Vec<u8>)> + 'a>;
+
 pub struct MemoryIterator<'a, S: StorageSchema> {
-    inner: hash_map::Iter<'a, Vec<u8>, Vec<u8>>,
+    inner: RawIter<'a>,
     pin_s: PhantomData<S>,
 }
 
@@ -57,6 +193,26 @@ impl<'a, S: StorageSchema> Iterator for MemoryIterator<'a, S> {
     }
 }
 
+pub struct MemorySnapshotIterator<S: StorageSchema> {
+    inner: btree_map::IntoIter<Vec<u8>, Vec<u8>>,
+    pin_s: PhantomData<S>,
+}
+
+impl<S: StorageSchema> Iterator for MemorySnapshotIterator<S> {
+    type Item = ProtocolResult<(<S as StorageSchema>::Key, <S as StorageSchema>::Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kv_decode = |(k_bytes, v_bytes): (Vec<u8>, Vec<u8>)| -> ProtocolResult<_> {
+            let key = <_>::decode(Bytes::from(k_bytes))?;
+            let val = <_>::decode(Bytes::from(v_bytes))?;
+
+            Ok((key, val))
+        };
+
+        self.inner.next().map(kv_decode)
+    }
+}
+
 pub struct MemoryIntoIterator<'a, S: StorageSchema> {
     inner: parking_lot::RwLockReadGuard<'a, HashMap<String, Category>>,
     pin_s: PhantomData<S>,
@@ -68,11 +224,12 @@ impl<'a, 'b: 'a, S: StorageSchema> IntoIterator for &'b MemoryIntoIterator<'a, S
 
     fn into_iter(self) -> Self::IntoIter {
         Box::new(MemoryIterator {
-            inner: self
-                .inner
-                .get(&S::category().to_string())
-                .expect("impossible, already ensure we have category in prepare_iter")
-                .iter(),
+            inner: Box::new(
+                self.inner
+                    .get(&S::category().to_string())
+                    .expect("impossible, already ensure we have category in prepare_iter")
+                    .iter(),
+            ),
             pin_s: PhantomData::<S>,
         })
     }
@@ -84,6 +241,51 @@ impl<'c, S: StorageSchema> IntoIteratorByRef<S> for MemoryIntoIterator<'c, S> {
     }
 }
 
+pub struct MemoryRangeIntoIterator<'a, S: StorageSchema> {
+    inner:     parking_lot::RwLockReadGuard<'a, HashMap<String, Category>>,
+    lower:     Bound<Vec<u8>>,
+    upper:     Bound<Vec<u8>>,
+    direction: IterDirection,
+    pin_s:     PhantomData<S>,
+}
+
+impl<'a, 'b: 'a, S: StorageSchema> IntoIterator for &'b MemoryRangeIntoIterator<'a, S> {
+    type IntoIter = StorageIterator<'a, S>;
+    type Item = ProtocolResult<(<S as StorageSchema>::Key, <S as StorageSchema>::Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let category = self
+            .inner
+            .get(&S::category().to_string())
+            .expect("impossible, already ensure we have category in prepare_range_iter");
+
+        // `BTreeMap::range` panics on a reversed range
+        let inner: RawIter<'a> = match (&self.lower, &self.upper) {
+            (Bound::Included(lower), Bound::Included(upper)) if lower > upper => {
+                Box::new(std::iter::empty())
+            }
+            _ => {
+                let range = category.range((self.lower.clone(), self.upper.clone()));
+                match self.direction {
+                    IterDirection::Forward => Box::new(range),
+                    IterDirection::Reverse => Box::new(range.rev()),
+                }
+            }
+        };
+
+        Box::new(MemoryIterator {
+            inner,
+            pin_s: PhantomData::<S>,
+        })
+    }
+}
+
+impl<'c, S: StorageSchema> IntoIteratorByRef<S> for MemoryRangeIntoIterator<'c, S> {
+    fn ref_to_iter<'a, 'b: 'a>(&'b self) -> StorageIterator<'a, S> {
+        self.into_iter()
+    }
+}
+
 #[async_trait]
 impl StorageAdapter for MemoryAdapter {
     async fn insert<S: StorageSchema>(
@@ -97,7 +299,7 @@ impl StorageAdapter for MemoryAdapter {
         let mut db = self.db.write();
         let db = db
             .entry(S::category().to_string())
-            .or_insert_with(HashMap::new);
+            .or_insert_with(Category::new);
 
         db.insert(key, val);
 
@@ -113,7 +315,7 @@ impl StorageAdapter for MemoryAdapter {
         let mut db = self.db.write();
         let db = db
             .entry(S::category().to_string())
-            .or_insert_with(HashMap::new);
+            .or_insert_with(Category::new);
 
         let opt_bytes = db.get(&key.to_vec()).cloned();
 
@@ -132,13 +334,33 @@ impl StorageAdapter for MemoryAdapter {
         let mut db = self.db.write();
         let db = db
             .entry(S::category().to_string())
-            .or_insert_with(HashMap::new);
+            .or_insert_with(Category::new);
 
         db.remove(&key);
 
         Ok(())
     }
 
+    async fn remove_prefix<S: StorageSchema>(&self, prefix: Vec<u8>) -> ProtocolResult<usize> {
+        let mut db = self.db.write();
+        let db = db
+            .entry(S::category().to_string())
+            .or_insert_with(Category::new);
+
+        // The matching keys are contiguous in the sorted category
+        let keys = db
+            .range(prefix.clone()..)
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(&prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys.iter() {
+            db.remove(key);
+        }
+
+        Ok(keys.len())
+    }
+
     async fn contains<S: StorageSchema>(
         &self,
         key: <S as StorageSchema>::Key,
@@ -148,7 +370,7 @@ impl StorageAdapter for MemoryAdapter {
         let mut db = self.db.write();
         let db = db
             .entry(S::category().to_string())
-            .or_insert_with(HashMap::new);
+            .or_insert_with(Category::new);
 
         Ok(db.get(&key).is_some())
     }
@@ -178,7 +400,7 @@ impl StorageAdapter for MemoryAdapter {
         let mut db = self.db.write();
         let db = db
             .entry(S::category().to_string())
-            .or_insert_with(HashMap::new);
+            .or_insert_with(Category::new);
 
         for (key, value) in pairs.into_iter() {
             match value {
@@ -198,7 +420,7 @@ impl StorageAdapter for MemoryAdapter {
             self.db
                 .write()
                 .entry(S::category().to_string())
-                .or_insert_with(HashMap::new);
+                .or_insert_with(Category::new);
         }
 
         Ok(Box::new(MemoryIntoIterator {
@@ -159,6 +159,29 @@ impl StorageAdapter for RocksAdapter {
         Ok(())
     }
 
+    async fn remove_prefix<S: StorageSchema>(&self, prefix: Vec<u8>) -> ProtocolResult<usize> {
+        let column = get_column::<S>(&self.db)?;
+
+        // The prefix iterator only seeks to the prefix, so stop at the first
+        // key out of it.
+        let mut batch = WriteBatch::default();
+        let mut count = 0usize;
+        for (key, _) in self
+            .db
+            .prefix_iterator_cf(column, &prefix)
+            .map_err(RocksAdapterError::from)?
+            .take_while(|(key, _)| key.starts_with(&prefix))
+        {
+            batch
+                .delete_cf(column, key)
+                .map_err(RocksAdapterError::from)?;
+            count += 1;
+        }
+
+        self.db.write(&batch).map_err(RocksAdapterError::from)?;
+        Ok(count)
+    }
+
     async fn contains<S: StorageSchema>(
         &self,
         key: <S as StorageSchema>::Key,
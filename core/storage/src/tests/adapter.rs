@@ -1,9 +1,17 @@
-use protocol::traits::{StorageAdapter, StorageBatchModify};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
 
-use crate::adapter::memory::MemoryAdapter;
+use rand::random;
+
+use protocol::traits::{IntoIteratorByRef, StorageAdapter, StorageBatchModify, StorageSchema};
+use protocol::types::{Hash, Header};
+
+use crate::adapter::memory::{Category, CategoryOp, IterDirection, MemoryAdapter};
 use crate::adapter::rocks::RocksAdapter;
-use crate::tests::mock_signed_tx;
-use crate::{CommonHashKey, TransactionSchema};
+use crate::tests::{mock_receipt, mock_signed_tx};
+use crate::{BlockHeaderSchema, BlockKey, CommonHashKey, ReceiptSchema, TransactionSchema};
 
 #[test]
 fn test_adapter_insert() {
@@ -23,6 +31,195 @@ fn test_adapter_remove() {
     adapter_remove_test(RocksAdapter::new("rocksdb/test_adapter_remove", 64).unwrap())
 }
 
+#[test]
+fn test_adapter_remove_prefix() {
+    adapter_remove_prefix_test(MemoryAdapter::new());
+    adapter_remove_prefix_test(RocksAdapter::new("rocksdb/test_adapter_remove_prefix", 64).unwrap())
+}
+
+#[test]
+fn test_adapter_remove_prefix_bounds() {
+    adapter_remove_prefix_bounds_test(MemoryAdapter::new());
+    adapter_remove_prefix_bounds_test(
+        RocksAdapter::new("rocksdb/test_adapter_remove_prefix_bounds", 64).unwrap(),
+    )
+}
+
+#[test]
+fn test_memory_adapter_snapshot() {
+    let db = MemoryAdapter::new();
+    let mut tx_keys = Vec::new();
+    let mut receipt_keys = Vec::new();
+
+    for _ in 0..5 {
+        let stx = mock_signed_tx();
+        let key = CommonHashKey::new(1, stx.transaction.hash);
+        exec!(db.insert::<TransactionSchema>(key.clone(), stx.clone()));
+        tx_keys.push(key);
+
+        let receipt = mock_receipt(stx.transaction.hash);
+        let key = CommonHashKey::new(1, receipt.tx_hash);
+        exec!(db.insert::<ReceiptSchema>(key.clone(), receipt));
+        receipt_keys.push(key);
+    }
+
+    let restored = MemoryAdapter::from_snapshot(db.export());
+    // Writes after the export don't leak into the snapshot
+    exec!(db.remove::<TransactionSchema>(tx_keys[0].clone()));
+
+    for key in tx_keys.iter().skip(1) {
+        assert_eq!(
+            exec!(db.get::<TransactionSchema>(key.clone())),
+            exec!(restored.get::<TransactionSchema>(key.clone()))
+        );
+    }
+    assert!(exec!(
+        restored.contains::<TransactionSchema>(tx_keys[0].clone())
+    ));
+    for key in receipt_keys {
+        let receipt = exec!(restored.get::<ReceiptSchema>(key.clone()));
+        assert!(receipt.is_some());
+        assert_eq!(exec!(db.get::<ReceiptSchema>(key)), receipt);
+    }
+}
+
+#[test]
+fn test_memory_adapter_batch_modify_multi() {
+    let db = Arc::new(MemoryAdapter::new());
+    let done = Arc::new(AtomicBool::new(false));
+    let tx_category = TransactionSchema::category().to_string();
+    let receipt_category = ReceiptSchema::category().to_string();
+
+    let readers = (0..2)
+        .map(|_| {
+            let (db, done) = (Arc::clone(&db), Arc::clone(&done));
+            let (tx_category, receipt_category) = (tx_category.clone(), receipt_category.clone());
+            thread::spawn(move || {
+                let len = |snapshot: &HashMap<String, Category>, category: &String| {
+                    snapshot.get(category).map(|c| c.len()).unwrap_or_default()
+                };
+                while !done.load(Ordering::Acquire) {
+                    let snapshot = db.export();
+                    assert_eq!(
+                        len(&snapshot, &tx_category),
+                        len(&snapshot, &receipt_category)
+                    );
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut keys = Vec::new();
+    for _ in 0..100 {
+        let stx = mock_signed_tx();
+        let receipt = mock_receipt(stx.transaction.hash);
+        let key = CommonHashKey::new(1, stx.transaction.hash);
+        db.batch_modify_multi(vec![
+            CategoryOp::insert::<TransactionSchema>(key.clone(), stx).unwrap(),
+            CategoryOp::insert::<ReceiptSchema>(key.clone(), receipt).unwrap(),
+        ]);
+        keys.push(key);
+    }
+    for key in keys.iter().take(50) {
+        db.batch_modify_multi(vec![
+            CategoryOp::remove::<TransactionSchema>(key.clone()).unwrap(),
+            CategoryOp::remove::<ReceiptSchema>(key.clone()).unwrap(),
+        ]);
+    }
+
+    done.store(true, Ordering::Release);
+    for reader in readers.into_iter() {
+        reader.join().unwrap();
+    }
+
+    for (i, key) in keys.into_iter().enumerate() {
+        let kept = i >= 50;
+        assert_eq!(exec!(db.contains::<TransactionSchema>(key.clone())), kept);
+        assert_eq!(exec!(db.contains::<ReceiptSchema>(key)), kept);
+    }
+}
+
+fn range_numbers(
+    db: &MemoryAdapter,
+    from: Option<u64>,
+    to: Option<u64>,
+    direction: IterDirection,
+) -> Vec<u64> {
+    let iter = db
+        .prepare_range_iter::<BlockHeaderSchema>(
+            from.map(BlockKey::new),
+            to.map(BlockKey::new),
+            direction,
+        )
+        .unwrap();
+    let numbers = iter.ref_to_iter().map(|kv| kv.unwrap().1.number).collect();
+    numbers
+}
+
+#[test]
+fn test_memory_adapter_range_iter() {
+    let db = MemoryAdapter::new();
+    // Insert out of order, the iteration follows the key order anyway
+    for number in [7, 2, 9, 1, 5, 3, 10, 4, 8, 6] {
+        let header = Header {
+            number,
+            ..Default::default()
+        };
+        exec!(db.insert::<BlockHeaderSchema>(BlockKey::new(number), header));
+    }
+
+    assert_eq!(
+        range_numbers(&db, Some(7), None, IterDirection::Forward),
+        vec![7, 8, 9, 10]
+    );
+    assert_eq!(
+        range_numbers(&db, Some(4), None, IterDirection::Reverse),
+        vec![4, 3, 2, 1]
+    );
+    assert_eq!(
+        range_numbers(&db, Some(3), Some(6), IterDirection::Forward),
+        vec![3, 4, 5, 6]
+    );
+    assert_eq!(
+        range_numbers(&db, Some(6), Some(3), IterDirection::Reverse),
+        vec![6, 5, 4, 3]
+    );
+    assert!(range_numbers(&db, Some(6), Some(3), IterDirection::Forward).is_empty());
+    assert_eq!(
+        range_numbers(&db, None, None, IterDirection::Forward),
+        (1..=10).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_memory_adapter_snapshot_iter() {
+    let db = MemoryAdapter::new();
+    let insert = |number| {
+        let header = Header {
+            number,
+            ..Default::default()
+        };
+        exec!(db.insert::<BlockHeaderSchema>(BlockKey::new(number), header));
+    };
+    for number in 1..=5 {
+        insert(number);
+    }
+
+    let mut snapshot = db.snapshot_iter::<BlockHeaderSchema>();
+    assert_eq!(snapshot.next().unwrap().unwrap().1.number, 1);
+    // The snapshot holds no lock, the insert would deadlock otherwise
+    insert(6);
+
+    let rest = snapshot.map(|kv| kv.unwrap().1.number).collect::<Vec<_>>();
+    assert_eq!(rest, vec![2, 3, 4, 5]);
+    let fresh = db
+        .snapshot_iter::<BlockHeaderSchema>()
+        .map(|kv| kv.unwrap().1.number)
+        .collect::<Vec<_>>();
+    assert_eq!(fresh, (1..=6).collect::<Vec<_>>());
+    assert_eq!(db.snapshot_iter::<ReceiptSchema>().count(), 0);
+}
+
 fn adapter_insert_test(db: impl StorageAdapter) {
     let stx = mock_signed_tx();
     let tx_key = CommonHashKey::new(1, stx.transaction.hash);
@@ -66,3 +263,44 @@ fn adapter_remove_test(db: impl StorageAdapter) {
     let is_exist = exec!(db.contains::<TransactionSchema>(tx_key));
     assert!(!is_exist);
 }
+
+fn adapter_remove_prefix_test(db: impl StorageAdapter) {
+    // Random heights keep the entries left by former runs out of the prefix.
+    let height = random::<u64>() >> 1;
+    let mut keys = Vec::new();
+
+    for i in 0..5 {
+        let stx = mock_signed_tx();
+        let key = CommonHashKey::new(height + i % 2, stx.transaction.hash);
+        exec!(db.insert::<TransactionSchema>(key.clone(), stx));
+        keys.push(key);
+    }
+
+    let count = exec!(db.remove_prefix::<TransactionSchema>(height.to_be_bytes().to_vec()));
+    assert_eq!(count, 3);
+
+    for (i, key) in keys.into_iter().enumerate() {
+        let is_exist = exec!(db.contains::<TransactionSchema>(key));
+        assert_eq!(is_exist, i % 2 == 1);
+    }
+}
+
+fn adapter_remove_prefix_bounds_test(db: impl StorageAdapter) {
+    let height = (random::<u64>() >> 1) + 1;
+    // The keys right before and right after the prefix range in key order
+    let before = CommonHashKey::new(height - 1, Hash::repeat_byte(0xff));
+    let first = CommonHashKey::new(height, Hash::zero());
+    let last = CommonHashKey::new(height, Hash::repeat_byte(0xff));
+    let after = CommonHashKey::new(height + 1, Hash::zero());
+    for key in [&before, &first, &last, &after] {
+        exec!(db.insert::<TransactionSchema>(key.clone(), mock_signed_tx()));
+    }
+
+    let count = exec!(db.remove_prefix::<TransactionSchema>(height.to_be_bytes().to_vec()));
+    assert_eq!(count, 2);
+
+    assert!(exec!(db.contains::<TransactionSchema>(before)));
+    assert!(!exec!(db.contains::<TransactionSchema>(first)));
+    assert!(!exec!(db.contains::<TransactionSchema>(last)));
+    assert!(exec!(db.contains::<TransactionSchema>(after)));
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::types::{
     Address, Block, BlockNumber, Bytes, ExecResp, Hash, Header, Hex, Log, MerkleRoot, Metadata,
@@ -74,6 +75,16 @@ pub trait SynchronizationAdapter: CommonConsensusAdapter + Send + Sync {
         ctx: Context,
         number: BlockNumber,
     ) -> ProtocolResult<Proof>;
+
+    /// Tell the adapter whether the node is syncing or following the chain.
+    fn set_sync_mode(&self, syncing: bool);
+
+    /// Verify the proofs of a batch of blocks, stop at the first invalid one.
+    async fn verify_proofs(
+        self: &Arc<Self>,
+        ctx: Context,
+        proofs: Vec<(Block, Proof)>,
+    ) -> ProtocolResult<()>;
 }
 
 #[async_trait]
@@ -21,6 +21,16 @@ pub trait MemPool: Send + Sync {
         tx_hashes: &[Hash],
     ) -> ProtocolResult<Vec<SignedTransaction>>;
 
+    /// Resolve the txs like `get_full_txs` but return the unresolved hashes,
+    /// in the given order, along with the found txs rather than failing on
+    /// them.
+    async fn get_full_txs_partial(
+        &self,
+        ctx: Context,
+        height: Option<u64>,
+        tx_hashes: &[Hash],
+    ) -> ProtocolResult<(Vec<SignedTransaction>, Vec<Hash>)>;
+
     async fn ensure_order_txs(
         &self,
         ctx: Context,
@@ -53,6 +63,8 @@ pub trait MemPoolAdapter: Send + Sync {
 
     async fn get_latest_height(&self, ctx: Context) -> ProtocolResult<u64>;
 
+    async fn get_account_nonce(&self, ctx: Context, address: H160) -> ProtocolResult<U256>;
+
     async fn get_transactions_from_storage(
         &self,
         ctx: Context,
@@ -63,4 +75,7 @@ pub trait MemPoolAdapter: Send + Sync {
     fn set_args(&self, context: Context, state_root: MerkleRoot, gas_limit: u64, max_tx_size: u64);
 
     fn report_good(&self, ctx: Context);
+
+    /// Report the peer behind `ctx` for a bad response, nothing by default.
+    fn report_bad(&self, _ctx: Context) {}
 }
@@ -6,7 +6,8 @@ pub trait MetadataControl: Sync + Send {
 
     fn need_change_metadata(&self, block_number: u64) -> bool;
 
-    fn update_metadata(&self, ctx: Context, header: &Header) -> ProtocolResult<()>;
+    /// Update the metadata of the next epoch and return it.
+    fn update_metadata(&self, ctx: Context, header: &Header) -> ProtocolResult<Metadata>;
 
     fn get_metadata(&self, ctx: Context, header: &Header) -> ProtocolResult<Metadata>;
 
@@ -152,6 +152,10 @@ pub trait StorageAdapter: Send + Sync {
 
     async fn remove<S: StorageSchema>(&self, key: <S as StorageSchema>::Key) -> ProtocolResult<()>;
 
+    /// Remove all the entries whose encoded key starts with the prefix, return
+    /// the number of removed entries.
+    async fn remove_prefix<S: StorageSchema>(&self, prefix: Vec<u8>) -> ProtocolResult<usize>;
+
     async fn contains<S: StorageSchema>(
         &self,
         key: <S as StorageSchema>::Key,
@@ -5,6 +5,7 @@ use crate::types::{
     Bloom, BloomInput, Bytes, ExecResp, Hash, Hasher, MerkleRoot, SignedTransaction, H160, H64,
     U256,
 };
+use crate::ProtocolResult;
 
 pub type BlockNumber = u64;
 
@@ -114,6 +115,11 @@ impl Block {
     pub fn header_hash(&self) -> Hash {
         self.header.hash()
     }
+
+    /// The hash a proof commits to, the hash of the block as a proposal.
+    pub fn proposal_hash(&self) -> ProtocolResult<Hash> {
+        Ok(Hasher::digest(Proposal::from(self.clone()).encode()?))
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq)]
@@ -166,8 +172,10 @@ pub struct RichBlock {
 
 #[cfg(test)]
 mod tests {
+    use crate::codec::ProtocolCodec;
     use crate::types::{
-        Block, Header, Hex, Metadata, MetadataVersion, RichBlock, ValidatorExtend, H160,
+        Block, Bytes, Hasher, Header, Hex, Metadata, MetadataVersion, Proposal, RichBlock,
+        ValidatorExtend, H160,
     };
     use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -211,6 +219,20 @@ mod tests {
         println!("{}", serde_json::to_string(&genesis).unwrap());
     }
 
+    #[test]
+    fn test_proposal_hash() {
+        let mut block = Block::default();
+        block.header.number = 10;
+        block.header.chain_id = 5;
+        block.header.timestamp = time_now();
+        block.header.prev_hash = Hasher::digest(Bytes::from_static(b"prev"));
+        block.header.proof.number = 9;
+        block.tx_hashes = vec![Hasher::digest(Bytes::from_static(b"tx"))];
+
+        let expect = Hasher::digest(Proposal::from(block.clone()).encode().unwrap());
+        assert_eq!(block.proposal_hash().unwrap(), expect);
+    }
+
     #[test]
     fn print_metadata() {
         let metadata = Metadata {